@@ -0,0 +1,303 @@
+use {
+    anyhow::{Context, Result},
+    database::auction::AuctionId,
+    indexmap::IndexMap,
+    serde_json::Value as JsonValue,
+    std::{num::NonZeroUsize, time::Duration},
+    tokio::{
+        sync::{mpsc, oneshot},
+        task::JoinHandle,
+        time::{self, MissedTickBehavior},
+    },
+};
+
+/// Where a `CompetitionWriter` writes its batches to.
+#[async_trait::async_trait]
+pub trait CompetitionSink: Send + Sync + 'static {
+    async fn save_many(&self, entries: &[(AuctionId, JsonValue)]) -> Result<()>;
+}
+
+#[async_trait::async_trait]
+impl CompetitionSink for super::Postgres {
+    async fn save_many(&self, entries: &[(AuctionId, JsonValue)]) -> Result<()> {
+        let _timer = super::Metrics::get()
+            .database_queries
+            .with_label_values(&["save_many_competitions"])
+            .start_timer();
+
+        let mut ex = self.pool.acquire().await?;
+        database::solver_competition::save_many(&mut ex, entries)
+            .await
+            .context("solver_competition::save_many")
+    }
+}
+
+/// Coalesces competition saves into batches written with a single statement.
+/// A batch is written once `batch_size` competitions are buffered or
+/// `flush_interval` elapsed, whichever comes first.
+///
+/// Saving an auction that is still buffered replaces its pending competition.
+/// Batches that fail to be written stay buffered and are retried with the next
+/// one until `MAX_WRITE_ATTEMPTS` attempts failed, after which they are
+/// dropped. `shutdown` retries the final write the same way, so pending
+/// competitions are lost if the writer is dropped without calling it or if
+/// writing keeps failing.
+pub struct CompetitionWriter {
+    commands: mpsc::UnboundedSender<Command>,
+    task: JoinHandle<Result<()>>,
+}
+
+/// How often writing a batch is attempted before its competitions are dropped.
+const MAX_WRITE_ATTEMPTS: usize = 3;
+
+enum Command {
+    Save(AuctionId, JsonValue),
+    Flush(oneshot::Sender<Result<()>>),
+}
+
+impl CompetitionWriter {
+    pub fn new(
+        sink: impl CompetitionSink,
+        batch_size: NonZeroUsize,
+        flush_interval: Duration,
+    ) -> Self {
+        let (commands, receiver) = mpsc::unbounded_channel();
+        let task = tokio::spawn(run(sink, receiver, batch_size.get(), flush_interval));
+        Self { commands, task }
+    }
+
+    /// Buffers the competition of auction `id` to be written with the next
+    /// batch.
+    pub fn save(&self, id: AuctionId, competition: JsonValue) {
+        if self.commands.send(Command::Save(id, competition)).is_err() {
+            tracing::error!(id, "competition writer stopped; dropping competition");
+        }
+    }
+
+    /// Writes all buffered competitions right away.
+    pub async fn flush(&self) -> Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        self.commands
+            .send(Command::Flush(sender))
+            .ok()
+            .context("competition writer stopped")?;
+        receiver.await.context("competition writer stopped")?
+    }
+
+    /// Stops the writer after writing all buffered competitions.
+    pub async fn shutdown(self) -> Result<()> {
+        drop(self.commands);
+        self.task.await.context("competition writer panicked")?
+    }
+}
+
+async fn run(
+    sink: impl CompetitionSink,
+    mut commands: mpsc::UnboundedReceiver<Command>,
+    batch_size: usize,
+    flush_interval: Duration,
+) -> Result<()> {
+    let mut buffer = Buffer::default();
+    let mut interval = time::interval_at(time::Instant::now() + flush_interval, flush_interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            command = commands.recv() => match command {
+                Some(Command::Save(id, competition)) => {
+                    buffer.competitions.insert(id, competition);
+                    if buffer.competitions.len() >= batch_size {
+                        write(&sink, &mut buffer).await.ok();
+                        interval.reset();
+                    }
+                }
+                Some(Command::Flush(reply)) => {
+                    let _ = reply.send(write(&sink, &mut buffer).await);
+                }
+                None => loop {
+                    match write(&sink, &mut buffer).await {
+                        Err(_) if !buffer.competitions.is_empty() => continue,
+                        result => return result,
+                    }
+                },
+            },
+            _ = interval.tick() => {
+                write(&sink, &mut buffer).await.ok();
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Buffer {
+    /// Competitions waiting to be written, by auction id.
+    competitions: IndexMap<AuctionId, JsonValue>,
+    /// How often writing the buffered competitions failed in a row.
+    failed_attempts: usize,
+}
+
+/// Writes the buffered competitions, keeping them buffered if that fails
+/// unless it already failed `MAX_WRITE_ATTEMPTS` times.
+async fn write(sink: &impl CompetitionSink, buffer: &mut Buffer) -> Result<()> {
+    if buffer.competitions.is_empty() {
+        return Ok(());
+    }
+    let entries: Vec<_> = std::mem::take(&mut buffer.competitions)
+        .into_iter()
+        .collect();
+    match sink.save_many(&entries).await {
+        Ok(()) => {
+            tracing::debug!(count = entries.len(), "wrote competitions");
+            buffer.failed_attempts = 0;
+            Ok(())
+        }
+        Err(err) => {
+            buffer.failed_attempts += 1;
+            if buffer.failed_attempts < MAX_WRITE_ATTEMPTS {
+                tracing::warn!(?err, count = entries.len(), "failed to write competitions");
+                buffer.competitions = entries.into_iter().collect();
+            } else {
+                tracing::error!(
+                    ?err,
+                    count = entries.len(),
+                    "dropping competitions that repeatedly failed to be written"
+                );
+                buffer.failed_attempts = 0;
+            }
+            Err(err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        std::sync::{Arc, Mutex},
+    };
+
+    /// Records every written batch.
+    #[derive(Clone, Default)]
+    struct RecordingSink(Arc<Mutex<Vec<Vec<(AuctionId, JsonValue)>>>>);
+
+    #[async_trait::async_trait]
+    impl CompetitionSink for RecordingSink {
+        async fn save_many(&self, entries: &[(AuctionId, JsonValue)]) -> Result<()> {
+            self.0.lock().unwrap().push(entries.to_vec());
+            Ok(())
+        }
+    }
+
+    impl RecordingSink {
+        fn batches(&self) -> Vec<Vec<AuctionId>> {
+            self.0
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|batch| batch.iter().map(|(id, _)| *id).collect())
+                .collect()
+        }
+    }
+
+    /// Fails every write and counts the attempts.
+    #[derive(Clone, Default)]
+    struct FailingSink(Arc<Mutex<usize>>);
+
+    #[async_trait::async_trait]
+    impl CompetitionSink for FailingSink {
+        async fn save_many(&self, _: &[(AuctionId, JsonValue)]) -> Result<()> {
+            *self.0.lock().unwrap() += 1;
+            Err(anyhow::anyhow!("database unavailable"))
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn writes_full_batches_right_away() {
+        let sink = RecordingSink::default();
+        let writer = CompetitionWriter::new(
+            sink.clone(),
+            NonZeroUsize::new(2).unwrap(),
+            Duration::from_secs(60),
+        );
+
+        for id in 0..5 {
+            writer.save(id, JsonValue::from(id));
+        }
+        writer.flush().await.unwrap();
+        assert_eq!(sink.batches(), vec![vec![0, 1], vec![2, 3], vec![4]]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn writes_partial_batches_on_the_timer() {
+        let sink = RecordingSink::default();
+        let writer = CompetitionWriter::new(
+            sink.clone(),
+            NonZeroUsize::new(10).unwrap(),
+            Duration::from_secs(1),
+        );
+
+        writer.save(0, JsonValue::Null);
+        writer.save(1, JsonValue::Null);
+        time::sleep(Duration::from_millis(500)).await;
+        assert!(sink.batches().is_empty());
+
+        time::sleep(Duration::from_millis(600)).await;
+        assert_eq!(sink.batches(), vec![vec![0, 1]]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn saving_an_auction_again_replaces_its_competition() {
+        let sink = RecordingSink::default();
+        let writer = CompetitionWriter::new(
+            sink.clone(),
+            NonZeroUsize::new(10).unwrap(),
+            Duration::from_secs(3600),
+        );
+
+        writer.save(0, JsonValue::from("old"));
+        writer.save(1, JsonValue::Null);
+        writer.save(0, JsonValue::from("new"));
+        writer.flush().await.unwrap();
+        assert_eq!(
+            *sink.0.lock().unwrap(),
+            vec![vec![(0, JsonValue::from("new")), (1, JsonValue::Null)]]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn drops_batches_that_keep_failing() {
+        let sink = FailingSink::default();
+        let writer = CompetitionWriter::new(
+            sink.clone(),
+            NonZeroUsize::new(10).unwrap(),
+            Duration::from_secs(3600),
+        );
+
+        writer.save(0, JsonValue::Null);
+        for _ in 0..MAX_WRITE_ATTEMPTS {
+            assert!(writer.flush().await.is_err());
+        }
+        // The batch was dropped, so there is nothing left to write.
+        writer.flush().await.unwrap();
+        assert_eq!(*sink.0.lock().unwrap(), MAX_WRITE_ATTEMPTS);
+
+        // Shutdown retries until the batch is dropped as well.
+        writer.save(1, JsonValue::Null);
+        assert!(writer.shutdown().await.is_err());
+        assert_eq!(*sink.0.lock().unwrap(), 2 * MAX_WRITE_ATTEMPTS);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shutdown_drains_the_buffer() {
+        let sink = RecordingSink::default();
+        let writer = CompetitionWriter::new(
+            sink.clone(),
+            NonZeroUsize::new(10).unwrap(),
+            Duration::from_secs(3600),
+        );
+
+        writer.save(0, JsonValue::Null);
+        writer.save(1, JsonValue::Null);
+        writer.shutdown().await.unwrap();
+        assert_eq!(sink.batches(), vec![vec![0, 1]]);
+    }
+}
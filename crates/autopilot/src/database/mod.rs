@@ -7,6 +7,7 @@ use {
 mod auction;
 pub mod auction_prices;
 pub mod competition;
+pub mod competition_writer;
 pub mod ethflow_events;
 pub mod events;
 pub mod fee_policies;
@@ -7,9 +7,16 @@ use {
         PgTransaction,
         TransactionHash,
     },
-    bigdecimal::BigDecimal,
-    sqlx::{types::JsonValue, PgConnection, QueryBuilder},
-    std::ops::DerefMut,
+    bigdecimal::{num_bigint::BigInt, BigDecimal},
+    flate2::{read::GzDecoder, write::GzEncoder},
+    futures::{stream::BoxStream, StreamExt, TryStreamExt},
+    serde::{de, Deserialize, Deserializer},
+    sqlx::{types::JsonValue, PgConnection, PgPool, QueryBuilder},
+    std::{
+        io::{Read, Write},
+        ops::DerefMut,
+        str::FromStr,
+    },
 };
 
 pub async fn save_solver_competition(
@@ -25,7 +32,214 @@ VALUES ($1, $2)
     Ok(())
 }
 
-#[derive(Clone, Debug, sqlx::FromRow)]
+/// Whether `save_returning` created a new competition or replaced an existing
+/// one.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SaveOutcome {
+    Inserted,
+    Updated,
+}
+
+/// Saves a competition, replacing an existing one with the same id, and
+/// reports which of the two happened. This allows e.g. backfill jobs to detect
+/// that they overwrote existing data.
+pub async fn save_returning(
+    ex: &mut PgConnection,
+    id: AuctionId,
+    data: &JsonValue,
+) -> Result<SaveOutcome, sqlx::Error> {
+    // `xmax` is only set for rows written by the `DO UPDATE` path.
+    const QUERY: &str = r#"
+INSERT INTO solver_competitions (id, json)
+VALUES ($1, $2)
+ON CONFLICT (id) DO UPDATE SET json = EXCLUDED.json, compression = NULL, compressed_json = NULL
+RETURNING (xmax = 0)
+    ;"#;
+    let inserted: bool = sqlx::query_scalar(QUERY)
+        .bind(id)
+        .bind(data)
+        .fetch_one(ex)
+        .await?;
+    Ok(match inserted {
+        true => SaveOutcome::Inserted,
+        false => SaveOutcome::Updated,
+    })
+}
+
+/// Saves multiple competitions with a single statement. Competitions that
+/// already exist get their blob replaced.
+pub async fn save_many(
+    ex: &mut PgConnection,
+    entries: &[(AuctionId, JsonValue)],
+) -> Result<(), sqlx::Error> {
+    const QUERY: &str = r#"
+INSERT INTO solver_competitions (id, json)
+SELECT * FROM UNNEST($1::bigint[], $2::jsonb[])
+ON CONFLICT (id) DO UPDATE SET json = EXCLUDED.json, compression = NULL, compressed_json = NULL
+    ;"#;
+    let (ids, blobs): (Vec<_>, Vec<_>) = entries.iter().cloned().unzip();
+    sqlx::query(QUERY).bind(ids).bind(blobs).execute(ex).await?;
+    Ok(())
+}
+
+/// Saves a competition and marks its auction as settled by `tx_hash` in the
+/// `settled_auctions` table. Both writes happen in the caller's transaction so
+/// that they are either committed together or not at all. An existing
+/// competition or settlement of the auction is replaced.
+pub async fn save_and_settle(
+    ex: &mut PgTransaction<'_>,
+    id: AuctionId,
+    data: &JsonValue,
+    tx_hash: &TransactionHash,
+) -> Result<(), sqlx::Error> {
+    const SAVE_COMPETITION: &str = r#"
+INSERT INTO solver_competitions (id, json)
+VALUES ($1, $2)
+ON CONFLICT (id) DO UPDATE SET json = EXCLUDED.json, compression = NULL, compressed_json = NULL
+    ;"#;
+    sqlx::query(SAVE_COMPETITION)
+        .bind(id)
+        .bind(data)
+        .execute(ex.deref_mut())
+        .await?;
+
+    const SETTLE_AUCTION: &str = r#"
+INSERT INTO settled_auctions (auction_id, tx_hash)
+VALUES ($1, $2)
+ON CONFLICT (auction_id) DO UPDATE SET tx_hash = EXCLUDED.tx_hash
+    ;"#;
+    sqlx::query(SETTLE_AUCTION)
+        .bind(id)
+        .bind(tx_hash)
+        .execute(ex.deref_mut())
+        .await?;
+    Ok(())
+}
+
+/// Merges a partially recorded competition into the stored one, for auctions
+/// whose competition is recorded by several processes. Objects are merged key
+/// by key and arrays are concatenated, while any other value of `partial`
+/// replaces the stored one, so the last writer wins per leaf. Concurrent
+/// merges for the same auction are serialized by locking its row until the
+/// caller's transaction ends.
+pub async fn merge_save(
+    ex: &mut PgTransaction<'_>,
+    id: AuctionId,
+    partial: &JsonValue,
+) -> Result<(), sqlx::Error> {
+    // Inserting a placeholder first means there always is a row to lock, even
+    // if this is the first partial competition for the auction.
+    const INSERT_PLACEHOLDER: &str = r#"
+INSERT INTO solver_competitions (id, json)
+VALUES ($1, 'null')
+ON CONFLICT (id) DO NOTHING
+    ;"#;
+    sqlx::query(INSERT_PLACEHOLDER)
+        .bind(id)
+        .execute(ex.deref_mut())
+        .await?;
+
+    const LOCK: &str = r#"
+SELECT json, compression, compressed_json
+FROM solver_competitions
+WHERE id = $1
+FOR UPDATE
+    ;"#;
+    let (json, compression, compressed_json): (
+        Option<JsonValue>,
+        Option<Compression>,
+        Option<Vec<u8>>,
+    ) = sqlx::query_as(LOCK)
+        .bind(id)
+        .fetch_one(ex.deref_mut())
+        .await?;
+    let mut merged = match (compression, compressed_json) {
+        (Some(compression), Some(data)) => compression.decompress(&data)?,
+        _ => json.unwrap_or_default(),
+    };
+    merge_json(&mut merged, partial.clone());
+
+    const UPDATE: &str = r#"
+UPDATE solver_competitions
+SET json = $2, compression = NULL, compressed_json = NULL
+WHERE id = $1
+    ;"#;
+    sqlx::query(UPDATE)
+        .bind(id)
+        .bind(merged)
+        .execute(ex.deref_mut())
+        .await?;
+    Ok(())
+}
+
+/// Deep merges `partial` into `base`, see `merge_save`.
+fn merge_json(base: &mut JsonValue, partial: JsonValue) {
+    match (base, partial) {
+        (JsonValue::Object(base), JsonValue::Object(partial)) => {
+            for (key, value) in partial {
+                merge_json(base.entry(key).or_insert(JsonValue::Null), value);
+            }
+        }
+        (JsonValue::Array(base), JsonValue::Array(partial)) => base.extend(partial),
+        (base, partial) => *base = partial,
+    }
+}
+
+/// Algorithm a competition is compressed with.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, sqlx::Type)]
+#[sqlx(type_name = "CompressionKind")]
+#[sqlx(rename_all = "lowercase")]
+pub enum Compression {
+    Gzip,
+}
+
+impl Compression {
+    fn compress(self, data: &JsonValue) -> Result<Vec<u8>, sqlx::Error> {
+        let json = serde_json::to_vec(data).map_err(|err| sqlx::Error::Encode(err.into()))?;
+        match self {
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(&json)?;
+                Ok(encoder.finish()?)
+            }
+        }
+    }
+
+    fn decompress(self, data: &[u8]) -> Result<JsonValue, sqlx::Error> {
+        let json = match self {
+            Self::Gzip => {
+                let mut json = Vec::new();
+                GzDecoder::new(data).read_to_end(&mut json)?;
+                json
+            }
+        };
+        serde_json::from_slice(&json).map_err(|err| sqlx::Error::Decode(err.into()))
+    }
+}
+
+/// Saves the competition compressed. Loading functions decompress it
+/// transparently, but it can't be filtered by its content, like
+/// `load_by_solver` does.
+pub async fn save_compressed(
+    ex: &mut PgConnection,
+    id: AuctionId,
+    data: &JsonValue,
+    compression: Compression,
+) -> Result<(), sqlx::Error> {
+    const QUERY: &str = r#"
+INSERT INTO solver_competitions (id, compression, compressed_json)
+VALUES ($1, $2, $3)
+    ;"#;
+    sqlx::query(QUERY)
+        .bind(id)
+        .bind(compression)
+        .bind(compression.compress(data)?)
+        .execute(ex)
+        .await?;
+    Ok(())
+}
+
+#[derive(Clone, Debug)]
 pub struct LoadCompetition {
     pub json: JsonValue,
     pub id: AuctionId,
@@ -33,26 +247,138 @@ pub struct LoadCompetition {
     pub tx_hashes: Vec<TransactionHash>,
 }
 
+/// A competition as stored, possibly compressed.
+#[derive(sqlx::FromRow)]
+struct CompetitionRow {
+    json: Option<JsonValue>,
+    compression: Option<Compression>,
+    compressed_json: Option<Vec<u8>>,
+    id: AuctionId,
+    tx_hashes: Vec<TransactionHash>,
+}
+
+impl CompetitionRow {
+    fn into_competition(self) -> Result<LoadCompetition, sqlx::Error> {
+        let json = match (self.compression, self.compressed_json) {
+            (Some(compression), Some(data)) => compression.decompress(&data)?,
+            _ => self.json.unwrap_or_default(),
+        };
+        Ok(LoadCompetition {
+            json,
+            id: self.id,
+            tx_hashes: self.tx_hashes,
+        })
+    }
+}
+
 pub async fn load_by_id(
     ex: &mut PgConnection,
     id: AuctionId,
 ) -> Result<Option<LoadCompetition>, sqlx::Error> {
     const QUERY: &str = r#"
-SELECT sc.json, sc.id, COALESCE(ARRAY_AGG(s.tx_hash) FILTER (WHERE s.tx_hash IS NOT NULL), '{}') AS tx_hashes
+SELECT sc.json, sc.compression, sc.compressed_json, sc.id, COALESCE(ARRAY_AGG(s.tx_hash) FILTER (WHERE s.tx_hash IS NOT NULL), '{}') AS tx_hashes
 FROM solver_competitions sc
 -- outer joins because the data might not have been indexed yet
 LEFT OUTER JOIN settlements s ON sc.id = s.auction_id
 WHERE sc.id = $1
 GROUP BY sc.id
     ;"#;
-    sqlx::query_as(QUERY).bind(id).fetch_optional(ex).await
+    let row: Option<CompetitionRow> = sqlx::query_as(QUERY).bind(id).fetch_optional(ex).await?;
+    row.map(CompetitionRow::into_competition).transpose()
+}
+
+/// The commonly used parts of a stored competition. Fields that aren't
+/// modelled are kept in `extra`, so that competitions with a newer schema
+/// still deserialize.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SolverCompetition {
+    /// Not part of the JSON document, taken from the row.
+    #[serde(skip)]
+    pub auction_id: AuctionId,
+    #[serde(default)]
+    pub solutions: Vec<CompetitionSolution>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, JsonValue>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompetitionSolution {
+    pub solver: String,
+    /// The lowest ranking wins. Competitions stored before rankings were
+    /// introduced don't have it.
+    #[serde(default)]
+    pub ranking: Option<u64>,
+    /// The score of the solution, whichever way it was computed.
+    #[serde(
+        default,
+        alias = "scoreProtocol",
+        alias = "scoreProtocolWithSolverRisk",
+        alias = "scoreDiscounted",
+        deserialize_with = "deserialize_score"
+    )]
+    pub score: Option<BigDecimal>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, JsonValue>,
+}
+
+impl SolverCompetition {
+    /// Returns the winning solution, which is the one with the lowest ranking.
+    /// Competitions without rankings list the winner last.
+    pub fn winner(&self) -> Option<&CompetitionSolution> {
+        self.solutions
+            .iter()
+            .filter(|solution| solution.ranking.is_some())
+            .min_by_key(|solution| solution.ranking)
+            .or_else(|| self.solutions.last())
+    }
+
+    /// Returns the name of the winning solver.
+    pub fn winning_solver(&self) -> Option<&str> {
+        self.winner().map(|winner| winner.solver.as_str())
+    }
+}
+
+/// Scores are stored as decimal or `0x` prefixed hexadecimal strings.
+fn deserialize_score<'de, D>(deserializer: D) -> Result<Option<BigDecimal>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let Some(score) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    let value = match score.strip_prefix("0x") {
+        Some(hex) => BigInt::parse_bytes(hex.as_bytes(), 16).map(BigDecimal::from),
+        None => BigDecimal::from_str(&score).ok(),
+    };
+    value
+        .map(Some)
+        .ok_or_else(|| de::Error::custom(format!("invalid score {score:?}")))
+}
+
+/// Like `load_by_id` but deserializes the competition into a
+/// `SolverCompetition`.
+pub async fn load_by_id_typed(
+    ex: &mut PgConnection,
+    id: AuctionId,
+) -> Result<Option<SolverCompetition>, sqlx::Error> {
+    let Some(competition) = load_by_id(ex, id).await? else {
+        return Ok(None);
+    };
+    let typed: SolverCompetition =
+        serde_json::from_value(competition.json).map_err(|err| sqlx::Error::Decode(err.into()))?;
+    Ok(Some(SolverCompetition {
+        auction_id: competition.id,
+        ..typed
+    }))
 }
 
 pub async fn load_latest_competition(
     ex: &mut PgConnection,
 ) -> Result<Option<LoadCompetition>, sqlx::Error> {
     const QUERY: &str = r#"
-SELECT sc.json, sc.id, COALESCE(ARRAY_AGG(s.tx_hash) FILTER (WHERE s.tx_hash IS NOT NULL), '{}') AS tx_hashes
+SELECT sc.json, sc.compression, sc.compressed_json, sc.id, COALESCE(ARRAY_AGG(s.tx_hash) FILTER (WHERE s.tx_hash IS NOT NULL), '{}') AS tx_hashes
 FROM solver_competitions sc
 -- outer joins because the data might not have been indexed yet
 LEFT OUTER JOIN settlements s ON sc.id = s.auction_id
@@ -60,7 +386,137 @@ GROUP BY sc.id
 ORDER BY sc.id DESC
 LIMIT 1
     ;"#;
-    sqlx::query_as(QUERY).fetch_optional(ex).await
+    let row: Option<CompetitionRow> = sqlx::query_as(QUERY).fetch_optional(ex).await?;
+    row.map(CompetitionRow::into_competition).transpose()
+}
+
+/// Loads up to `limit` competitions ordered by descending id. Passing the
+/// smallest id of the previous page as `before` continues from there.
+pub async fn load_latest_competitions(
+    ex: &mut PgConnection,
+    limit: i64,
+    before: Option<AuctionId>,
+) -> Result<Vec<LoadCompetition>, sqlx::Error> {
+    const QUERY: &str = r#"
+SELECT sc.json, sc.compression, sc.compressed_json, sc.id, COALESCE(ARRAY_AGG(s.tx_hash) FILTER (WHERE s.tx_hash IS NOT NULL), '{}') AS tx_hashes
+FROM solver_competitions sc
+-- outer joins because the data might not have been indexed yet
+LEFT OUTER JOIN settlements s ON sc.id = s.auction_id
+WHERE $2::bigint IS NULL OR sc.id < $2
+GROUP BY sc.id
+ORDER BY sc.id DESC
+LIMIT $1
+    ;"#;
+    let rows: Vec<CompetitionRow> = sqlx::query_as(QUERY)
+        .bind(limit)
+        .bind(before)
+        .fetch_all(ex)
+        .await?;
+    rows.into_iter()
+        .map(CompetitionRow::into_competition)
+        .collect()
+}
+
+/// Loads up to `limit` of the latest competitions that were settled on chain,
+/// i.e. have an indexed settlement transaction, ordered by descending id.
+pub async fn load_settled(
+    ex: &mut PgConnection,
+    limit: i64,
+) -> Result<Vec<LoadCompetition>, sqlx::Error> {
+    load_by_settlement_status(ex, limit, true).await
+}
+
+/// Loads up to `limit` of the latest competitions without an indexed
+/// settlement transaction, ordered by descending id. These include recent
+/// competitions whose settlement wasn't indexed yet.
+pub async fn load_unsettled(
+    ex: &mut PgConnection,
+    limit: i64,
+) -> Result<Vec<LoadCompetition>, sqlx::Error> {
+    load_by_settlement_status(ex, limit, false).await
+}
+
+async fn load_by_settlement_status(
+    ex: &mut PgConnection,
+    limit: i64,
+    settled: bool,
+) -> Result<Vec<LoadCompetition>, sqlx::Error> {
+    const QUERY: &str = r#"
+SELECT sc.json, sc.compression, sc.compressed_json, sc.id, COALESCE(ARRAY_AGG(s.tx_hash) FILTER (WHERE s.tx_hash IS NOT NULL), '{}') AS tx_hashes
+FROM solver_competitions sc
+LEFT OUTER JOIN settlements s ON sc.id = s.auction_id
+WHERE sc.id IN (
+    -- Select the page first so that only its competitions get aggregated.
+    SELECT c.id
+    FROM solver_competitions c
+    WHERE EXISTS (
+        SELECT 1 FROM settlements st WHERE st.auction_id = c.id AND st.tx_hash IS NOT NULL
+    ) = $2
+    ORDER BY c.id DESC
+    LIMIT $1
+)
+GROUP BY sc.id
+ORDER BY sc.id DESC
+    ;"#;
+    let rows: Vec<CompetitionRow> = sqlx::query_as(QUERY)
+        .bind(limit)
+        .bind(settled)
+        .fetch_all(ex)
+        .await?;
+    rows.into_iter()
+        .map(CompetitionRow::into_competition)
+        .collect()
+}
+
+/// Loads all competitions with `from <= id < to` ordered by ascending id.
+pub async fn load_range(
+    ex: &mut PgConnection,
+    from: AuctionId,
+    to: AuctionId,
+) -> Result<Vec<LoadCompetition>, sqlx::Error> {
+    stream_range(ex, from, to).try_collect().await
+}
+
+/// Like `load_range` but streams the competitions instead of holding all of
+/// them in memory at once.
+pub fn stream_range(
+    ex: &mut PgConnection,
+    from: AuctionId,
+    to: AuctionId,
+) -> BoxStream<'_, Result<LoadCompetition, sqlx::Error>> {
+    const QUERY: &str = r#"
+SELECT sc.json, sc.compression, sc.compressed_json, sc.id, COALESCE(ARRAY_AGG(s.tx_hash) FILTER (WHERE s.tx_hash IS NOT NULL), '{}') AS tx_hashes
+FROM solver_competitions sc
+-- outer joins because the data might not have been indexed yet
+LEFT OUTER JOIN settlements s ON sc.id = s.auction_id
+WHERE sc.id >= $1 AND sc.id < $2
+GROUP BY sc.id
+ORDER BY sc.id ASC
+    ;"#;
+    sqlx::query_as::<_, CompetitionRow>(QUERY)
+        .bind(from)
+        .bind(to)
+        .fetch(ex)
+        .map(|row| row?.into_competition())
+        .boxed()
+}
+
+/// Streams all competitions ordered by ascending id. Rows are fetched from the
+/// database as the stream is polled, so memory use does not grow with the
+/// size of the table.
+pub fn stream_all(ex: &mut PgConnection) -> BoxStream<'_, Result<LoadCompetition, sqlx::Error>> {
+    const QUERY: &str = r#"
+SELECT sc.json, sc.compression, sc.compressed_json, sc.id, COALESCE(ARRAY_AGG(s.tx_hash) FILTER (WHERE s.tx_hash IS NOT NULL), '{}') AS tx_hashes
+FROM solver_competitions sc
+-- outer joins because the data might not have been indexed yet
+LEFT OUTER JOIN settlements s ON sc.id = s.auction_id
+GROUP BY sc.id
+ORDER BY sc.id ASC
+    ;"#;
+    sqlx::query_as::<_, CompetitionRow>(QUERY)
+        .fetch(ex)
+        .map(|row| row?.into_competition())
+        .boxed()
 }
 
 pub async fn load_by_tx_hash(
@@ -74,13 +530,359 @@ WITH competition AS (
     JOIN settlements s ON sc.id = s.auction_id
     WHERE s.tx_hash = $1
 )
-SELECT sc.json, sc.id, COALESCE(ARRAY_AGG(s.tx_hash) FILTER (WHERE s.tx_hash IS NOT NULL), '{}') AS tx_hashes
+SELECT sc.json, sc.compression, sc.compressed_json, sc.id, COALESCE(ARRAY_AGG(s.tx_hash) FILTER (WHERE s.tx_hash IS NOT NULL), '{}') AS tx_hashes
 FROM solver_competitions sc
 JOIN settlements s ON sc.id = s.auction_id
 WHERE sc.id = (SELECT id FROM competition)
 GROUP BY sc.id
     ;"#;
-    sqlx::query_as(QUERY).bind(tx_hash).fetch_optional(ex).await
+    let row: Option<CompetitionRow> = sqlx::query_as(QUERY)
+        .bind(tx_hash)
+        .fetch_optional(ex)
+        .await?;
+    row.map(CompetitionRow::into_competition).transpose()
+}
+
+/// Loads the competitions settled by any of the given transactions, ordered
+/// by ascending id. Hashes without a settled competition are omitted and a
+/// competition settled by several of the hashes is returned only once.
+pub async fn load_by_tx_hashes(
+    ex: &mut PgConnection,
+    tx_hashes: &[TransactionHash],
+) -> Result<Vec<LoadCompetition>, sqlx::Error> {
+    const QUERY: &str = r#"
+WITH competitions AS (
+    SELECT DISTINCT sc.id
+    FROM solver_competitions sc
+    JOIN settlements s ON sc.id = s.auction_id
+    WHERE s.tx_hash = ANY($1)
+)
+SELECT sc.json, sc.compression, sc.compressed_json, sc.id, COALESCE(ARRAY_AGG(s.tx_hash) FILTER (WHERE s.tx_hash IS NOT NULL), '{}') AS tx_hashes
+FROM solver_competitions sc
+JOIN settlements s ON sc.id = s.auction_id
+WHERE sc.id IN (SELECT id FROM competitions)
+GROUP BY sc.id
+ORDER BY sc.id ASC
+    ;"#;
+    let rows: Vec<CompetitionRow> = sqlx::query_as(QUERY).bind(tx_hashes).fetch_all(ex).await?;
+    rows.into_iter()
+        .map(CompetitionRow::into_competition)
+        .collect()
+}
+
+/// Loads the competitions settled by transactions whose hash starts with
+/// `prefix`, ordered by ascending id, e.g. to reconcile logs that only carry
+/// abbreviated hashes. An empty prefix matches nothing rather than every
+/// settled competition.
+pub async fn load_by_tx_hash_prefix(
+    ex: &mut PgConnection,
+    prefix: &[u8],
+) -> Result<Vec<LoadCompetition>, sqlx::Error> {
+    if prefix.is_empty() {
+        return Ok(Vec::new());
+    }
+    const QUERY: &str = r#"
+WITH competitions AS (
+    SELECT DISTINCT sc.id
+    FROM solver_competitions sc
+    JOIN settlements s ON sc.id = s.auction_id
+    WHERE substring(s.tx_hash FROM 1 FOR length($1)) = $1
+)
+SELECT sc.json, sc.compression, sc.compressed_json, sc.id, COALESCE(ARRAY_AGG(s.tx_hash) FILTER (WHERE s.tx_hash IS NOT NULL), '{}') AS tx_hashes
+FROM solver_competitions sc
+JOIN settlements s ON sc.id = s.auction_id
+WHERE sc.id IN (SELECT id FROM competitions)
+GROUP BY sc.id
+ORDER BY sc.id ASC
+    ;"#;
+    let rows: Vec<CompetitionRow> = sqlx::query_as(QUERY).bind(prefix).fetch_all(ex).await?;
+    rows.into_iter()
+        .map(CompetitionRow::into_competition)
+        .collect()
+}
+
+/// Loads up to `limit` competitions whose `solver` field matches the given
+/// address regardless of its casing, ordered by descending id. The filter is
+/// backed by the `solver_competitions_solver` expression index.
+pub async fn load_by_solver(
+    ex: &mut PgConnection,
+    solver: &Address,
+    limit: i64,
+) -> Result<Vec<LoadCompetition>, sqlx::Error> {
+    const QUERY: &str = r#"
+SELECT sc.json, sc.compression, sc.compressed_json, sc.id, COALESCE(ARRAY_AGG(s.tx_hash) FILTER (WHERE s.tx_hash IS NOT NULL), '{}') AS tx_hashes
+FROM solver_competitions sc
+-- outer joins because the data might not have been indexed yet
+LEFT OUTER JOIN settlements s ON sc.id = s.auction_id
+WHERE lower(sc.json ->> 'solver') = lower($1)
+GROUP BY sc.id
+ORDER BY sc.id DESC
+LIMIT $2
+    ;"#;
+    let rows: Vec<CompetitionRow> = sqlx::query_as(QUERY)
+        .bind(format!("0x{}", hex::encode(solver.0)))
+        .bind(limit)
+        .fetch_all(ex)
+        .await?;
+    rows.into_iter()
+        .map(CompetitionRow::into_competition)
+        .collect()
+}
+
+/// Returns the total number of stored competitions.
+pub async fn count(ex: &mut PgConnection) -> Result<i64, sqlx::Error> {
+    const QUERY: &str = r#"
+SELECT COUNT(*)
+FROM solver_competitions
+    ;"#;
+    sqlx::query_scalar(QUERY).fetch_one(ex).await
+}
+
+/// Returns whether a competition with the given id is stored, without loading
+/// it.
+pub async fn exists(ex: &mut PgConnection, id: AuctionId) -> Result<bool, sqlx::Error> {
+    const QUERY: &str = r#"
+SELECT EXISTS(SELECT 1 FROM solver_competitions WHERE id = $1)
+    ;"#;
+    sqlx::query_scalar(QUERY).bind(id).fetch_one(ex).await
+}
+
+/// Loads a single field of a competition without transferring the whole
+/// document. `path` is a dot separated list of object keys or array indices,
+/// for example `"prices.0"`. Returns `None` if the competition or the field
+/// doesn't exist. Compressed competitions can't be projected in the database
+/// and also return `None`.
+pub async fn load_json_field(
+    ex: &mut PgConnection,
+    id: AuctionId,
+    path: &str,
+) -> Result<Option<JsonValue>, sqlx::Error> {
+    let path = parse_json_path(path)?;
+    const QUERY: &str = r#"
+SELECT json #> $2
+FROM solver_competitions
+WHERE id = $1
+    ;"#;
+    let field: Option<Option<JsonValue>> = sqlx::query_scalar(QUERY)
+        .bind(id)
+        .bind(path)
+        .fetch_optional(ex)
+        .await?;
+    Ok(field.flatten())
+}
+
+fn parse_json_path(path: &str) -> Result<Vec<&str>, sqlx::Error> {
+    let segments: Vec<&str> = path.split('.').collect();
+    let is_valid = |segment: &&str| {
+        !segment.is_empty()
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    };
+    if !segments.iter().all(is_valid) {
+        return Err(sqlx::Error::Encode(
+            format!("invalid json path {path:?}").into(),
+        ));
+    }
+    Ok(segments)
+}
+
+/// Deletes all competitions with an id smaller than `cutoff` and returns the
+/// number of deleted rows.
+pub async fn delete_older_than(
+    ex: &mut PgConnection,
+    cutoff: AuctionId,
+) -> Result<u64, sqlx::Error> {
+    const QUERY: &str = r#"
+DELETE FROM solver_competitions
+WHERE id < $1
+    ;"#;
+    let result = sqlx::query(QUERY).bind(cutoff).execute(ex).await?;
+    Ok(result.rows_affected())
+}
+
+/// Deletes all competitions with an id smaller than `cutoff` like
+/// `delete_older_than` and refreshes the planner statistics of the table
+/// afterwards. Returns the number of deleted rows.
+///
+/// This doesn't return the space of the deleted rows to the operating system,
+/// which requires a `VACUUM`. Since that can't run inside of a transaction,
+/// use `prune_and_vacuum` on a connection outside of a transaction for it.
+pub async fn prune_and_analyze(
+    ex: &mut PgConnection,
+    cutoff: AuctionId,
+) -> Result<u64, sqlx::Error> {
+    const QUERY: &str = r#"
+ANALYZE solver_competitions
+    ;"#;
+    let deleted = delete_older_than(ex, cutoff).await?;
+    sqlx::query(QUERY).execute(ex).await?;
+    Ok(deleted)
+}
+
+/// Like `prune_and_analyze` but also reclaims the space of the deleted rows.
+/// Fails if `ex` is inside of a transaction, because `VACUUM` can't run in
+/// one. The deletion is committed even if the `VACUUM` fails.
+pub async fn prune_and_vacuum(
+    ex: &mut PgConnection,
+    cutoff: AuctionId,
+) -> Result<u64, sqlx::Error> {
+    const QUERY: &str = r#"
+VACUUM ANALYZE solver_competitions
+    ;"#;
+    let deleted = delete_older_than(ex, cutoff).await?;
+    sqlx::query(QUERY).execute(ex).await?;
+    Ok(deleted)
+}
+
+/// Records the block in which the competition was settled.
+pub async fn set_block_number(
+    ex: &mut PgConnection,
+    id: AuctionId,
+    block_number: u64,
+) -> Result<(), sqlx::Error> {
+    let block_number = i64::try_from(block_number).unwrap_or(i64::MAX);
+    const QUERY: &str = r#"
+UPDATE solver_competitions
+SET block_number = $2
+WHERE id = $1
+    ;"#;
+    sqlx::query(QUERY)
+        .bind(id)
+        .bind(block_number)
+        .execute(ex)
+        .await
+        .map(|_| ())
+}
+
+/// Loads all competitions settled in a block with `from <= block < to`
+/// ordered by ascending block and id.
+pub async fn load_by_block_range(
+    ex: &mut PgConnection,
+    from: u64,
+    to: u64,
+) -> Result<Vec<LoadCompetition>, sqlx::Error> {
+    let from = i64::try_from(from).unwrap_or(i64::MAX);
+    let to = i64::try_from(to).unwrap_or(i64::MAX);
+    const QUERY: &str = r#"
+SELECT sc.json, sc.compression, sc.compressed_json, sc.id, COALESCE(ARRAY_AGG(s.tx_hash) FILTER (WHERE s.tx_hash IS NOT NULL), '{}') AS tx_hashes
+FROM solver_competitions sc
+-- outer joins because the data might not have been indexed yet
+LEFT OUTER JOIN settlements s ON sc.id = s.auction_id
+WHERE sc.block_number >= $1 AND sc.block_number < $2
+GROUP BY sc.id
+ORDER BY sc.block_number ASC, sc.id ASC
+    ;"#;
+    let rows: Vec<CompetitionRow> = sqlx::query_as(QUERY)
+        .bind(from)
+        .bind(to)
+        .fetch_all(ex)
+        .await?;
+    rows.into_iter()
+        .map(CompetitionRow::into_competition)
+        .collect()
+}
+
+/// Resets the block number of all competitions settled after `block` so they
+/// get re-evaluated after a reorg. The competitions themselves are kept.
+/// Returns the number of invalidated competitions.
+pub async fn invalidate_above_block(ex: &mut PgConnection, block: u64) -> Result<u64, sqlx::Error> {
+    let block = i64::try_from(block).unwrap_or(i64::MAX);
+    const QUERY: &str = r#"
+UPDATE solver_competitions
+SET block_number = NULL
+WHERE block_number > $1
+    ;"#;
+    let result = sqlx::query(QUERY).bind(block).execute(ex).await?;
+    Ok(result.rows_affected())
+}
+
+/// Runs solver competition queries on connections acquired from a pool.
+/// Callers that need the queries to be part of a transaction should use the
+/// free functions of this module instead.
+#[derive(Clone, Debug)]
+pub struct SolverCompetitionStore {
+    pool: PgPool,
+}
+
+impl SolverCompetitionStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn save_solver_competition(
+        &self,
+        id: AuctionId,
+        data: &JsonValue,
+    ) -> Result<(), sqlx::Error> {
+        let mut ex = self.pool.acquire().await?;
+        save_solver_competition(&mut ex, id, data).await
+    }
+
+    pub async fn save_compressed(
+        &self,
+        id: AuctionId,
+        data: &JsonValue,
+        compression: Compression,
+    ) -> Result<(), sqlx::Error> {
+        let mut ex = self.pool.acquire().await?;
+        save_compressed(&mut ex, id, data, compression).await
+    }
+
+    /// Saves the proposed solutions of an auction in a single transaction.
+    pub async fn save(
+        &self,
+        auction_id: AuctionId,
+        solutions: &[Solution],
+    ) -> Result<(), sqlx::Error> {
+        let mut ex = self.pool.begin().await?;
+        save(&mut ex, auction_id, solutions).await?;
+        ex.commit().await
+    }
+
+    /// Merges a partial competition into the stored one in its own
+    /// transaction, see `merge_save`.
+    pub async fn merge_save(&self, id: AuctionId, partial: &JsonValue) -> Result<(), sqlx::Error> {
+        let mut ex = self.pool.begin().await?;
+        merge_save(&mut ex, id, partial).await?;
+        ex.commit().await
+    }
+
+    /// Deletes competitions older than `cutoff` and reclaims their space, see
+    /// `prune_and_vacuum`.
+    pub async fn prune(&self, cutoff: AuctionId) -> Result<u64, sqlx::Error> {
+        let mut ex = self.pool.acquire().await?;
+        prune_and_vacuum(&mut ex, cutoff).await
+    }
+
+    pub async fn load_by_id(&self, id: AuctionId) -> Result<Option<LoadCompetition>, sqlx::Error> {
+        let mut ex = self.pool.acquire().await?;
+        load_by_id(&mut ex, id).await
+    }
+
+    pub async fn load_by_tx_hash(
+        &self,
+        tx_hash: &TransactionHash,
+    ) -> Result<Option<LoadCompetition>, sqlx::Error> {
+        let mut ex = self.pool.acquire().await?;
+        load_by_tx_hash(&mut ex, tx_hash).await
+    }
+
+    pub async fn load_by_tx_hashes(
+        &self,
+        tx_hashes: &[TransactionHash],
+    ) -> Result<Vec<LoadCompetition>, sqlx::Error> {
+        let mut ex = self.pool.acquire().await?;
+        load_by_tx_hashes(&mut ex, tx_hashes).await
+    }
+
+    pub async fn load_by_tx_hash_prefix(
+        &self,
+        prefix: &[u8],
+    ) -> Result<Vec<LoadCompetition>, sqlx::Error> {
+        let mut ex = self.pool.acquire().await?;
+        load_by_tx_hash_prefix(&mut ex, prefix).await
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Default)]
@@ -309,12 +1111,90 @@ mod tests {
         sqlx::{Connection, Row},
     };
 
-    #[tokio::test]
-    #[ignore]
-    async fn postgres_roundtrip() {
-        let mut db = PgConnection::connect("postgresql://").await.unwrap();
-        let mut db = db.begin().await.unwrap();
-        crate::clear_DANGER_(&mut db).await.unwrap();
+    fn competition_blob() -> JsonValue {
+        serde_json::json!({
+            "auctionStartBlock": 13,
+            "auction": { "orders": [], "prices": {} },
+            "solutions": [
+                {
+                    "solver": "loser",
+                    "solverAddress": "0x0101010101010101010101010101010101010101",
+                    "scoreProtocol": "1000",
+                    "ranking": 2,
+                    "clearingPrices": {},
+                    "orders": [],
+                },
+                {
+                    "solver": "winner",
+                    "score": "0x07d0",
+                    "ranking": 1,
+                    "clearingPrices": {},
+                    "orders": [],
+                },
+            ],
+        })
+    }
+
+    #[test]
+    fn deserialize_typed_competition() {
+        let competition: SolverCompetition = serde_json::from_value(competition_blob()).unwrap();
+
+        assert_eq!(competition.solutions.len(), 2);
+        assert_eq!(competition.winning_solver(), Some("winner"));
+        assert_eq!(competition.solutions[0].score, Some(BigDecimal::from(1000)));
+        assert_eq!(competition.solutions[1].score, Some(BigDecimal::from(2000)));
+        assert_eq!(
+            competition.solutions[0].extra["solverAddress"],
+            "0x0101010101010101010101010101010101010101"
+        );
+        assert_eq!(competition.extra["auctionStartBlock"], 13);
+
+        // Without rankings the last solution won.
+        let legacy: SolverCompetition = serde_json::from_value(serde_json::json!({
+            "solutions": [{ "solver": "first" }, { "solver": "last" }],
+        }))
+        .unwrap();
+        assert_eq!(legacy.winning_solver(), Some("last"));
+        assert_eq!(legacy.solutions[0].score, None);
+
+        // Rankings don't have to start at 1.
+        let zero_based: SolverCompetition = serde_json::from_value(serde_json::json!({
+            "solutions": [
+                { "solver": "second", "ranking": 1 },
+                { "solver": "first", "ranking": 0 },
+                { "solver": "unranked" },
+            ],
+        }))
+        .unwrap();
+        assert_eq!(zero_based.winning_solver(), Some("first"));
+
+        let invalid = serde_json::json!({ "solutions": [{ "solver": "a", "score": "x" }] });
+        assert!(serde_json::from_value::<SolverCompetition>(invalid).is_err());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn postgres_load_by_id_typed() {
+        let mut db = PgConnection::connect("postgresql://").await.unwrap();
+        let mut db = db.begin().await.unwrap();
+        crate::clear_DANGER_(&mut db).await.unwrap();
+
+        save_solver_competition(&mut db, 7, &competition_blob())
+            .await
+            .unwrap();
+
+        let competition = load_by_id_typed(&mut db, 7).await.unwrap().unwrap();
+        assert_eq!(competition.auction_id, 7);
+        assert_eq!(competition.winning_solver(), Some("winner"));
+        assert!(load_by_id_typed(&mut db, 8).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn postgres_roundtrip() {
+        let mut db = PgConnection::connect("postgresql://").await.unwrap();
+        let mut db = db.begin().await.unwrap();
+        crate::clear_DANGER_(&mut db).await.unwrap();
 
         let value = JsonValue::Bool(true);
         save_solver_competition(&mut db, 0, &value).await.unwrap();
@@ -391,6 +1271,739 @@ mod tests {
         assert!(value_.tx_hashes.len() == 2);
     }
 
+    #[tokio::test]
+    #[ignore]
+    async fn postgres_load_by_tx_hashes() {
+        let mut db = PgConnection::connect("postgresql://").await.unwrap();
+        let mut db = db.begin().await.unwrap();
+        crate::clear_DANGER_(&mut db).await.unwrap();
+
+        for id in 0..3 {
+            save_solver_competition(&mut db, id, &JsonValue::from(id))
+                .await
+                .unwrap();
+            crate::events::insert_settlement(
+                &mut db,
+                &EventIndex {
+                    block_number: id,
+                    log_index: 0,
+                },
+                &Settlement {
+                    solver: Default::default(),
+                    transaction_hash: ByteArray([id as u8; 32]),
+                },
+            )
+            .await
+            .unwrap();
+            crate::settlements::update_settlement_auction(&mut db, id, 0, id)
+                .await
+                .unwrap();
+        }
+
+        let competitions = load_by_tx_hashes(
+            &mut db,
+            &[
+                ByteArray([2u8; 32]),
+                ByteArray([0u8; 32]),
+                ByteArray([9u8; 32]),
+            ],
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            competitions
+                .iter()
+                .map(|competition| competition.id)
+                .collect::<Vec<_>>(),
+            vec![0, 2]
+        );
+        assert_eq!(competitions[1].tx_hashes, vec![ByteArray([2u8; 32])]);
+
+        assert!(load_by_tx_hashes(&mut db, &[]).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn postgres_load_by_tx_hash_prefix() {
+        let mut db = PgConnection::connect("postgresql://").await.unwrap();
+        let mut db = db.begin().await.unwrap();
+        crate::clear_DANGER_(&mut db).await.unwrap();
+
+        let tx_hash = |prefix: [u8; 2], id: u8| {
+            let mut hash = [id; 32];
+            hash[..2].copy_from_slice(&prefix);
+            ByteArray(hash)
+        };
+        let hashes = [
+            tx_hash([0xab, 0xcd], 0),
+            tx_hash([0xab, 0xcd], 1),
+            tx_hash([0xab, 0xce], 2),
+        ];
+        for (id, hash) in (0_i64..).zip(&hashes) {
+            save_solver_competition(&mut db, id, &JsonValue::from(id))
+                .await
+                .unwrap();
+            crate::events::insert_settlement(
+                &mut db,
+                &EventIndex {
+                    block_number: id,
+                    log_index: 0,
+                },
+                &Settlement {
+                    solver: Default::default(),
+                    transaction_hash: *hash,
+                },
+            )
+            .await
+            .unwrap();
+            crate::settlements::update_settlement_auction(&mut db, id, 0, id)
+                .await
+                .unwrap();
+        }
+
+        let competitions = load_by_tx_hash_prefix(&mut db, &[0xab, 0xcd])
+            .await
+            .unwrap();
+        assert_eq!(
+            competitions
+                .iter()
+                .map(|competition| competition.id)
+                .collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+        assert_eq!(competitions[1].tx_hashes, vec![hashes[1]]);
+
+        let competitions = load_by_tx_hash_prefix(&mut db, &hashes[2].0).await.unwrap();
+        assert_eq!(competitions.len(), 1);
+        assert!(load_by_tx_hash_prefix(&mut db, &[])
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    async fn settled_tx_hash(db: &mut PgConnection, id: AuctionId) -> Option<TransactionHash> {
+        sqlx::query_scalar("SELECT tx_hash FROM settled_auctions WHERE auction_id = $1")
+            .bind(id)
+            .fetch_optional(db)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn postgres_load_settled_and_unsettled() {
+        let mut db = PgConnection::connect("postgresql://").await.unwrap();
+        let mut db = db.begin().await.unwrap();
+        crate::clear_DANGER_(&mut db).await.unwrap();
+
+        for id in 0..5 {
+            save_solver_competition(&mut db, id, &JsonValue::from(id))
+                .await
+                .unwrap();
+        }
+        for id in [1, 3] {
+            crate::events::insert_settlement(
+                &mut db,
+                &EventIndex {
+                    block_number: id,
+                    log_index: 0,
+                },
+                &Settlement {
+                    solver: Default::default(),
+                    transaction_hash: ByteArray([id as u8; 32]),
+                },
+            )
+            .await
+            .unwrap();
+            crate::settlements::update_settlement_auction(&mut db, id, 0, id)
+                .await
+                .unwrap();
+        }
+
+        let ids = |competitions: Vec<LoadCompetition>| {
+            competitions
+                .into_iter()
+                .map(|competition| competition.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(load_settled(&mut db, 10).await.unwrap()), vec![3, 1]);
+        assert_eq!(
+            ids(load_unsettled(&mut db, 10).await.unwrap()),
+            vec![4, 2, 0]
+        );
+        assert_eq!(ids(load_unsettled(&mut db, 2).await.unwrap()), vec![4, 2]);
+        assert_eq!(
+            load_settled(&mut db, 1).await.unwrap()[0].tx_hashes,
+            vec![ByteArray([3u8; 32])]
+        );
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn postgres_save_and_settle() {
+        let mut db = PgConnection::connect("postgresql://").await.unwrap();
+        let mut db = db.begin().await.unwrap();
+        crate::clear_DANGER_(&mut db).await.unwrap();
+
+        let mut tx = db.begin().await.unwrap();
+        save_and_settle(&mut tx, 0, &JsonValue::Bool(true), &ByteArray([1; 32]))
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+        assert_eq!(
+            load_by_id(&mut db, 0).await.unwrap().unwrap().json,
+            JsonValue::Bool(true)
+        );
+        assert_eq!(settled_tx_hash(&mut db, 0).await, Some(ByteArray([1; 32])));
+
+        let mut tx = db.begin().await.unwrap();
+        save_and_settle(&mut tx, 1, &JsonValue::Bool(true), &ByteArray([2; 32]))
+            .await
+            .unwrap();
+        tx.rollback().await.unwrap();
+        assert!(load_by_id(&mut db, 1).await.unwrap().is_none());
+        assert!(settled_tx_hash(&mut db, 1).await.is_none());
+    }
+
+    #[test]
+    fn merges_json_per_leaf() {
+        let mut base = serde_json::json!({
+            "auctionStartBlock": 13,
+            "prices": { "0x01": "1", "0x02": "2" },
+            "solutions": [{ "solver": "first" }],
+        });
+        merge_json(
+            &mut base,
+            serde_json::json!({
+                "auctionStartBlock": 14,
+                "prices": { "0x02": "3", "0x03": "4" },
+                "solutions": [{ "solver": "second" }],
+                "liquidityCollectedBlock": 12,
+            }),
+        );
+        assert_eq!(
+            base,
+            serde_json::json!({
+                "auctionStartBlock": 14,
+                "prices": { "0x01": "1", "0x02": "3", "0x03": "4" },
+                "solutions": [{ "solver": "first" }, { "solver": "second" }],
+                "liquidityCollectedBlock": 12,
+            })
+        );
+
+        // Values of different types are replaced as a whole.
+        merge_json(&mut base, serde_json::json!({ "prices": [] }));
+        assert_eq!(base["prices"], serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn postgres_merge_save() {
+        let mut db = PgConnection::connect("postgresql://").await.unwrap();
+        let mut db = db.begin().await.unwrap();
+        crate::clear_DANGER_(&mut db).await.unwrap();
+
+        let mut tx = db.begin().await.unwrap();
+        merge_save(
+            &mut tx,
+            0,
+            &serde_json::json!({
+                "auctionStartBlock": 13,
+                "solutions": [{ "solver": "first" }],
+            }),
+        )
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+
+        let mut tx = db.begin().await.unwrap();
+        merge_save(
+            &mut tx,
+            0,
+            &serde_json::json!({
+                "auctionStartBlock": 14,
+                "solutions": [{ "solver": "second" }],
+            }),
+        )
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+
+        assert_eq!(
+            load_by_id(&mut db, 0).await.unwrap().unwrap().json,
+            serde_json::json!({
+                "auctionStartBlock": 14,
+                "solutions": [{ "solver": "first" }, { "solver": "second" }],
+            })
+        );
+
+        // Compressed competitions are merged too.
+        save_compressed(
+            &mut db,
+            1,
+            &serde_json::json!({ "solutions": [{ "solver": "first" }] }),
+            Compression::Gzip,
+        )
+        .await
+        .unwrap();
+        let mut tx = db.begin().await.unwrap();
+        merge_save(
+            &mut tx,
+            1,
+            &serde_json::json!({ "solutions": [{ "solver": "second" }] }),
+        )
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+        assert_eq!(
+            load_by_id(&mut db, 1).await.unwrap().unwrap().json,
+            serde_json::json!({ "solutions": [{ "solver": "first" }, { "solver": "second" }] })
+        );
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn postgres_load_latest_competition_by_highest_id() {
+        let mut db = PgConnection::connect("postgresql://").await.unwrap();
+        let mut db = db.begin().await.unwrap();
+        crate::clear_DANGER_(&mut db).await.unwrap();
+
+        assert!(load_latest_competition(&mut db).await.unwrap().is_none());
+
+        for id in [0, 5, 3] {
+            save_solver_competition(&mut db, id, &JsonValue::from(id))
+                .await
+                .unwrap();
+        }
+        let competition = load_latest_competition(&mut db).await.unwrap().unwrap();
+        assert_eq!(competition.id, 5);
+        assert_eq!(competition.json, JsonValue::from(5));
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn postgres_compressed_roundtrip() {
+        let mut db = PgConnection::connect("postgresql://").await.unwrap();
+        let mut db = db.begin().await.unwrap();
+        crate::clear_DANGER_(&mut db).await.unwrap();
+
+        let large: JsonValue = (0..10_000)
+            .map(|i| serde_json::json!({ "index": i, "solver": "0x0102" }))
+            .collect();
+        save_compressed(&mut db, 0, &large, Compression::Gzip)
+            .await
+            .unwrap();
+        save_solver_competition(&mut db, 1, &JsonValue::from("uncompressed"))
+            .await
+            .unwrap();
+
+        let compressed = load_by_id(&mut db, 0).await.unwrap().unwrap();
+        assert_eq!(compressed.json, large);
+        let uncompressed = load_by_id(&mut db, 1).await.unwrap().unwrap();
+        assert_eq!(uncompressed.json, JsonValue::from("uncompressed"));
+
+        let all = load_range(&mut db, 0, 2).await.unwrap();
+        assert_eq!(all[0].json, large);
+        assert_eq!(all[1].json, uncompressed.json);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn postgres_count_and_exists() {
+        let mut db = PgConnection::connect("postgresql://").await.unwrap();
+        let mut db = db.begin().await.unwrap();
+        crate::clear_DANGER_(&mut db).await.unwrap();
+
+        assert_eq!(count(&mut db).await.unwrap(), 0);
+        assert!(!exists(&mut db, 1).await.unwrap());
+
+        for id in [1, 2] {
+            save_solver_competition(&mut db, id, &JsonValue::Null)
+                .await
+                .unwrap();
+            assert_eq!(count(&mut db).await.unwrap(), id);
+        }
+        assert!(exists(&mut db, 1).await.unwrap());
+        assert!(exists(&mut db, 2).await.unwrap());
+        assert!(!exists(&mut db, 3).await.unwrap());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn postgres_save_returning() {
+        let mut db = PgConnection::connect("postgresql://").await.unwrap();
+        let mut db = db.begin().await.unwrap();
+        crate::clear_DANGER_(&mut db).await.unwrap();
+
+        let outcome = save_returning(&mut db, 0, &JsonValue::from("a"))
+            .await
+            .unwrap();
+        assert_eq!(outcome, SaveOutcome::Inserted);
+        let outcome = save_returning(&mut db, 0, &JsonValue::from("b"))
+            .await
+            .unwrap();
+        assert_eq!(outcome, SaveOutcome::Updated);
+        let outcome = save_returning(&mut db, 1, &JsonValue::from("c"))
+            .await
+            .unwrap();
+        assert_eq!(outcome, SaveOutcome::Inserted);
+
+        let competition = load_by_id(&mut db, 0).await.unwrap().unwrap();
+        assert_eq!(competition.json, JsonValue::from("b"));
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn postgres_save_many() {
+        let mut db = PgConnection::connect("postgresql://").await.unwrap();
+        let mut db = db.begin().await.unwrap();
+        crate::clear_DANGER_(&mut db).await.unwrap();
+
+        save_many(
+            &mut db,
+            &[
+                (0, JsonValue::from("a")),
+                (1, JsonValue::from("b")),
+                (2, JsonValue::from("c")),
+            ],
+        )
+        .await
+        .unwrap();
+        save_many(
+            &mut db,
+            &[(1, JsonValue::from("B")), (2, JsonValue::from("C"))],
+        )
+        .await
+        .unwrap();
+
+        for (id, expected) in [(0, "a"), (1, "B"), (2, "C")] {
+            let competition = load_by_id(&mut db, id).await.unwrap().unwrap();
+            assert_eq!(competition.json, JsonValue::from(expected));
+        }
+        assert!(load_by_id(&mut db, 3).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn postgres_load_latest_competitions() {
+        let mut db = PgConnection::connect("postgresql://").await.unwrap();
+        let mut db = db.begin().await.unwrap();
+        crate::clear_DANGER_(&mut db).await.unwrap();
+
+        for id in 0..5 {
+            save_solver_competition(&mut db, id, &JsonValue::from(id))
+                .await
+                .unwrap();
+        }
+        let ids = |competitions: Vec<LoadCompetition>| {
+            competitions.into_iter().map(|c| c.id).collect::<Vec<_>>()
+        };
+
+        let page = load_latest_competitions(&mut db, 2, None).await.unwrap();
+        assert_eq!(ids(page), vec![4, 3]);
+        let page = load_latest_competitions(&mut db, 2, Some(3)).await.unwrap();
+        assert_eq!(ids(page), vec![2, 1]);
+        let page = load_latest_competitions(&mut db, 2, Some(1)).await.unwrap();
+        assert_eq!(ids(page), vec![0]);
+        let page = load_latest_competitions(&mut db, 2, Some(0)).await.unwrap();
+        assert!(page.is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn postgres_load_range() {
+        let mut db = PgConnection::connect("postgresql://").await.unwrap();
+        let mut db = db.begin().await.unwrap();
+        crate::clear_DANGER_(&mut db).await.unwrap();
+
+        for id in 0..5 {
+            save_solver_competition(&mut db, id, &JsonValue::from(id))
+                .await
+                .unwrap();
+        }
+
+        let competitions = load_range(&mut db, 1, 4).await.unwrap();
+        let ids: Vec<_> = competitions.into_iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+
+        let ids: Vec<_> = stream_range(&mut db, 1, 4)
+            .map_ok(|c| c.id)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(ids, vec![1, 2, 3]);
+
+        assert!(load_range(&mut db, 4, 4).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn postgres_stream_all() {
+        let mut db = PgConnection::connect("postgresql://").await.unwrap();
+        let mut db = db.begin().await.unwrap();
+        crate::clear_DANGER_(&mut db).await.unwrap();
+
+        assert!(stream_all(&mut db).next().await.is_none());
+
+        // Together the competitions are much larger than the socket buffers, so
+        // the database can't send all of them before they are consumed.
+        const COUNT: i64 = 1000;
+        let padding = "x".repeat(32 * 1024);
+        let entries: Vec<_> = (0..COUNT)
+            .rev()
+            .map(|id| (id, serde_json::json!({ "id": id, "padding": padding })))
+            .collect();
+        save_many(&mut db, &entries).await.unwrap();
+        let pid: i32 = sqlx::query_scalar("SELECT pg_backend_pid()")
+            .fetch_one(&mut *db)
+            .await
+            .unwrap();
+        let mut observer = PgConnection::connect("postgresql://").await.unwrap();
+
+        // Consume the competitions one at a time without collecting them.
+        let mut stream = stream_all(&mut db);
+        let first = stream.next().await.unwrap().unwrap();
+        assert_eq!(first.id, 0);
+        // The query is still running after the first competition arrived, so
+        // the remaining ones aren't buffered in memory.
+        let state: Option<String> =
+            sqlx::query_scalar("SELECT state FROM pg_stat_activity WHERE pid = $1")
+                .bind(pid)
+                .fetch_one(&mut observer)
+                .await
+                .unwrap();
+        assert_eq!(state.as_deref(), Some("active"));
+
+        let mut expected = 1;
+        while let Some(competition) = stream.next().await {
+            let competition = competition.unwrap();
+            assert_eq!(competition.id, expected);
+            assert_eq!(competition.json["id"], expected);
+            expected += 1;
+        }
+        assert_eq!(expected, COUNT);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn postgres_load_by_solver() {
+        let mut db = PgConnection::connect("postgresql://").await.unwrap();
+        let mut db = db.begin().await.unwrap();
+        crate::clear_DANGER_(&mut db).await.unwrap();
+
+        let solver_a = ByteArray([1u8; 20]);
+        // Stored uppercased, so the filter has to ignore the casing.
+        let solver_b = ByteArray([0xab; 20]);
+        let blob = |solver: &Address| {
+            JsonValue::from_iter([("solver", format!("0x{}", hex::encode_upper(solver.0)))])
+        };
+        for (id, solver) in [(0, &solver_a), (1, &solver_b), (2, &solver_a)] {
+            save_solver_competition(&mut db, id, &blob(solver))
+                .await
+                .unwrap();
+        }
+        let ids = |competitions: Vec<LoadCompetition>| {
+            competitions.into_iter().map(|c| c.id).collect::<Vec<_>>()
+        };
+
+        let competitions = load_by_solver(&mut db, &solver_a, 10).await.unwrap();
+        assert_eq!(ids(competitions), vec![2, 0]);
+        let competitions = load_by_solver(&mut db, &solver_a, 1).await.unwrap();
+        assert_eq!(ids(competitions), vec![2]);
+        let competitions = load_by_solver(&mut db, &solver_b, 10).await.unwrap();
+        assert_eq!(ids(competitions), vec![1]);
+        let competitions = load_by_solver(&mut db, &ByteArray([3u8; 20]), 10)
+            .await
+            .unwrap();
+        assert!(competitions.is_empty());
+    }
+
+    #[test]
+    fn json_path_validation() {
+        assert_eq!(parse_json_path("a").unwrap(), ["a"]);
+        assert_eq!(parse_json_path("a.0.b_c").unwrap(), ["a", "0", "b_c"]);
+        assert!(parse_json_path("").is_err());
+        assert!(parse_json_path("a..b").is_err());
+        assert!(parse_json_path("a.b.").is_err());
+        assert!(parse_json_path("a.{b}").is_err());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn postgres_load_json_field() {
+        let mut db = PgConnection::connect("postgresql://").await.unwrap();
+        let mut db = db.begin().await.unwrap();
+        crate::clear_DANGER_(&mut db).await.unwrap();
+
+        let value = serde_json::json!({
+            "solutions": [{ "objective": { "gasUsed": 1337 } }],
+        });
+        save_solver_competition(&mut db, 0, &value).await.unwrap();
+
+        let field = load_json_field(&mut db, 0, "solutions.0.objective.gasUsed")
+            .await
+            .unwrap();
+        assert_eq!(field, Some(JsonValue::from(1337)));
+        let field = load_json_field(&mut db, 0, "solutions.0.objective")
+            .await
+            .unwrap();
+        assert_eq!(field, Some(serde_json::json!({ "gasUsed": 1337 })));
+
+        // missing field
+        assert!(load_json_field(&mut db, 0, "solutions.1")
+            .await
+            .unwrap()
+            .is_none());
+        // missing competition
+        assert!(load_json_field(&mut db, 1, "solutions")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn postgres_delete_older_than() {
+        let mut db = PgConnection::connect("postgresql://").await.unwrap();
+        let mut db = db.begin().await.unwrap();
+        crate::clear_DANGER_(&mut db).await.unwrap();
+
+        for id in 0..3 {
+            save_solver_competition(&mut db, id, &JsonValue::from(id))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(delete_older_than(&mut db, 2).await.unwrap(), 2);
+        assert!(load_by_id(&mut db, 0).await.unwrap().is_none());
+        assert!(load_by_id(&mut db, 1).await.unwrap().is_none());
+        assert!(load_by_id(&mut db, 2).await.unwrap().is_some());
+
+        // nothing left to prune
+        assert_eq!(delete_older_than(&mut db, 2).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn postgres_prune_and_analyze() {
+        let mut db = PgConnection::connect("postgresql://").await.unwrap();
+        let mut db = db.begin().await.unwrap();
+        crate::clear_DANGER_(&mut db).await.unwrap();
+
+        for id in 0..5 {
+            save_solver_competition(&mut db, id, &JsonValue::from(id))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(prune_and_analyze(&mut db, 3).await.unwrap(), 3);
+        assert_eq!(count(&mut db).await.unwrap(), 2);
+        assert!(load_by_id(&mut db, 3).await.unwrap().is_some());
+
+        // `VACUUM` is rejected inside of the transaction.
+        assert!(prune_and_vacuum(&mut db, 4).await.is_err());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn postgres_load_by_block_range() {
+        let mut db = PgConnection::connect("postgresql://").await.unwrap();
+        let mut db = db.begin().await.unwrap();
+        crate::clear_DANGER_(&mut db).await.unwrap();
+
+        for id in 0..5 {
+            save_solver_competition(&mut db, id, &JsonValue::from(id))
+                .await
+                .unwrap();
+        }
+        // competition 4 has not been settled yet
+        for (id, block) in [(0, 10), (1, 12), (2, 11), (3, 13)] {
+            set_block_number(&mut db, id, block).await.unwrap();
+        }
+        let ids = |competitions: Vec<LoadCompetition>| {
+            competitions.into_iter().map(|c| c.id).collect::<Vec<_>>()
+        };
+
+        let competitions = load_by_block_range(&mut db, 11, 13).await.unwrap();
+        assert_eq!(ids(competitions), vec![2, 1]);
+        let competitions = load_by_block_range(&mut db, 0, u64::MAX).await.unwrap();
+        assert_eq!(ids(competitions), vec![0, 2, 1, 3]);
+        assert!(load_by_block_range(&mut db, 14, 20)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn postgres_invalidate_above_block() {
+        let mut db = PgConnection::connect("postgresql://").await.unwrap();
+        let mut db = db.begin().await.unwrap();
+        crate::clear_DANGER_(&mut db).await.unwrap();
+
+        for (id, block) in [(0, 10), (1, 11), (2, 12)] {
+            save_solver_competition(&mut db, id, &JsonValue::from(id))
+                .await
+                .unwrap();
+            set_block_number(&mut db, id, block).await.unwrap();
+        }
+
+        assert_eq!(invalidate_above_block(&mut db, 10).await.unwrap(), 2);
+        let competitions = load_by_block_range(&mut db, 0, u64::MAX).await.unwrap();
+        assert_eq!(competitions.len(), 1);
+        assert_eq!(competitions[0].id, 0);
+
+        // invalidated competitions are not deleted
+        for id in 0..3 {
+            let competition = load_by_id(&mut db, id).await.unwrap().unwrap();
+            assert_eq!(competition.json, JsonValue::from(id));
+        }
+
+        // nothing left to invalidate
+        assert_eq!(invalidate_above_block(&mut db, 10).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn postgres_store_roundtrip() {
+        let pool = PgPool::connect("postgresql://").await.unwrap();
+        crate::clear_DANGER(&pool).await.unwrap();
+        let store = SolverCompetitionStore::new(pool);
+
+        store
+            .save_solver_competition(0, &JsonValue::from(0))
+            .await
+            .unwrap();
+        let competition = store.load_by_id(0).await.unwrap().unwrap();
+        assert_eq!(competition.json, JsonValue::from(0));
+        assert!(store.load_by_id(1).await.unwrap().is_none());
+        assert!(store
+            .load_by_tx_hash(&ByteArray([0u8; 32]))
+            .await
+            .unwrap()
+            .is_none());
+
+        // concurrent queries run on separate connections
+        let (saved, loaded) = futures::join!(
+            store.save_solver_competition(1, &JsonValue::from(1)),
+            store.load_by_id(0)
+        );
+        saved.unwrap();
+        assert_eq!(loaded.unwrap().unwrap().json, JsonValue::from(0));
+        let competition = store.load_by_id(1).await.unwrap().unwrap();
+        assert_eq!(competition.json, JsonValue::from(1));
+
+        let solutions = vec![Solution {
+            uid: 0,
+            solver: ByteArray([1u8; 20]),
+            orders: vec![Default::default()],
+            ..Default::default()
+        }];
+        store.save(1, &solutions).await.unwrap();
+        let mut ex = store.pool.acquire().await.unwrap();
+        assert_eq!(fetch(&mut ex, 1).await.unwrap(), solutions);
+    }
+
     #[tokio::test]
     #[ignore]
     async fn postgres_solutions_roundtrip() {
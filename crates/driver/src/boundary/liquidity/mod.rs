@@ -42,6 +42,8 @@ fn cache_config() -> CacheConfig {
         maximum_recent_block_age: 4,
         max_retries: 5,
         delay_between_retries: Duration::from_secs(1),
+        max_age: None,
+        max_staleness_blocks: None,
     }
 }
 
@@ -12,13 +12,36 @@ use {
     lazy_static::lazy_static,
     model::order::BUY_ETH_ADDRESS,
     num::{BigInt, BigRational, One as _, ToPrimitive as _},
-    std::collections::{BTreeMap, HashMap},
+    std::{
+        collections::{BTreeMap, HashMap},
+        time::{Duration, Instant},
+    },
 };
 
+/// A source of token prices in the same denomination as the `/auction`
+/// endpoint prices, i.e. the amount of native token needed to buy 1e18 of the
+/// token.
+pub trait PriceSource {
+    fn price(&self, token: &H160) -> Option<U256>;
+}
+
+impl PriceSource for HashMap<H160, U256> {
+    fn price(&self, token: &H160) -> Option<U256> {
+        self.get(token).copied()
+    }
+}
+
 /// A collection of external prices used for converting token amounts to native
 /// assets.
 #[derive(Clone, Debug)]
-pub struct ExternalPrices(HashMap<H160, BigRational>);
+pub struct ExternalPrices {
+    xrates: HashMap<H160, BigRational>,
+    /// When the prices of tokens were last updated. Prices without a timestamp
+    /// never become stale.
+    updated_at: HashMap<H160, Instant>,
+    /// How old a price may get before it is no longer used.
+    max_age: Option<Duration>,
+}
 
 impl ExternalPrices {
     /// Creates a new set of external prices for the specified exchange rates.
@@ -40,7 +63,25 @@ impl ExternalPrices {
             }
         }
 
-        Ok(Self(xrates))
+        Ok(Self {
+            xrates,
+            updated_at: Default::default(),
+            max_age: None,
+        })
+    }
+
+    /// Records when the prices of `tokens` were last updated, so that they can
+    /// be checked against a maximum age.
+    pub fn with_updated_at(mut self, tokens: impl IntoIterator<Item = (H160, Instant)>) -> Self {
+        self.updated_at.extend(tokens);
+        self
+    }
+
+    /// Treats prices updated longer than `max_age` ago as missing, so that
+    /// outdated prices aren't used for absolute slippage limits.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
     }
 
     /// Returns a set of external prices for the specified auction model prices.
@@ -57,12 +98,57 @@ impl ExternalPrices {
         )
     }
 
+    /// Like `try_from_auction_prices` but consults `fallback` for the auction
+    /// `tokens` that don't have a price. Tokens without a price in either
+    /// source remain unpriced.
+    pub fn try_from_auction_prices_with_fallback(
+        native_token: H160,
+        mut prices: BTreeMap<H160, U256>,
+        tokens: impl IntoIterator<Item = H160>,
+        fallback: &dyn PriceSource,
+    ) -> Result<Self> {
+        for token in tokens {
+            if prices.contains_key(&token) {
+                continue;
+            }
+            if let Some(price) = fallback.price(&token) {
+                prices.insert(token, price);
+            }
+        }
+        Self::try_from_auction_prices(native_token, prices)
+    }
+
     /// Returns the price of a token relative to the native token.
     /// I.e., the price of the native token is 1 and
     /// the price of a token T is represented as how much native token
     // is needed in order to buy 1 atom of the token T
+    ///
+    /// Returns `None` for prices older than the maximum age, if one is set.
     pub fn price(&self, token: &H160) -> Option<&BigRational> {
-        self.0.get(token)
+        let price = self.xrates.get(token)?;
+        if let (Some(max_age), Some(updated_at)) = (self.max_age, self.updated_at.get(token)) {
+            if updated_at.elapsed() > max_age {
+                tracing::debug!(?token, age = ?updated_at.elapsed(), "ignoring stale price");
+                return None;
+            }
+        }
+        Some(price)
+    }
+
+    /// Adds the prices from `other`, for example from an external oracle, to
+    /// this set. Prices for tokens that already have one are only replaced if
+    /// `overwrite` is set. Both sets have to use the same native token.
+    pub fn merge(&mut self, mut other: ExternalPrices, overwrite: bool) {
+        for (token, price) in other.xrates {
+            if !overwrite && self.xrates.contains_key(&token) {
+                continue;
+            }
+            self.xrates.insert(token, price);
+            match other.updated_at.remove(&token) {
+                Some(updated_at) => self.updated_at.insert(token, updated_at),
+                None => self.updated_at.remove(&token),
+            };
+        }
     }
 }
 
@@ -130,7 +216,7 @@ mod tests {
                 },
             )
             .unwrap()
-            .0,
+            .xrates,
             hashmap! {
                 H160([1; 20]) => BigRational::new(1.into(), 10.into()),
                 native_token => BigRational::one(),
@@ -139,6 +225,125 @@ mod tests {
         );
     }
 
+    #[test]
+    fn falls_back_for_tokens_without_auction_price() {
+        let native_token = H160([42; 20]);
+        let (priced, unpriced, unknown) = (H160([1; 20]), H160([2; 20]), H160([3; 20]));
+        let fallback = hashmap! {
+            priced => U256::from(1_000_000_000_000_000_000_u128),
+            unpriced => U256::from(200_000_000_000_000_000_u128),
+        };
+
+        let prices = ExternalPrices::try_from_auction_prices_with_fallback(
+            native_token,
+            btreemap! {
+                priced => U256::from(100_000_000_000_000_000_u128),
+            },
+            [priced, unpriced, unknown],
+            &fallback,
+        )
+        .unwrap();
+        assert_eq!(
+            prices.xrates,
+            hashmap! {
+                // The auction price takes precedence.
+                priced => BigRational::new(1.into(), 10.into()),
+                unpriced => BigRational::new(1.into(), 5.into()),
+                native_token => BigRational::one(),
+                BUY_ETH_ADDRESS => BigRational::one(),
+            },
+        );
+    }
+
+    #[test]
+    fn price_lookup() {
+        let token = H160([1; 20]);
+        let prices = ExternalPrices::new(
+            H160([42; 20]),
+            hashmap! { token => BigRational::new(1.into(), 10.into()) },
+        )
+        .unwrap();
+
+        assert_eq!(
+            prices.price(&token),
+            Some(&BigRational::new(1.into(), 10.into()))
+        );
+        assert_eq!(prices.price(&H160([2; 20])), None);
+    }
+
+    #[test]
+    fn ignores_stale_prices() {
+        let (fresh, stale, untracked) = (H160([1; 20]), H160([2; 20]), H160([3; 20]));
+        let now = Instant::now();
+        let prices = ExternalPrices::new(
+            H160([42; 20]),
+            hashmap! {
+                fresh => BigRational::one(),
+                stale => BigRational::one(),
+                untracked => BigRational::one(),
+            },
+        )
+        .unwrap()
+        .with_updated_at([
+            (fresh, now),
+            (
+                stale,
+                now.checked_sub(Duration::from_secs(120))
+                    .expect("monotonic clock too early to backdate a price by 2 minutes"),
+            ),
+        ]);
+
+        // Without a maximum age, prices never become stale.
+        assert!(prices.price(&stale).is_some());
+
+        let prices = prices.with_max_age(Duration::from_secs(60));
+        assert!(prices.price(&fresh).is_some());
+        assert_eq!(prices.price(&stale), None);
+        assert!(prices.price(&untracked).is_some());
+    }
+
+    #[test]
+    fn merges_prices() {
+        let native_token = H160([42; 20]);
+        let (shared, missing) = (H160([1; 20]), H160([2; 20]));
+        let auction = ExternalPrices::new(
+            native_token,
+            hashmap! { shared => BigRational::new(1.into(), 10.into()) },
+        )
+        .unwrap();
+        let oracle = ExternalPrices::new(
+            native_token,
+            hashmap! {
+                shared => BigRational::new(1.into(), 5.into()),
+                missing => BigRational::new(2.into(), 1.into()),
+            },
+        )
+        .unwrap();
+
+        let mut prices = auction.clone();
+        prices.merge(oracle.clone(), false);
+        assert_eq!(
+            prices.price(&shared),
+            Some(&BigRational::new(1.into(), 10.into()))
+        );
+        assert_eq!(
+            prices.price(&missing),
+            Some(&BigRational::from_integer(2.into()))
+        );
+
+        let mut prices = auction;
+        prices.merge(oracle, true);
+        assert_eq!(
+            prices.price(&shared),
+            Some(&BigRational::new(1.into(), 5.into()))
+        );
+        assert_eq!(
+            prices.price(&missing),
+            Some(&BigRational::from_integer(2.into()))
+        );
+        assert_eq!(prices.price(&native_token), Some(&BigRational::one()));
+    }
+
     #[test]
     fn from_auction_price_errors_on_invalid_native_prices() {
         let native_token = H160([42; 20]);
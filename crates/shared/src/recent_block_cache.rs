@@ -41,6 +41,7 @@ use {
         sync::{Arc, Mutex},
         time::Duration,
     },
+    tokio::time::Instant,
     tracing::Instrument,
 };
 
@@ -108,6 +109,8 @@ where
     metrics: &'static Metrics,
     metrics_label: &'static str,
     requests: BoxRequestSharing<(K, Block), Option<Vec<V>>>,
+    block_stream: CurrentBlockWatcher,
+    max_staleness_blocks: Option<u64>,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -117,6 +120,17 @@ pub struct CacheConfig {
     pub maximum_recent_block_age: u64,
     pub max_retries: u32,
     pub delay_between_retries: Duration,
+    /// Maximum wall-clock age of a cached entry to still be returned for
+    /// `Block::Recent` requests. Older entries are treated as cache misses
+    /// and refetched. `None` disables age based invalidation.
+    pub max_age: Option<Duration>,
+    /// Maximum number of blocks a cached entry can trail the current block by
+    /// to still be returned for `Block::Recent` requests. Unlike
+    /// `maximum_recent_block_age` this is relative to the chain head instead
+    /// of the last automatic update, so it also applies when the updates fall
+    /// behind. Stale entries are refetched at the current block. `None`
+    /// disables the check.
+    pub max_staleness_blocks: Option<u64>,
 }
 
 impl Default for CacheConfig {
@@ -127,6 +141,8 @@ impl Default for CacheConfig {
             maximum_recent_block_age: Default::default(),
             max_retries: Default::default(),
             delay_between_retries: Default::default(),
+            max_age: None,
+            max_staleness_blocks: None,
         }
     }
 }
@@ -158,6 +174,9 @@ where
     ///
     /// maximum_recent_block_age: When a recent block is requested, this is the
     /// maximum a cached block can have to be considered.
+    ///
+    /// max_age: When a recent block is requested, this is the maximum time
+    /// since an entry was cached for it to be considered.
     pub fn new(
         config: CacheConfig,
         fetcher: F,
@@ -170,6 +189,7 @@ where
                 config.number_of_entries_to_auto_update,
                 block,
                 config.maximum_recent_block_age,
+                config.max_age,
             )),
             number_of_blocks_to_cache: config.number_of_blocks_to_cache,
             fetcher: Arc::new(fetcher),
@@ -178,6 +198,8 @@ where
             metrics: Metrics::instance(observe::metrics::get_storage_registry()).unwrap(),
             metrics_label,
             requests: BoxRequestSharing::labelled("liquidity_fetching".into()),
+            block_stream: block_stream.clone(),
+            max_staleness_blocks: config.max_staleness_blocks,
         });
 
         Self::spawn_gc_task(
@@ -193,6 +215,35 @@ where
         self.inner.fetch(keys, block).await
     }
 
+    /// Like `fetch` but also returns the block every value was read at.
+    pub async fn fetch_with_blocks(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+        block: Block,
+    ) -> Result<Vec<(V, u64)>> {
+        self.inner.fetch_with_blocks(keys, block).await
+    }
+
+    /// The number of blocks the last automatic update trails the current
+    /// block by.
+    pub fn head_lag(&self) -> u64 {
+        self.inner.head_lag()
+    }
+
+    /// Fetches and caches the entries for `keys` ahead of time, so that the
+    /// first requests for them are cache hits. Found entries are marked as
+    /// recently used and so are kept up to date by the automatic updates.
+    pub async fn warm(&self, keys: impl IntoIterator<Item = K>) -> Result<()> {
+        let block = self.inner.mutexed.lock().unwrap().last_update_block;
+        self.inner.fetch(keys, Block::Number(block)).await?;
+        Ok(())
+    }
+
+    /// The maximum age of entries returned for `Block::Recent` requests.
+    pub fn max_age(&self) -> Option<Duration> {
+        self.inner.mutexed.lock().unwrap().max_age
+    }
+
     fn spawn_gc_task(
         inner: std::sync::Weak<Inner<K, V, F>>,
         block_stream: CurrentBlockWatcher,
@@ -281,11 +332,33 @@ where
         fut.await.context("could not fetch liquidity")
     }
 
+    fn head_lag(&self) -> u64 {
+        let head = self.block_stream.borrow().number;
+        let last_update_block = self.mutexed.lock().unwrap().last_update_block;
+        head.saturating_sub(last_update_block)
+    }
+
     async fn fetch(&self, keys: impl IntoIterator<Item = K>, block: Block) -> Result<Vec<V>> {
+        let values = self.fetch_with_blocks(keys, block).await?;
+        Ok(values.into_iter().map(|(value, _)| value).collect())
+    }
+
+    async fn fetch_with_blocks(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+        block: Block,
+    ) -> Result<Vec<(V, u64)>> {
         let block = match block {
             Block::Recent => None,
             Block::Number(number) => Some(number),
         };
+        let head = self.block_stream.borrow().number;
+        let is_stale = |cached_block: u64| {
+            block.is_none()
+                && self
+                    .max_staleness_blocks
+                    .is_some_and(|max| head.saturating_sub(cached_block) > max)
+        };
 
         let mut cache_hit_count = 0usize;
         let mut cache_hits = Vec::new();
@@ -295,11 +368,11 @@ where
             let mut mutexed = self.mutexed.lock().unwrap();
             for key in keys {
                 match mutexed.get(key.clone(), block) {
-                    Some(values) => {
+                    Some((cached_block, values)) if !is_stale(cached_block) => {
                         cache_hit_count += 1;
-                        cache_hits.extend_from_slice(values);
+                        cache_hits.extend(values.iter().map(|value| (value.clone(), cached_block)));
                     }
-                    None => {
+                    _ => {
                         cache_misses.insert(key);
                     }
                 }
@@ -320,7 +393,10 @@ where
             return Ok(cache_hits);
         }
 
-        let cache_miss_block = block.unwrap_or(last_update_block);
+        let cache_miss_block = match self.max_staleness_blocks {
+            Some(_) => block.unwrap_or(cmp::max(last_update_block, head)),
+            None => block.unwrap_or(last_update_block),
+        };
         let cache_misses: Vec<_> = cache_misses.into_iter().collect();
         // Splits fetches into chunks because we can get over 1400 requests when the
         // cache is empty which tend to time out if we don't chunk them.
@@ -330,7 +406,11 @@ where
                 .fetch_inner_many(keys, Block::Number(cache_miss_block))
                 .await?;
             let found_keys = fetched.iter().map(K::for_value).unique().collect_vec();
-            cache_hits.extend_from_slice(&fetched);
+            cache_hits.extend(
+                fetched
+                    .iter()
+                    .map(|value| (value.clone(), cache_miss_block)),
+            );
 
             let mut mutexed = self.mutexed.lock().unwrap();
             mutexed.insert(cache_miss_block, chunk.iter().cloned(), fetched);
@@ -363,6 +443,10 @@ where
     last_update_block: u64,
     // Maximum age a cached block can have to count as recent.
     maximum_recent_block_age: u64,
+    // When the most recent entry for a key was inserted.
+    inserted_at: HashMap<K, Instant>,
+    // Maximum time since insertion for an entry to count as recent.
+    max_age: Option<Duration>,
 }
 
 impl<K, V> Mutexed<K, V>
@@ -373,6 +457,7 @@ where
         entries_lru_size: NonZeroUsize,
         current_block: u64,
         maximum_recent_block_age: u64,
+        max_age: Option<Duration>,
     ) -> Self {
         Self {
             recently_used: SizedCache::with_size(entries_lru_size.get()),
@@ -380,11 +465,18 @@ where
             entries: BTreeMap::new(),
             last_update_block: current_block,
             maximum_recent_block_age,
+            inserted_at: HashMap::new(),
+            max_age,
         }
     }
 
-    fn get(&mut self, key: K, block: Option<u64>) -> Option<&[V]> {
+    /// Returns the cached values for `key` together with the block they are
+    /// cached at.
+    fn get(&mut self, key: K, block: Option<u64>) -> Option<(u64, &[V])> {
         let allow_background_udpates = block.is_some();
+        if block.is_none() && self.is_expired(&key) {
+            return None;
+        }
         let block = block.or_else(|| {
             self.cached_most_recently_at_block
                 .get(&key)
@@ -397,7 +489,16 @@ where
         if allow_background_udpates && result.is_some_and(|values| !values.is_empty()) {
             self.recently_used.cache_set(key, ());
         }
-        result
+        result.map(|values| (block, values))
+    }
+
+    fn is_expired(&self, key: &K) -> bool {
+        let Some(max_age) = self.max_age else {
+            return false;
+        };
+        self.inserted_at
+            .get(key)
+            .is_none_or(|inserted_at| inserted_at.elapsed() > max_age)
     }
 
     fn insert(
@@ -406,15 +507,21 @@ where
         keys: impl IntoIterator<Item = K>,
         values: impl IntoIterator<Item = V>,
     ) {
+        let now = Instant::now();
         for key in keys {
-            match self.cached_most_recently_at_block.entry(key.clone()) {
+            let is_most_recent = match self.cached_most_recently_at_block.entry(key.clone()) {
                 Entry::Occupied(mut entry) => {
                     let value = entry.get_mut();
                     *value = cmp::max(*value, block);
+                    *value == block
                 }
                 Entry::Vacant(entry) => {
                     entry.insert(block);
+                    true
                 }
+            };
+            if is_most_recent {
+                self.inserted_at.insert(key.clone(), now);
             }
             // Make sure entries without any values are cached.
             self.entries.insert((block, key), Vec::new());
@@ -448,6 +555,8 @@ where
 
         self.cached_most_recently_at_block
             .retain(|_, block| *block >= oldest_to_keep);
+        self.inserted_at
+            .retain(|key, _| self.cached_most_recently_at_block.contains_key(key));
         tracing::debug!(
             entries = self.entries.len(),
             items,
@@ -709,6 +818,51 @@ mod tests {
         assert!(result.contains(&value2));
     }
 
+    #[tokio::test(start_paused = true)]
+    async fn refetches_entries_older_than_max_age() {
+        let fetcher = FakeCacheFetcher::default();
+        let values = fetcher.0.clone();
+        let block_number = 10u64;
+        let block_stream = mock_single_block(BlockInfo {
+            number: block_number,
+            ..Default::default()
+        });
+        let cache = RecentBlockCache::new(
+            CacheConfig {
+                max_age: Some(Duration::from_secs(10)),
+                ..Default::default()
+            },
+            fetcher,
+            block_stream,
+            "",
+        )
+        .unwrap();
+        assert_eq!(cache.max_age(), Some(Duration::from_secs(10)));
+
+        let old_value = TestValue::new(0, "old");
+        let new_value = TestValue::new(0, "new");
+
+        *values.lock().unwrap() = vec![old_value.clone()];
+        let result = cache.fetch(test_keys(0..1), Block::Recent).await.unwrap();
+        assert_eq!(result, [old_value.clone()]);
+
+        // Still within the TTL so the cached value is returned.
+        *values.lock().unwrap() = vec![new_value.clone()];
+        tokio::time::advance(Duration::from_secs(5)).await;
+        let result = cache.fetch(test_keys(0..1), Block::Recent).await.unwrap();
+        assert_eq!(result, [old_value]);
+
+        // Past the TTL the entry counts as a miss and gets refetched.
+        tokio::time::advance(Duration::from_secs(6)).await;
+        let result = cache.fetch(test_keys(0..1), Block::Recent).await.unwrap();
+        assert_eq!(result, [new_value.clone()]);
+
+        // The refetched entry is cached again.
+        values.lock().unwrap().clear();
+        let result = cache.fetch(test_keys(0..1), Block::Recent).await.unwrap();
+        assert_eq!(result, [new_value]);
+    }
+
     #[tokio::test]
     async fn uses_most_recent_cached_for_latest_block() {
         let fetcher = FakeCacheFetcher::default();
@@ -827,6 +981,58 @@ mod tests {
         assert_eq!(cache.mutexed.lock().unwrap().entries.len(), 2);
     }
 
+    #[tokio::test]
+    async fn refetches_entries_trailing_the_head() {
+        let fetcher = FakeCacheFetcher::new(vec![TestValue::new(0, "1")]);
+        let values = fetcher.0.clone();
+        let block = |number| BlockInfo {
+            number,
+            ..Default::default()
+        };
+        let (block_sender, block_stream) = tokio::sync::watch::channel(block(10));
+        let cache = RecentBlockCache::new(
+            CacheConfig {
+                number_of_blocks_to_cache: NonZeroU64::new(10).unwrap(),
+                maximum_recent_block_age: 10,
+                max_staleness_blocks: Some(2),
+                ..Default::default()
+            },
+            fetcher,
+            block_stream,
+            "",
+        )
+        .unwrap()
+        .inner;
+
+        let result = cache
+            .fetch_with_blocks(test_keys(0..1), Block::Recent)
+            .await
+            .unwrap();
+        assert_eq!(result, vec![(TestValue::new(0, "1"), 10)]);
+        values.lock().unwrap()[0] = TestValue::new(0, "2");
+
+        // The automatic updates don't keep up with the head, but the cached
+        // entry is still within the staleness bound.
+        block_sender.send(block(12)).unwrap();
+        assert_eq!(cache.head_lag(), 2);
+        let result = cache
+            .fetch_with_blocks(test_keys(0..1), Block::Recent)
+            .await
+            .unwrap();
+        assert_eq!(result, vec![(TestValue::new(0, "1"), 10)]);
+
+        // Once the head moves past the bound, the entry is fetched again at the
+        // current block.
+        block_sender.send(block(13)).unwrap();
+        let result = cache
+            .fetch_with_blocks(test_keys(0..1), Block::Recent)
+            .await
+            .unwrap();
+        assert_eq!(result, vec![(TestValue::new(0, "2"), 13)]);
+        let result = cache.fetch(test_keys(0..1), Block::Recent).await.unwrap();
+        assert_eq!(result, vec![TestValue::new(0, "2")]);
+    }
+
     #[tokio::test]
     async fn respects_max_age_limit_for_recent() {
         let fetcher = FakeCacheFetcher::default();
@@ -17,6 +17,7 @@ use {
     contracts::IUniswapLikeRouter,
     ethcontract::{H160, H256},
     hex_literal::hex,
+    model::TokenPair,
     std::{fmt::Display, str::FromStr, sync::Arc},
 };
 
@@ -41,6 +42,42 @@ pub const SWAPR_INIT: [u8; 32] =
 pub const TESTNET_UNISWAP_INIT: [u8; 32] =
     hex!("0efd7612822d579e24a8851501d8c2ad854264a1050e3dfcee8afcca08f80a86");
 
+/// Init code digests of the pairs deployed by the factory of every Uniswap V2
+/// like source, by chain id. The same source can deploy different pairs on
+/// different chains, e.g. the Uniswap V2 router on Gnosis Chain is Honeyswap's.
+const INIT_CODE_DIGESTS: &[(u64, BaselineSource, [u8; 32])] = &[
+    (1, BaselineSource::UniswapV2, UNISWAP_INIT),
+    (1, BaselineSource::SushiSwap, SUSHISWAP_INIT),
+    (1, BaselineSource::Swapr, SWAPR_INIT),
+    (5, BaselineSource::UniswapV2, UNISWAP_INIT),
+    (5, BaselineSource::SushiSwap, SUSHISWAP_INIT),
+    (100, BaselineSource::UniswapV2, HONEYSWAP_INIT),
+    (100, BaselineSource::Honeyswap, HONEYSWAP_INIT),
+    (100, BaselineSource::SushiSwap, SUSHISWAP_INIT),
+    (100, BaselineSource::Baoswap, BAOSWAP_INIT),
+    (100, BaselineSource::Swapr, SWAPR_INIT),
+    (8453, BaselineSource::UniswapV2, UNISWAP_INIT),
+    (8453, BaselineSource::SushiSwap, SUSHISWAP_INIT),
+    (42161, BaselineSource::UniswapV2, UNISWAP_INIT),
+    (42161, BaselineSource::SushiSwap, SUSHISWAP_INIT),
+    (42161, BaselineSource::Swapr, SWAPR_INIT),
+    (
+        11155111,
+        BaselineSource::TestnetUniswapV2,
+        TESTNET_UNISWAP_INIT,
+    ),
+];
+
+/// Returns the init code digest of the pairs `source` deploys on the chain
+/// with the specified id, or `None` if the source isn't a Uniswap V2 like
+/// source deployed on that chain.
+pub fn init_code_digest_for(chain_id: u64, source: BaselineSource) -> Option<[u8; 32]> {
+    INIT_CODE_DIGESTS
+        .iter()
+        .find(|(chain, source_, _)| *chain == chain_id && *source_ == source)
+        .map(|(_, _, digest)| *digest)
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct UniV2BaselineSourceParameters {
     router: H160,
@@ -63,39 +100,34 @@ pub struct UniV2BaselineSource {
 impl UniV2BaselineSourceParameters {
     pub fn from_baseline_source(source: BaselineSource, chain: &str) -> Option<Self> {
         use BaselineSource as BS;
-        let (contract, init_code_digest, pool_reading) = match source {
+        let (contract, pool_reading) = match source {
             BS::None | BS::BalancerV2 | BS::ZeroEx | BS::UniswapV3 => None,
             BS::UniswapV2 => Some((
                 contracts::UniswapV2Router02::raw_contract(),
-                UNISWAP_INIT,
                 PoolReadingStyle::Default,
             )),
             BS::Honeyswap => Some((
                 contracts::HoneyswapRouter::raw_contract(),
-                HONEYSWAP_INIT,
                 PoolReadingStyle::Default,
             )),
             BS::SushiSwap => Some((
                 contracts::SushiSwapRouter::raw_contract(),
-                SUSHISWAP_INIT,
                 PoolReadingStyle::Default,
             )),
             BS::Baoswap => Some((
                 contracts::BaoswapRouter::raw_contract(),
-                BAOSWAP_INIT,
                 PoolReadingStyle::Default,
             )),
             BS::Swapr => Some((
                 contracts::SwaprRouter::raw_contract(),
-                SWAPR_INIT,
                 PoolReadingStyle::Swapr,
             )),
             BS::TestnetUniswapV2 => Some((
                 contracts::TestnetUniswapV2Router02::raw_contract(),
-                TESTNET_UNISWAP_INIT,
                 PoolReadingStyle::Default,
             )),
         }?;
+        let init_code_digest = init_code_digest_for(chain.parse().ok()?, source)?;
         Some(Self {
             router: contract.networks.get(chain)?.address,
             init_code_digest: H256(init_code_digest),
@@ -106,11 +138,7 @@ impl UniV2BaselineSourceParameters {
     pub async fn into_source(&self, web3: &Web3) -> Result<UniV2BaselineSource> {
         let web3 = ethrpc::instrumented::instrument_with_label(web3, "uniswapV2".into());
         let router = contracts::IUniswapLikeRouter::at(&web3, self.router);
-        let factory = router.factory().call().await.context("factory")?;
-        let pair_provider = pair_provider::PairProvider {
-            factory,
-            init_code_digest: self.init_code_digest.0,
-        };
+        let pair_provider = self.pair_provider(&router).await?;
         let pool_reader = DefaultPoolReader::new(web3.clone(), pair_provider);
         let pool_reader: Box<dyn PoolReading> = match self.pool_reading {
             PoolReadingStyle::Default => Box::new(pool_reader),
@@ -124,6 +152,51 @@ impl UniV2BaselineSourceParameters {
             pool_fetching: Arc::new(fetcher),
         })
     }
+
+    async fn pair_provider(&self, router: &IUniswapLikeRouter) -> Result<PairProvider> {
+        let factory = router.factory().call().await.context("factory")?;
+        Ok(PairProvider::new(factory, self.init_code_digest.0))
+    }
+}
+
+/// Returns the pair provider of a Uniswap V2 like baseline source on the
+/// connected chain. The factory is resolved through the router the source has
+/// deployed on that chain.
+pub async fn pair_provider_for_source(web3: &Web3, source: BaselineSource) -> Result<PairProvider> {
+    let chain_id = web3.eth().chain_id().await.context("chain id")?;
+    let parameters =
+        UniV2BaselineSourceParameters::from_baseline_source(source, &chain_id.to_string())
+            .with_context(|| {
+                format!("{source:?} is not a Uniswap V2 like source deployed on chain {chain_id}")
+            })?;
+    let router = IUniswapLikeRouter::at(web3, parameters.router);
+    parameters.pair_provider(&router).await
+}
+
+/// Returns the Uniswap V2 like sources among `sources` that have a pool
+/// deployed for `pair` on the connected chain, together with the address of
+/// that pool. Sources that are not Uniswap V2 like or not deployed on the chain
+/// are skipped.
+pub async fn resolve_sources(
+    web3: &Web3,
+    pair: &TokenPair,
+    sources: &[BaselineSource],
+) -> Result<Vec<(BaselineSource, H160)>> {
+    let chain_id = web3.eth().chain_id().await.context("chain id")?.to_string();
+    let mut resolved = Vec::new();
+    for &source in sources {
+        let Some(parameters) =
+            UniV2BaselineSourceParameters::from_baseline_source(source, &chain_id)
+        else {
+            continue;
+        };
+        let router = IUniswapLikeRouter::at(web3, parameters.router);
+        let pair_provider = parameters.pair_provider(&router).await?;
+        if let Some(address) = pair_provider.pair_address_checked(web3, pair).await? {
+            resolved.push((source, address));
+        }
+    }
+    Ok(resolved)
 }
 
 impl Display for UniV2BaselineSourceParameters {
@@ -166,7 +239,7 @@ impl FromStr for UniV2BaselineSourceParameters {
 
 #[cfg(test)]
 mod tests {
-    use {super::*, model::TokenPair};
+    use {super::*, ethrpc::mock::MockTransport, serde_json::json};
 
     #[test]
     fn parse_address_init() {
@@ -191,6 +264,166 @@ mod tests {
         assert!(matches!(parsed.pool_reading, PoolReadingStyle::Swapr));
     }
 
+    #[test]
+    fn init_code_digests_by_chain() {
+        assert_eq!(
+            init_code_digest_for(1, BaselineSource::UniswapV2),
+            Some(UNISWAP_INIT)
+        );
+        assert_eq!(
+            init_code_digest_for(100, BaselineSource::Honeyswap),
+            Some(HONEYSWAP_INIT)
+        );
+        // The Uniswap V2 router on Gnosis Chain is Honeyswap's.
+        assert_eq!(
+            init_code_digest_for(100, BaselineSource::UniswapV2),
+            Some(HONEYSWAP_INIT)
+        );
+        assert_eq!(init_code_digest_for(42161, BaselineSource::Honeyswap), None);
+        assert_eq!(init_code_digest_for(1, BaselineSource::BalancerV2), None);
+    }
+
+    #[test]
+    fn every_router_network_has_an_init_code_digest() {
+        let routers = [
+            (
+                BaselineSource::UniswapV2,
+                contracts::UniswapV2Router02::raw_contract(),
+            ),
+            (
+                BaselineSource::Honeyswap,
+                contracts::HoneyswapRouter::raw_contract(),
+            ),
+            (
+                BaselineSource::SushiSwap,
+                contracts::SushiSwapRouter::raw_contract(),
+            ),
+            (
+                BaselineSource::Baoswap,
+                contracts::BaoswapRouter::raw_contract(),
+            ),
+            (
+                BaselineSource::Swapr,
+                contracts::SwaprRouter::raw_contract(),
+            ),
+            (
+                BaselineSource::TestnetUniswapV2,
+                contracts::TestnetUniswapV2Router02::raw_contract(),
+            ),
+        ];
+        for (source, router) in routers {
+            for chain in router.networks.keys() {
+                assert!(
+                    init_code_digest_for(chain.parse().unwrap(), source).is_some(),
+                    "{source:?} on chain {chain}"
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn pair_provider_for_mainnet_sources() {
+        let uniswap_router = contracts::UniswapV2Router02::raw_contract().networks["1"].address;
+        let sushiswap_router = contracts::SushiSwapRouter::raw_contract().networks["1"].address;
+        let uniswap_factory = H160([1; 20]);
+        let sushiswap_factory = H160([2; 20]);
+
+        let transport = MockTransport::new();
+        transport
+            .mock()
+            .expect_execute()
+            .returning(move |method, params| match method.as_str() {
+                "eth_chainId" => Ok(json!("0x1")),
+                "eth_call" => {
+                    let router: H160 = serde_json::from_value(params[0]["to"].clone()).unwrap();
+                    let factory = if router == uniswap_router {
+                        uniswap_factory
+                    } else if router == sushiswap_router {
+                        sushiswap_factory
+                    } else {
+                        panic!("unexpected router {router:?}");
+                    };
+                    Ok(json!(format!("0x{:0>64}", hex::encode(factory))))
+                }
+                _ => panic!("unexpected method {method}"),
+            });
+        let web3 = Web3::new(ethrpc::Web3Transport::new(transport));
+
+        let provider = pair_provider_for_source(&web3, BaselineSource::UniswapV2)
+            .await
+            .unwrap();
+        assert_eq!(provider.factory, uniswap_factory);
+        assert_eq!(provider.init_code_digest, UNISWAP_INIT);
+
+        let provider = pair_provider_for_source(&web3, BaselineSource::SushiSwap)
+            .await
+            .unwrap();
+        assert_eq!(provider.factory, sushiswap_factory);
+        assert_eq!(provider.init_code_digest, SUSHISWAP_INIT);
+
+        // Honeyswap is only deployed on Gnosis Chain.
+        assert!(pair_provider_for_source(&web3, BaselineSource::Honeyswap)
+            .await
+            .is_err());
+        // Balancer is not a Uniswap V2 like source.
+        assert!(pair_provider_for_source(&web3, BaselineSource::BalancerV2)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn resolve_sources_with_deployed_pools() {
+        let uniswap_router = contracts::UniswapV2Router02::raw_contract().networks["1"].address;
+        let sushiswap_router = contracts::SushiSwapRouter::raw_contract().networks["1"].address;
+        let uniswap_factory = H160([1; 20]);
+        let sushiswap_factory = H160([2; 20]);
+        let pair = TokenPair::new(testlib::tokens::GNO, testlib::tokens::WETH).unwrap();
+        let uniswap_pool = PairProvider::uniswap(uniswap_factory).pair_address(&pair);
+
+        let transport = MockTransport::new();
+        transport
+            .mock()
+            .expect_execute()
+            .returning(move |method, params| match method.as_str() {
+                "eth_chainId" => Ok(json!("0x1")),
+                "eth_call" => {
+                    let router: H160 = serde_json::from_value(params[0]["to"].clone()).unwrap();
+                    let factory = if router == uniswap_router {
+                        uniswap_factory
+                    } else if router == sushiswap_router {
+                        sushiswap_factory
+                    } else {
+                        panic!("unexpected router {router:?}");
+                    };
+                    Ok(json!(format!("0x{:0>64}", hex::encode(factory))))
+                }
+                "eth_getCode" => {
+                    let address: H160 = serde_json::from_value(params[0].clone()).unwrap();
+                    // Only Uniswap has a pool for the pair.
+                    Ok(json!(if address == uniswap_pool {
+                        "0x01"
+                    } else {
+                        "0x"
+                    }))
+                }
+                _ => panic!("unexpected method {method}"),
+            });
+        let web3 = Web3::new(ethrpc::Web3Transport::new(transport));
+
+        let resolved = resolve_sources(
+            &web3,
+            &pair,
+            &[
+                BaselineSource::UniswapV2,
+                BaselineSource::SushiSwap,
+                BaselineSource::BalancerV2,
+            ],
+        )
+        .await
+        .unwrap();
+        assert_eq!(resolved, vec![(BaselineSource::UniswapV2, uniswap_pool)]);
+    }
+
     async fn test_baseline_source(
         web3: &Web3,
         version: &str,
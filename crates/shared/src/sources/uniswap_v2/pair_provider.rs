@@ -1,12 +1,44 @@
-use {ethcontract::H160, model::TokenPair, web3::signing::keccak256};
+use {
+    crate::ethrpc::Web3,
+    anyhow::{Context, Result},
+    ethcontract::H160,
+    model::TokenPair,
+    web3::signing::keccak256,
+};
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct PairProvider {
     pub factory: H160,
     pub init_code_digest: [u8; 32],
 }
 
 impl PairProvider {
+    /// Creates a pair provider for a Uniswap V2 compatible factory. Forks
+    /// (SushiSwap, Honeyswap, ...) deploy pairs with their own init code, so
+    /// the digest of that init code needs to be specified.
+    pub fn new(factory: H160, init_code_digest: [u8; 32]) -> Self {
+        Self {
+            factory,
+            init_code_digest,
+        }
+    }
+
+    /// Creates a pair provider for a factory deploying canonical Uniswap V2
+    /// pairs.
+    pub fn uniswap(factory: H160) -> Self {
+        Self::new(factory, super::UNISWAP_INIT)
+    }
+
+    /// The factory deploying the pairs.
+    pub fn factory(&self) -> H160 {
+        self.factory
+    }
+
+    /// The digest of the init code the factory deploys pairs with.
+    pub fn init_code_digest(&self) -> [u8; 32] {
+        self.init_code_digest
+    }
+
     pub fn pair_address(&self, pair: &TokenPair) -> H160 {
         let (H160(token0), H160(token1)) = pair.get();
 
@@ -19,6 +51,50 @@ impl PairProvider {
         };
         create2_target_address(self.factory, &salt, &self.init_code_digest)
     }
+
+    /// Like `pair_address` but checks that a pool is actually deployed at the
+    /// computed address, returning `None` if there is no code there.
+    pub async fn pair_address_checked(
+        &self,
+        web3: &Web3,
+        pair: &TokenPair,
+    ) -> Result<Option<H160>> {
+        validate_pair(pair)?;
+        let address = self.pair_address(pair);
+        let code = web3
+            .eth()
+            .code(address, None)
+            .await
+            .with_context(|| format!("failed to fetch code of pair {address:?}"))?;
+        Ok((!code.0.is_empty()).then_some(address))
+    }
+}
+
+/// A token pair for which no pool can exist.
+#[derive(Debug, thiserror::Error, Eq, PartialEq)]
+pub enum InvalidPair {
+    #[error("token pair ({0:?}, {1:?}) contains the zero address")]
+    ZeroAddress(H160, H160),
+    #[error("token pair of identical tokens {0:?}")]
+    IdenticalTokens(H160),
+}
+
+/// Rejects pairs that no pool can exist for, so that they are caught before
+/// making any calls to the node. `TokenPair::new` already rules out
+/// identical tokens, but pairs with the zero address can be constructed.
+pub fn validate_pair(pair: &TokenPair) -> Result<(), InvalidPair> {
+    let (token0, token1) = pair.get();
+    validate_tokens(token0, token1)
+}
+
+fn validate_tokens(token0: H160, token1: H160) -> Result<(), InvalidPair> {
+    if token0 == token1 {
+        return Err(InvalidPair::IdenticalTokens(token0));
+    }
+    if token0.is_zero() || token1.is_zero() {
+        return Err(InvalidPair::ZeroAddress(token0, token1));
+    }
+    Ok(())
 }
 
 fn create2_target_address(creator: H160, salt: &[u8; 32], init_code_digest: &[u8; 32]) -> H160 {
@@ -31,7 +107,7 @@ fn create2_target_address(creator: H160, salt: &[u8; 32], init_code_digest: &[u8
 
 #[cfg(test)]
 mod tests {
-    use {super::*, hex_literal::hex};
+    use {super::*, ethrpc::mock::MockTransport, hex_literal::hex, serde_json::json};
 
     #[test]
     fn test_create2_mainnet() {
@@ -47,5 +123,123 @@ mod tests {
             provider.pair_address(&pair),
             addr!("3e8468f66d30fc99f745481d4b383f89861702c6")
         );
+        assert_eq!(
+            PairProvider::uniswap(provider.factory).pair_address(&pair),
+            provider.pair_address(&pair)
+        );
+    }
+
+    #[test]
+    fn test_create2_sushiswap() {
+        let provider = PairProvider::new(
+            addr!("C0AEe478e3658e2610c5F7A4A2E1777cE9e4f2Ac"),
+            crate::sources::uniswap_v2::SUSHISWAP_INIT,
+        );
+        let pair = TokenPair::new(testlib::tokens::GNO, testlib::tokens::WETH).unwrap();
+        assert_eq!(
+            provider.pair_address(&pair),
+            addr!("41328fdba556c8c969418ccccb077b7b8d932aa5")
+        );
+    }
+
+    #[test]
+    fn providers_for_same_factory_are_equal() {
+        let factory = H160([1; 20]);
+        let provider = PairProvider::uniswap(factory);
+        assert_eq!(provider.factory(), factory);
+        assert_eq!(
+            provider.init_code_digest(),
+            crate::sources::uniswap_v2::UNISWAP_INIT
+        );
+
+        assert_eq!(
+            provider,
+            PairProvider::new(factory, crate::sources::uniswap_v2::UNISWAP_INIT)
+        );
+        assert_ne!(provider, PairProvider::uniswap(H160([2; 20])));
+        assert_ne!(
+            provider,
+            PairProvider::new(factory, crate::sources::uniswap_v2::SUSHISWAP_INIT)
+        );
+    }
+
+    #[tokio::test]
+    async fn pair_address_checked_requires_code() {
+        let provider = PairProvider::uniswap(H160([1; 20]));
+        let deployed = TokenPair::new(testlib::tokens::GNO, testlib::tokens::WETH).unwrap();
+        let undeployed = TokenPair::new(testlib::tokens::GNO, testlib::tokens::USDC).unwrap();
+        let deployed_address = provider.pair_address(&deployed);
+
+        let transport = MockTransport::new();
+        transport
+            .mock()
+            .expect_execute()
+            .returning(move |method, params| {
+                assert_eq!(method, "eth_getCode");
+                let address: H160 = serde_json::from_value(params[0].clone()).unwrap();
+                if address == deployed_address {
+                    Ok(json!("0x6080"))
+                } else {
+                    Ok(json!("0x"))
+                }
+            });
+        let web3 = Web3::new(ethrpc::Web3Transport::new(transport));
+
+        assert_eq!(
+            provider
+                .pair_address_checked(&web3, &deployed)
+                .await
+                .unwrap(),
+            Some(deployed_address)
+        );
+        assert_eq!(
+            provider
+                .pair_address_checked(&web3, &undeployed)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_pairs_with_the_zero_address() {
+        let pair = TokenPair::new(H160::zero(), testlib::tokens::WETH).unwrap();
+        assert_eq!(
+            validate_pair(&pair),
+            Err(InvalidPair::ZeroAddress(
+                H160::zero(),
+                testlib::tokens::WETH
+            ))
+        );
+
+        // The mock panics on any call, so the pair is rejected up front.
+        let web3 = Web3::new(ethrpc::Web3Transport::new(MockTransport::new()));
+        let err = PairProvider::uniswap(H160([1; 20]))
+            .pair_address_checked(&web3, &pair)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<InvalidPair>(),
+            Some(&InvalidPair::ZeroAddress(
+                H160::zero(),
+                testlib::tokens::WETH
+            ))
+        );
+    }
+
+    #[test]
+    fn rejects_pairs_of_identical_tokens() {
+        // `TokenPair::new` already refuses to create such pairs.
+        assert_eq!(
+            TokenPair::new(testlib::tokens::WETH, testlib::tokens::WETH),
+            None
+        );
+        assert_eq!(
+            validate_tokens(testlib::tokens::WETH, testlib::tokens::WETH),
+            Err(InvalidPair::IdenticalTokens(testlib::tokens::WETH))
+        );
+
+        let pair = TokenPair::new(testlib::tokens::GNO, testlib::tokens::WETH).unwrap();
+        assert_eq!(validate_pair(&pair), Ok(()));
     }
 }
@@ -1,12 +1,22 @@
 use {
     crate::{
+        ethrpc::Web3,
         recent_block_cache::{Block, CacheConfig, CacheFetching, CacheKey, RecentBlockCache},
-        sources::uniswap_v2::pool_fetching::{Pool, PoolFetching},
+        sources::uniswap_v2::{
+            pair_provider::PairProvider,
+            pool_fetching::{self, Pool, PoolFetching, Reserves},
+        },
     },
     anyhow::Result,
+    cached::{Cached, SizedCache},
+    ethcontract::{BlockId, BlockNumber},
     ethrpc::block_stream::CurrentBlockWatcher,
     model::TokenPair,
-    std::{collections::HashSet, sync::Arc},
+    std::{
+        collections::HashSet,
+        sync::{Arc, Mutex},
+        time::Duration,
+    },
 };
 
 pub struct PoolCache(RecentBlockCache<TokenPair, Pool, Arc<dyn PoolFetching>>);
@@ -42,6 +52,36 @@ impl PoolCache {
             "uniswapv2",
         )?))
     }
+
+    /// The maximum age of pools returned for `Block::Recent` requests.
+    pub fn max_age(&self) -> Option<Duration> {
+        self.0.max_age()
+    }
+
+    /// Like `PoolFetching::fetch` but also returns the block the reserves of
+    /// every pool were read at, so that callers can tell how stale they are.
+    pub async fn reserves_with_block(
+        &self,
+        pairs: HashSet<TokenPair>,
+        block: Block,
+    ) -> Result<Vec<(Pool, u64)>> {
+        self.0.fetch_with_blocks(pairs, block).await
+    }
+
+    /// The number of blocks the cached pools trail the current block by. See
+    /// `CacheConfig::max_staleness_blocks` for forcing refetches of stale
+    /// pools.
+    pub fn head_lag(&self) -> u64 {
+        self.0.head_lag()
+    }
+
+    /// Pre-fetches the pools for `pairs`, e.g. the most commonly traded ones
+    /// at startup, so that the first quotes for them don't have to wait on
+    /// the node. Fetches go through the same batching as regular cache
+    /// misses.
+    pub async fn warm(&self, pairs: &[TokenPair]) -> Result<()> {
+        self.0.warm(pairs.iter().copied()).await
+    }
 }
 
 #[async_trait::async_trait]
@@ -50,3 +90,182 @@ impl PoolFetching for PoolCache {
         self.0.fetch(pairs, block).await
     }
 }
+
+/// Least recently used cache of pool reserves as of specific blocks, so that
+/// repeated quotes at the same block don't fetch the reserves from the node
+/// again. Pairs without a pool are cached as well.
+pub struct ReserveCache {
+    cache: Mutex<SizedCache<(TokenPair, u64), Option<Reserves>>>,
+}
+
+impl ReserveCache {
+    /// Creates a cache holding the reserves of at most `capacity` pairs and
+    /// blocks.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            cache: Mutex::new(SizedCache::with_size(capacity.max(1))),
+        }
+    }
+
+    /// Returns the cached reserves of `pair` at `block`, `Some(None)` if the
+    /// pair is known to have no pool.
+    pub fn get(&self, pair: TokenPair, block: u64) -> Option<Option<Reserves>> {
+        self.cache
+            .lock()
+            .unwrap()
+            .cache_get(&(pair, block))
+            .copied()
+    }
+
+    pub fn insert(&self, pair: TokenPair, block: u64, reserves: Option<Reserves>) {
+        self.cache
+            .lock()
+            .unwrap()
+            .cache_set((pair, block), reserves);
+    }
+
+    /// Number of lookups that were served from the cache.
+    pub fn hits(&self) -> u64 {
+        self.cache.lock().unwrap().cache_hits().unwrap_or_default()
+    }
+
+    /// Number of lookups that were not in the cache.
+    pub fn misses(&self) -> u64 {
+        self.cache
+            .lock()
+            .unwrap()
+            .cache_misses()
+            .unwrap_or_default()
+    }
+
+    /// Like `pool_fetching::fetch_reserves_batched` at `block`, but only
+    /// fetches the reserves that are not cached yet.
+    pub async fn fetch(
+        &self,
+        web3: &Web3,
+        pair_provider: &PairProvider,
+        pairs: &[TokenPair],
+        batch_size: usize,
+        block: u64,
+    ) -> Result<Vec<(TokenPair, Option<Reserves>)>> {
+        let cached: Vec<_> = pairs.iter().map(|pair| self.get(*pair, block)).collect();
+        let missing: Vec<_> = pairs
+            .iter()
+            .zip(&cached)
+            .filter(|(_, cached)| cached.is_none())
+            .map(|(pair, _)| *pair)
+            .collect();
+        let fetched = pool_fetching::fetch_reserves_batched(
+            web3,
+            pair_provider,
+            &missing,
+            batch_size,
+            BlockId::Number(BlockNumber::Number(block.into())),
+        )
+        .await?;
+        let mut fetched = fetched.into_iter();
+        Ok(pairs
+            .iter()
+            .zip(cached)
+            .map(|(pair, cached)| {
+                let reserves = match cached {
+                    Some(reserves) => reserves,
+                    None => {
+                        let (_, reserves) = fetched.next().expect("reserves for every pair");
+                        self.insert(*pair, block, reserves);
+                        reserves
+                    }
+                };
+                (*pair, reserves)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        ethcontract::H160,
+        ethrpc::block_stream::{mock_single_block, BlockInfo},
+        std::{
+            num::NonZeroUsize,
+            sync::atomic::{AtomicUsize, Ordering},
+        },
+    };
+
+    #[derive(Default)]
+    struct CountingPoolFetcher {
+        requested: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl PoolFetching for CountingPoolFetcher {
+        async fn fetch(&self, pairs: HashSet<TokenPair>, _: Block) -> Result<Vec<Pool>> {
+            self.requested.fetch_add(pairs.len(), Ordering::SeqCst);
+            Ok(pairs
+                .into_iter()
+                .map(|pair| Pool::uniswap(H160::zero(), pair, (1, 1)))
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn warm_caches_pools() {
+        let pair = |i| TokenPair::new(H160::from_low_u64_be(i), H160::zero()).unwrap();
+        let pairs = [pair(1), pair(2), pair(3)];
+        let fetcher = Arc::new(CountingPoolFetcher::default());
+        let cache = PoolCache::new(
+            CacheConfig {
+                number_of_entries_to_auto_update: NonZeroUsize::new(10).unwrap(),
+                ..Default::default()
+            },
+            fetcher.clone(),
+            mock_single_block(BlockInfo {
+                number: 10,
+                ..Default::default()
+            }),
+        )
+        .unwrap();
+
+        cache.warm(&pairs).await.unwrap();
+        assert_eq!(fetcher.requested.load(Ordering::SeqCst), 3);
+
+        let pools = cache
+            .fetch(pairs.into_iter().collect(), Block::Recent)
+            .await
+            .unwrap();
+        assert_eq!(pools.len(), 3);
+        let pools = cache
+            .fetch(pairs.into_iter().collect(), Block::Number(10))
+            .await
+            .unwrap();
+        assert_eq!(pools.len(), 3);
+        assert_eq!(fetcher.requested.load(Ordering::SeqCst), 3);
+
+        // Pairs that were not warmed are still fetched lazily.
+        cache.fetch([pair(4)].into(), Block::Recent).await.unwrap();
+        assert_eq!(fetcher.requested.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn reserve_cache_evicts_least_recently_used() {
+        let pair = |i| TokenPair::new(H160::from_low_u64_be(i), H160::zero()).unwrap();
+        let cache = ReserveCache::new(2);
+
+        cache.insert(pair(1), 10, Some((1, 1)));
+        cache.insert(pair(2), 10, None);
+        // Makes `pair(2)` the least recently used entry.
+        assert_eq!(cache.get(pair(1), 10), Some(Some((1, 1))));
+        cache.insert(pair(3), 10, Some((3, 3)));
+
+        assert_eq!(cache.get(pair(2), 10), None);
+        assert_eq!(cache.get(pair(1), 10), Some(Some((1, 1))));
+        assert_eq!(cache.get(pair(3), 10), Some(Some((3, 3))));
+        // Entries are per block.
+        assert_eq!(cache.get(pair(1), 11), None);
+
+        assert_eq!(cache.hits(), 3);
+        assert_eq!(cache.misses(), 2);
+    }
+}
@@ -1,21 +1,42 @@
 use {
-    super::pair_provider::PairProvider,
+    super::pair_provider::{self, PairProvider},
     crate::{baseline_solver::BaselineSolvable, ethrpc::Web3, recent_block_cache::Block},
-    anyhow::Result,
+    anyhow::{Context as _, Result},
     contracts::{errors::EthcontractErrorType, IUniswapLikePair, ERC20},
-    ethcontract::{errors::MethodError, BlockId, H160, U256},
+    ethcontract::{
+        errors::MethodError,
+        web3::ethabi::{self, ParamType, Token},
+        BlockId,
+        BlockNumber,
+        H160,
+        U256,
+    },
+    ethrpc::multicall::{self, MulticallExt as _},
     futures::{
         future::{self, BoxFuture},
         FutureExt as _,
     },
     model::TokenPair,
     num::rational::Ratio,
-    std::{collections::HashSet, sync::RwLock, time::Duration},
+    std::{
+        collections::{HashMap, HashSet},
+        sync::RwLock,
+        time::Duration,
+    },
     ttl_cache::TtlCache,
 };
 
 const POOL_SWAP_GAS_COST: usize = 60_000;
 
+/// Default number of `getReserves` calls aggregated into a single multicall.
+pub const DEFAULT_RESERVES_BATCH_SIZE: usize = 100;
+
+/// Swap fee charged by Uniswap V2 pools (30 basis points).
+pub const DEFAULT_POOL_FEE_BPS: u32 = 30;
+
+/// `getReserves()` function selector.
+const GET_RESERVES_SELECTOR: [u8; 4] = hex_literal::hex!("0902f1ac");
+
 lazy_static::lazy_static! {
     static ref POOL_MAX_RESERVES: U256 = U256::from((1u128 << 112) - 1);
 }
@@ -41,6 +62,43 @@ impl PoolReading for Box<dyn PoolReading> {
     }
 }
 
+/// A source of baseline liquidity that can report the effective reserves of
+/// its pool for a token pair, independently of the kind of AMM behind it.
+///
+/// The trait is object safe so that different sources can be used together
+/// as `Box<dyn BaselineLiquiditySource>`.
+pub trait BaselineLiquiditySource: Send + Sync {
+    /// Read the effective reserves for the specified token pair, ordered like
+    /// its tokens. Returns `None` if the source has no pool for the pair.
+    fn reserves(&self, pair: TokenPair, block: BlockId) -> BoxFuture<'_, Result<Option<Reserves>>>;
+}
+
+/// Fee-on-transfer (deflationary) tokens together with the fraction of every
+/// transferred amount they withhold.
+#[derive(Clone, Debug, Default)]
+pub struct TransferFees(HashMap<H160, Ratio<u32>>);
+
+impl TransferFees {
+    pub fn new(fees: HashMap<H160, Ratio<u32>>) -> Self {
+        Self(fees)
+    }
+
+    /// Returns whether transfers of `token` are charged a fee.
+    pub fn fee_on_transfer(&self, token: &H160) -> bool {
+        self.0.contains_key(token)
+    }
+
+    /// Returns the amount received when transferring `amount` of `token`.
+    fn amount_received(&self, token: &H160, amount: U256) -> Option<U256> {
+        let Some(fee) = self.0.get(token) else {
+            return Some(amount);
+        };
+        amount
+            .checked_mul(U256::from(fee.denom().checked_sub(*fee.numer())?))?
+            .checked_div(U256::from(*fee.denom()))
+    }
+}
+
 #[derive(Clone, Copy, Eq, Hash, PartialEq, Debug)]
 pub struct Pool {
     pub address: H160,
@@ -51,11 +109,22 @@ pub struct Pool {
 
 impl Pool {
     pub fn uniswap(address: H160, tokens: TokenPair, reserves: (u128, u128)) -> Self {
+        Self::with_fee_bps(address, tokens, reserves, DEFAULT_POOL_FEE_BPS)
+    }
+
+    /// Creates a Uniswap-like pool that charges `fee_bps` basis points on
+    /// every swap.
+    pub fn with_fee_bps(
+        address: H160,
+        tokens: TokenPair,
+        reserves: (u128, u128),
+        fee_bps: u32,
+    ) -> Self {
         Self {
             address,
             tokens,
             reserves,
-            fee: Ratio::new(3, 1000),
+            fee: fee_from_bps(fee_bps),
         }
     }
 
@@ -70,6 +139,20 @@ impl Pool {
         ))
     }
 
+    /// Like `get_amount_out` but accounts for fee-on-transfer tokens: the pool
+    /// only receives the input amount net of the input token's transfer fee
+    /// and the returned output is net of the output token's transfer fee.
+    pub fn get_amount_out_net_of_transfer_fees(
+        &self,
+        token_in: H160,
+        amount_in: U256,
+        fees: &TransferFees,
+    ) -> Option<(U256, H160)> {
+        let amount_in = fees.amount_received(&token_in, amount_in)?;
+        let (amount_out, token_out) = self.get_amount_out(token_in, amount_in)?;
+        Some((fees.amount_received(&token_out, amount_out)?, token_out))
+    }
+
     /// Given an output amount and token, returns a required input amount and
     /// address of the other asset. Returns None if operation not possible
     /// due to arithmetic issues (e.g. over or underflow, reserve too small)
@@ -107,38 +190,84 @@ impl Pool {
     }
 
     fn amount_out(&self, amount_in: U256, reserve_in: U256, reserve_out: U256) -> Option<U256> {
-        if amount_in.is_zero() || reserve_in.is_zero() || reserve_out.is_zero() {
-            return None;
-        }
+        amount_out_with_fee(amount_in, reserve_in, reserve_out, self.fee)
+    }
 
-        let amount_in_with_fee =
-            amount_in.checked_mul(U256::from(self.fee.denom().checked_sub(*self.fee.numer())?))?;
-        let numerator = amount_in_with_fee.checked_mul(reserve_out)?;
-        let denominator = reserve_in
-            .checked_mul(U256::from(*self.fee.denom()))?
-            .checked_add(amount_in_with_fee)?;
-        let amount_out = numerator.checked_div(denominator)?;
+    fn amount_in(&self, amount_out: U256, reserve_in: U256, reserve_out: U256) -> Option<U256> {
+        amount_in_with_fee(amount_out, reserve_in, reserve_out, self.fee)
+    }
+}
 
-        check_final_reserves(amount_in, amount_out, reserve_in, reserve_out)?;
-        Some(amount_out)
+/// Returns the output amount received for selling `amount_in` to a pool with
+/// the given reserves charging `fee_bps` basis points, following
+/// `UniswapV2Library.getAmountOut`. Returns None for empty reserves or if the
+/// result doesn't fit into a `U256`, instead of panicking.
+pub fn get_amount_out(
+    amount_in: U256,
+    reserve_in: U256,
+    reserve_out: U256,
+    fee_bps: u32,
+) -> Option<U256> {
+    amount_out_with_fee(amount_in, reserve_in, reserve_out, fee_from_bps(fee_bps))
+}
+
+fn amount_out_with_fee(
+    amount_in: U256,
+    reserve_in: U256,
+    reserve_out: U256,
+    fee: Ratio<u32>,
+) -> Option<U256> {
+    if amount_in.is_zero() || reserve_in.is_zero() || reserve_out.is_zero() {
+        return None;
     }
 
-    fn amount_in(&self, amount_out: U256, reserve_in: U256, reserve_out: U256) -> Option<U256> {
-        if amount_out.is_zero() || reserve_in.is_zero() || reserve_out.is_zero() {
-            return None;
-        }
+    let amount_in_with_fee =
+        amount_in.checked_mul(U256::from(fee.denom().checked_sub(*fee.numer())?))?;
+    // The product can exceed a `U256` even if the output amount doesn't.
+    let numerator = amount_in_with_fee.full_mul(reserve_out);
+    let denominator = reserve_in
+        .checked_mul(U256::from(*fee.denom()))?
+        .checked_add(amount_in_with_fee)?;
+    let amount_out = numerator.checked_div(denominator.into())?.try_into().ok()?;
 
-        let numerator = reserve_in
-            .checked_mul(amount_out)?
-            .checked_mul(U256::from(*self.fee.denom()))?;
-        let denominator = reserve_out
-            .checked_sub(amount_out)?
-            .checked_mul(U256::from(self.fee.denom().checked_sub(*self.fee.numer())?))?;
-        let amount_in = numerator.checked_div(denominator)?.checked_add(1.into())?;
+    check_final_reserves(amount_in, amount_out, reserve_in, reserve_out)?;
+    Some(amount_out)
+}
+
+/// Returns the input amount required to buy `amount_out` from a pool with the
+/// given reserves charging `fee_bps` basis points, following
+/// `UniswapV2Library.getAmountIn`. The result is rounded up so the pool's
+/// invariant holds. Returns None if the pool can't provide `amount_out`, i.e.
+/// `amount_out >= reserve_out`, or on arithmetic issues.
+pub fn get_amount_in(
+    amount_out: U256,
+    reserve_in: U256,
+    reserve_out: U256,
+    fee_bps: u32,
+) -> Option<U256> {
+    amount_in_with_fee(amount_out, reserve_in, reserve_out, fee_from_bps(fee_bps))
+}
 
-        check_final_reserves(amount_in, amount_out, reserve_in, reserve_out)?;
-        Some(amount_in)
+fn amount_in_with_fee(
+    amount_out: U256,
+    reserve_in: U256,
+    reserve_out: U256,
+    fee: Ratio<u32>,
+) -> Option<U256> {
+    if amount_out.is_zero() || reserve_in.is_zero() || reserve_out.is_zero() {
+        return None;
     }
+
+    let numerator = reserve_in
+        .checked_mul(amount_out)?
+        .checked_mul(U256::from(*fee.denom()))?;
+    let denominator = reserve_out
+        .checked_sub(amount_out)?
+        .checked_mul(U256::from(fee.denom().checked_sub(*fee.numer())?))?;
+    let amount_in = numerator.checked_div(denominator)?.checked_add(1.into())?;
+
+    check_final_reserves(amount_in, amount_out, reserve_in, reserve_out)?;
+    Some(amount_in)
 }
 
 fn check_final_reserves(
@@ -238,10 +367,11 @@ where
 /// The default pool reader implementation.
 ///
 /// This fetches on-chain pool state for Uniswap-like pools assuming a constant
-/// fee of 0.3%.
+/// fee, which is 0.3% unless configured otherwise.
 pub struct DefaultPoolReader {
     pub pair_provider: PairProvider,
     pub web3: Web3,
+    pub fee: Ratio<u32>,
 }
 
 impl DefaultPoolReader {
@@ -249,8 +379,20 @@ impl DefaultPoolReader {
         Self {
             pair_provider,
             web3,
+            fee: fee_from_bps(DEFAULT_POOL_FEE_BPS),
         }
     }
+
+    /// Uses a swap fee of `fee_bps` basis points for all read pools, for
+    /// sources that forked Uniswap V2 with a different fee tier.
+    pub fn with_fee_bps(mut self, fee_bps: u32) -> Self {
+        self.fee = fee_from_bps(fee_bps);
+        self
+    }
+}
+
+fn fee_from_bps(fee_bps: u32) -> Ratio<u32> {
+    Ratio::new(fee_bps, 10_000)
 }
 
 impl PoolReading for DefaultPoolReader {
@@ -259,6 +401,7 @@ impl PoolReading for DefaultPoolReader {
 
         let pair_contract = IUniswapLikePair::at(&self.web3, pair_address);
         let fetch_reserves = pair_contract.get_reserves().block(block).call();
+        let fetch_pool_token0 = pair_contract.token0().block(block).call();
 
         // Fetch ERC20 token balances of the pools to sanity check with reserves
         let token0 = ERC20::at(&self.web3, pair.get().0);
@@ -266,27 +409,44 @@ impl PoolReading for DefaultPoolReader {
 
         let fetch_token0_balance = token0.balance_of(pair_address).block(block).call();
         let fetch_token1_balance = token1.balance_of(pair_address).block(block).call();
+        let fee = self.fee;
 
         async move {
-            let (reserves, token0_balance, token1_balance) =
-                futures::join!(fetch_reserves, fetch_token0_balance, fetch_token1_balance);
+            let (reserves, pool_token0, token0_balance, token1_balance) = futures::join!(
+                fetch_reserves,
+                fetch_pool_token0,
+                fetch_token0_balance,
+                fetch_token1_balance
+            );
             handle_results(
                 FetchedPool {
                     pair,
                     reserves,
+                    pool_token0,
                     token0_balance,
                     token1_balance,
                 },
                 pair_address,
+                fee,
             )
         }
         .boxed()
     }
 }
 
+impl BaselineLiquiditySource for DefaultPoolReader {
+    fn reserves(&self, pair: TokenPair, block: BlockId) -> BoxFuture<'_, Result<Option<Reserves>>> {
+        self.read_state(pair, block)
+            .map(|pool| Ok(pool?.map(|pool| pool.reserves)))
+            .boxed()
+    }
+}
+
 struct FetchedPool {
     pair: TokenPair,
     reserves: Result<(u128, u128, u32), MethodError>,
+    /// The token the pool's first reserve is denominated in.
+    pool_token0: Result<H160, MethodError>,
     token0_balance: Result<U256, MethodError>,
     token1_balance: Result<U256, MethodError>,
 }
@@ -303,11 +463,22 @@ pub fn handle_contract_error<T>(result: Result<T, MethodError>) -> Result<Option
     }
 }
 
-fn handle_results(fetched_pool: FetchedPool, address: H160) -> Result<Option<Pool>> {
+fn handle_results(
+    fetched_pool: FetchedPool,
+    address: H160,
+    fee: Ratio<u32>,
+) -> Result<Option<Pool>> {
     let reserves = handle_contract_error(fetched_pool.reserves)?;
+    let pool_token0 = handle_contract_error(fetched_pool.pool_token0)?;
     let token0_balance = handle_contract_error(fetched_pool.token0_balance)?;
     let token1_balance = handle_contract_error(fetched_pool.token1_balance)?;
 
+    let reserves = reserves
+        .zip(pool_token0)
+        .and_then(|(reserves, pool_token0)| {
+            orient_reserves(fetched_pool.pair, pool_token0, reserves)
+        });
+
     let pool = reserves.and_then(|reserves| {
         // Some ERC20s (e.g. AMPL) have an elastic supply and can thus reduce the
         // balance of their owners without any transfer or other interaction ("rebase").
@@ -325,16 +496,148 @@ fn handle_results(fetched_pool: FetchedPool, address: H160) -> Result<Option<Poo
         if U256::from(reserves.0) > token0_balance? || U256::from(reserves.1) > token1_balance? {
             return None;
         }
-        Some(Pool::uniswap(
+        Some(Pool {
             address,
-            fetched_pool.pair,
-            (reserves.0, reserves.1),
-        ))
+            tokens: fetched_pool.pair,
+            reserves,
+            fee,
+        })
     });
 
     Ok(pool)
 }
 
+/// Maps the reserves a pool reports for its own `token0` and `token1` onto
+/// the order of `pair`. Uniswap V2 like factories make the smaller token the
+/// pool's `token0`, but the pool is checked explicitly so that reserves never
+/// end up on the wrong side. Returns `None` for pools not trading `pair`.
+fn orient_reserves(
+    pair: TokenPair,
+    pool_token0: H160,
+    (reserve0, reserve1, _): (u128, u128, u32),
+) -> Option<Reserves> {
+    match pair.get() {
+        (first, _) if pool_token0 == first => Some((reserve0, reserve1)),
+        (_, second) if pool_token0 == second => Some((reserve1, reserve0)),
+        _ => None,
+    }
+}
+
+/// Reserves of a pool, ordered like the tokens of its `TokenPair`.
+pub type Reserves = (u128, u128);
+
+/// Fetches the reserves of many pools at once by aggregating their
+/// `getReserves` calls into multicalls of at most `batch_size` calls.
+///
+/// Pairs without a deployed pool and pairs that no pool can exist for map to
+/// `None`, while node errors fail the whole fetch.
+pub async fn fetch_reserves_batched(
+    web3: &Web3,
+    pair_provider: &PairProvider,
+    pairs: &[TokenPair],
+    batch_size: usize,
+    block: BlockId,
+) -> Result<Vec<(TokenPair, Option<Reserves>)>> {
+    let valid_pairs = pairs
+        .iter()
+        .filter(|pair| match pair_provider::validate_pair(pair) {
+            Ok(()) => true,
+            Err(err) => {
+                tracing::warn!(?pair, ?err, "skipping reserves of invalid pair");
+                false
+            }
+        })
+        .copied()
+        .collect::<Vec<_>>();
+    let mut reserves = HashMap::with_capacity(valid_pairs.len());
+    for chunk in valid_pairs.chunks(batch_size.max(1)) {
+        let calls = chunk
+            .iter()
+            .map(|pair| multicall::Call {
+                to: pair_provider.pair_address(pair),
+                data: GET_RESERVES_SELECTOR.to_vec(),
+                ..Default::default()
+            })
+            .collect();
+        let results = web3
+            .eth()
+            .multicall(calls, Default::default(), Some(block))
+            .await;
+
+        for (pair, result) in chunk.iter().zip(results) {
+            let pool_reserves = match result {
+                Ok(data) => decode_reserves(&data),
+                Err(err) => match EthcontractErrorType::classify(&err) {
+                    EthcontractErrorType::Node => return Err(err.into()),
+                    EthcontractErrorType::Contract => None,
+                },
+            };
+            reserves.insert(*pair, pool_reserves);
+        }
+    }
+    Ok(pairs
+        .iter()
+        .map(|pair| (*pair, reserves.get(pair).copied().flatten()))
+        .collect())
+}
+
+/// Like `fetch_reserves_batched` but guarantees a consistent snapshot: block
+/// tags like `latest` are resolved to a block number up front so that all
+/// `getReserves` calls read the same block, no matter how many multicalls they
+/// are split into.
+pub async fn fetch_reserves_at_block(
+    web3: &Web3,
+    pair_provider: &PairProvider,
+    pairs: &[TokenPair],
+    batch_size: usize,
+    block: BlockNumber,
+) -> Result<Vec<(TokenPair, Option<Reserves>)>> {
+    let number = match block {
+        BlockNumber::Number(number) => number,
+        BlockNumber::Latest => web3
+            .eth()
+            .block_number()
+            .await
+            .context("failed to fetch current block number")?,
+        tag => web3
+            .eth()
+            .block(BlockId::Number(tag))
+            .await
+            .with_context(|| format!("failed to fetch block {tag:?}"))?
+            .and_then(|block| block.number)
+            .with_context(|| format!("block {tag:?} has no number"))?,
+    };
+    fetch_reserves_batched(
+        web3,
+        pair_provider,
+        pairs,
+        batch_size,
+        BlockId::Number(BlockNumber::Number(number)),
+    )
+    .await
+}
+
+fn decode_reserves(data: &[u8]) -> Option<Reserves> {
+    // Calls to addresses without code succeed with empty return data, so
+    // anything that doesn't decode means that there is no pool.
+    let tokens = ethabi::decode(
+        &[
+            ParamType::Uint(112),
+            ParamType::Uint(112),
+            ParamType::Uint(32),
+        ],
+        data,
+    )
+    .ok()?;
+    match tokens.as_slice() {
+        [Token::Uint(reserve0), Token::Uint(reserve1), _] => Some((
+            u128::try_from(*reserve0).ok()?,
+            u128::try_from(*reserve1).ok()?,
+        )),
+        _ => None,
+    }
+}
+
 pub mod test_util {
     use {
         super::{Pool, PoolFetching},
@@ -364,8 +667,66 @@ mod tests {
     use {
         super::*,
         contracts::errors::{testing_contract_error, testing_node_error},
+        ethcontract::web3,
+        ethrpc::mock::MockTransport,
+        serde_json::json,
     };
 
+    /// ABI encodes multicall return data where each entry is either the
+    /// reserves returned by a pool or empty data for a missing pool.
+    fn multicall_response(results: &[Option<Reserves>]) -> serde_json::Value {
+        let results = results
+            .iter()
+            .map(|reserves| {
+                let data = reserves
+                    .map(|(reserve0, reserve1)| {
+                        ethabi::encode(&[
+                            Token::Uint(reserve0.into()),
+                            Token::Uint(reserve1.into()),
+                            Token::Uint(0.into()),
+                        ])
+                    })
+                    .unwrap_or_default();
+                Token::Tuple(vec![Token::Bool(true), Token::Bytes(data)])
+            })
+            .collect();
+        json!(web3::types::Bytes(ethabi::encode(&[Token::Array(results)])))
+    }
+
+    #[test]
+    fn amount_out_net_of_transfer_fees() {
+        let taxed = H160::from_low_u64_be(1);
+        let regular = H160::from_low_u64_be(2);
+        let pool = Pool::uniswap(
+            H160::from_low_u64_be(1),
+            TokenPair::new(taxed, regular).unwrap(),
+            (1_000_000, 1_000_000),
+        );
+        let fees = TransferFees::new(HashMap::from([(taxed, Ratio::new(1, 100))]));
+        assert!(fees.fee_on_transfer(&taxed));
+        assert!(!fees.fee_on_transfer(&regular));
+
+        // Without transfer fees the result is unchanged.
+        assert_eq!(
+            pool.get_amount_out_net_of_transfer_fees(taxed, 10_000.into(), &Default::default()),
+            Some((9_871.into(), regular))
+        );
+        // Only 9_900 of the sold tokens reach the pool.
+        assert_eq!(
+            pool.get_amount_out_net_of_transfer_fees(taxed, 10_000.into(), &fees),
+            pool.get_amount_out(taxed, 9_900.into())
+        );
+        assert_eq!(
+            pool.get_amount_out_net_of_transfer_fees(taxed, 10_000.into(), &fees),
+            Some((9_773.into(), regular))
+        );
+        // 1% of the bought tokens are withheld on the way out of the pool.
+        assert_eq!(
+            pool.get_amount_out_net_of_transfer_fees(regular, 10_000.into(), &fees),
+            Some((9_772.into(), taxed))
+        );
+    }
+
     #[test]
     fn test_get_amounts_out() {
         let sell_token = H160::from_low_u64_be(1);
@@ -475,6 +836,105 @@ mod tests {
         );
     }
 
+    #[test]
+    fn amounts_depend_on_fee_tier() {
+        let sell_token = H160::from_low_u64_be(1);
+        let buy_token = H160::from_low_u64_be(2);
+        let tokens = TokenPair::new(sell_token, buy_token).unwrap();
+        let reserves = (1_000_000, 1_000_000);
+
+        let default = Pool::uniswap(H160::from_low_u64_be(1), tokens, reserves);
+        let pool_30 = Pool::with_fee_bps(H160::from_low_u64_be(1), tokens, reserves, 30);
+        let pool_25 = Pool::with_fee_bps(H160::from_low_u64_be(1), tokens, reserves, 25);
+        assert_eq!(default, pool_30);
+
+        assert_eq!(
+            pool_30.get_amount_out(sell_token, 10_000.into()),
+            Some((9_871.into(), buy_token))
+        );
+        assert_eq!(
+            pool_25.get_amount_out(sell_token, 10_000.into()),
+            Some((9_876.into(), buy_token))
+        );
+        assert_eq!(
+            pool_30.get_amount_in(buy_token, 10_000.into()),
+            Some((10_132.into(), sell_token))
+        );
+        assert_eq!(
+            pool_25.get_amount_in(buy_token, 10_000.into()),
+            Some((10_127.into(), sell_token))
+        );
+    }
+
+    #[test]
+    fn amount_in_for_reserves() {
+        let amount_in = |amount_out: u128, reserves: (u128, u128), fee_bps| {
+            get_amount_in(
+                amount_out.into(),
+                reserves.0.into(),
+                reserves.1.into(),
+                fee_bps,
+            )
+        };
+        let eth = 10u128.pow(18);
+
+        assert_eq!(amount_in(100, (1000, 1000), 30), Some(112.into()));
+        assert_eq!(
+            amount_in(eth, (100 * eth, 50 * eth), 30),
+            Some(2_046_957_198_124_987_207u128.into())
+        );
+        assert_eq!(
+            amount_in(eth, (100 * eth, 50 * eth), 25),
+            Some(2_045_931_154_416_653_880u128.into())
+        );
+
+        // Buying all but one unit of the reserve is possible, buying all of it
+        // or more is not.
+        assert_eq!(amount_in(999, (1000, 1000), 30), Some(1_002_007.into()));
+        assert_eq!(amount_in(1000, (1000, 1000), 30), None);
+        assert_eq!(amount_in(1001, (1000, 1000), 30), None);
+
+        assert_eq!(amount_in(0, (1000, 1000), 30), None);
+        assert_eq!(amount_in(100, (0, 1000), 30), None);
+    }
+
+    #[test]
+    fn amount_out_for_reserves() {
+        let amount_out = |amount_in: u128, reserves: (U256, U256)| {
+            get_amount_out(amount_in.into(), reserves.0, reserves.1, 30)
+        };
+        let eth = 10u128.pow(18);
+
+        // amount_in * 997 * reserve_out / (reserve_in * 1000 + amount_in * 997)
+        assert_eq!(amount_out(100, (1000.into(), 1000.into())), Some(90.into()));
+        assert_eq!(
+            amount_out(eth, ((100 * eth).into(), (50 * eth).into())),
+            Some(493_579_017_198_530_649u128.into())
+        );
+
+        // Empty reserves.
+        assert_eq!(amount_out(100, (0.into(), 1000.into())), None);
+        assert_eq!(amount_out(100, (1000.into(), 0.into())), None);
+        assert_eq!(amount_out(0, (1000.into(), 1000.into())), None);
+
+        // The intermediate product overflows a `U256` but the result doesn't.
+        assert_eq!(
+            amount_out(1000, (1000.into(), U256::MAX)),
+            Some(
+                U256::from_dec_str(
+                    "57809070089937028962093275940742035117531384432470526964115779296890030170763"
+                )
+                .unwrap()
+            )
+        );
+        // Reserves that can't be scaled by the fee denominator.
+        assert_eq!(amount_out(1000, (U256::MAX, U256::MAX)), None);
+        assert_eq!(
+            get_amount_out(U256::MAX, 1000.into(), 1000.into(), 30),
+            None
+        );
+    }
+
     #[test]
     fn computes_final_reserves() {
         assert_eq!(
@@ -494,16 +954,249 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn fetches_reserves_in_one_multicall() {
+        let pairs = [
+            TokenPair::new(H160([1; 20]), H160([2; 20])).unwrap(),
+            TokenPair::new(H160([1; 20]), H160([3; 20])).unwrap(),
+            TokenPair::new(H160([2; 20]), H160([3; 20])).unwrap(),
+        ];
+        let transport = MockTransport::new();
+        transport
+            .mock()
+            .expect_execute()
+            .times(1)
+            .returning(|method, _| {
+                assert_eq!(method, "eth_call");
+                Ok(multicall_response(&[Some((1, 2)), None, Some((3, 4))]))
+            });
+        let web3 = Web3::new(ethrpc::Web3Transport::new(transport));
+
+        let reserves = fetch_reserves_batched(
+            &web3,
+            &PairProvider::uniswap(H160([0xfa; 20])),
+            &pairs,
+            DEFAULT_RESERVES_BATCH_SIZE,
+            BlockId::Number(ethcontract::BlockNumber::Latest),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            reserves,
+            vec![
+                (pairs[0], Some((1, 2))),
+                (pairs[1], None),
+                (pairs[2], Some((3, 4))),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn skips_reserves_of_invalid_pairs() {
+        let pairs = [
+            TokenPair::new(H160([1; 20]), H160([2; 20])).unwrap(),
+            TokenPair::new(H160::zero(), H160([2; 20])).unwrap(),
+            TokenPair::new(H160([2; 20]), H160([3; 20])).unwrap(),
+        ];
+        let transport = MockTransport::new();
+        transport
+            .mock()
+            .expect_execute()
+            .times(1)
+            .returning(|_, _| Ok(multicall_response(&[Some((1, 2)), Some((3, 4))])));
+        let web3 = Web3::new(ethrpc::Web3Transport::new(transport));
+
+        let reserves = fetch_reserves_batched(
+            &web3,
+            &PairProvider::uniswap(H160([0xfa; 20])),
+            &pairs,
+            DEFAULT_RESERVES_BATCH_SIZE,
+            BlockId::Number(ethcontract::BlockNumber::Latest),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            reserves,
+            vec![
+                (pairs[0], Some((1, 2))),
+                (pairs[1], None),
+                (pairs[2], Some((3, 4))),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn splits_reserves_fetching_into_batches() {
+        let pairs = [
+            TokenPair::new(H160([1; 20]), H160([2; 20])).unwrap(),
+            TokenPair::new(H160([1; 20]), H160([3; 20])).unwrap(),
+            TokenPair::new(H160([2; 20]), H160([3; 20])).unwrap(),
+        ];
+        let transport = MockTransport::new();
+        let mut seq = mockall::Sequence::new();
+        transport
+            .mock()
+            .expect_execute()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| Ok(multicall_response(&[Some((1, 2)), Some((3, 4))])));
+        transport
+            .mock()
+            .expect_execute()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| Ok(multicall_response(&[Some((5, 6))])));
+        let web3 = Web3::new(ethrpc::Web3Transport::new(transport));
+
+        let reserves = fetch_reserves_batched(
+            &web3,
+            &PairProvider::uniswap(H160([0xfa; 20])),
+            &pairs,
+            2,
+            BlockId::Number(ethcontract::BlockNumber::Latest),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            reserves.into_iter().map(|(_, r)| r).collect::<Vec<_>>(),
+            vec![Some((1, 2)), Some((3, 4)), Some((5, 6))]
+        );
+    }
+
+    #[tokio::test]
+    async fn fetches_all_reserves_at_the_same_block() {
+        let pairs = [
+            TokenPair::new(H160([1; 20]), H160([2; 20])).unwrap(),
+            TokenPair::new(H160([1; 20]), H160([3; 20])).unwrap(),
+            TokenPair::new(H160([2; 20]), H160([3; 20])).unwrap(),
+            TokenPair::new(H160([2; 20]), H160([4; 20])).unwrap(),
+        ];
+        let transport = MockTransport::new();
+        let mut seq = mockall::Sequence::new();
+        transport
+            .mock()
+            .expect_execute()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|method, _| {
+                assert_eq!(method, "eth_blockNumber");
+                Ok(json!("0x2a"))
+            });
+        transport
+            .mock()
+            .expect_execute()
+            .times(2)
+            .in_sequence(&mut seq)
+            .returning(|method, params| {
+                assert_eq!(method, "eth_call");
+                assert_eq!(params[1], json!("0x2a"));
+                Ok(multicall_response(&[Some((1, 2)), Some((3, 4))]))
+            });
+        let web3 = Web3::new(ethrpc::Web3Transport::new(transport));
+
+        let reserves = fetch_reserves_at_block(
+            &web3,
+            &PairProvider::uniswap(H160([0xfa; 20])),
+            &pairs,
+            2,
+            BlockNumber::Latest,
+        )
+        .await
+        .unwrap();
+        assert!(reserves.iter().all(|(_, reserves)| reserves.is_some()));
+    }
+
+    #[tokio::test]
+    async fn default_pool_reader_is_a_liquidity_source() {
+        let pair = TokenPair::new(H160([1; 20]), H160([2; 20])).unwrap();
+        let transport = MockTransport::new();
+        transport
+            .mock()
+            .expect_execute()
+            .times(4)
+            .returning(move |method, params| {
+                assert_eq!(method, "eth_call");
+                let data = params[0]["data"].as_str().unwrap();
+                let result = if data.starts_with("0x0902f1ac") {
+                    ethabi::encode(&[
+                        Token::Uint(100.into()),
+                        Token::Uint(200.into()),
+                        Token::Uint(0.into()),
+                    ])
+                } else if data.starts_with("0x0dfe1681") {
+                    ethabi::encode(&[Token::Address(pair.get().0)])
+                } else {
+                    ethabi::encode(&[Token::Uint(1_000.into())])
+                };
+                Ok(json!(web3::types::Bytes(result)))
+            });
+        let web3 = Web3::new(ethrpc::Web3Transport::new(transport));
+
+        let sources: Vec<Box<dyn BaselineLiquiditySource>> = vec![Box::new(
+            DefaultPoolReader::new(web3, PairProvider::uniswap(H160([0xfa; 20]))),
+        )];
+        let reserves = sources[0]
+            .reserves(pair, BlockId::Number(BlockNumber::Latest))
+            .await
+            .unwrap();
+        assert_eq!(reserves, Some((100, 200)));
+    }
+
+    #[tokio::test]
+    async fn orients_reserves_by_pool_token0() {
+        let (buy_token, sell_token) = (H160([1; 20]), H160([2; 20]));
+        let pair = TokenPair::new(sell_token, buy_token).unwrap();
+        let transport = MockTransport::new();
+        transport
+            .mock()
+            .expect_execute()
+            .times(4)
+            .returning(move |method, params| {
+                assert_eq!(method, "eth_call");
+                let data = params[0]["data"].as_str().unwrap();
+                // The pool reports its reserves in the order (sell, buy).
+                let result = if data.starts_with("0x0902f1ac") {
+                    ethabi::encode(&[
+                        Token::Uint(100.into()),
+                        Token::Uint(200.into()),
+                        Token::Uint(0.into()),
+                    ])
+                } else if data.starts_with("0x0dfe1681") {
+                    ethabi::encode(&[Token::Address(sell_token)])
+                } else {
+                    ethabi::encode(&[Token::Uint(1_000.into())])
+                };
+                Ok(json!(web3::types::Bytes(result)))
+            });
+        let web3 = Web3::new(ethrpc::Web3Transport::new(transport));
+
+        let reader = DefaultPoolReader::new(web3, PairProvider::uniswap(H160([0xfa; 20])));
+        let pool = reader
+            .read_state(pair, BlockId::Number(BlockNumber::Latest))
+            .await
+            .unwrap()
+            .unwrap();
+        // The pair orders the buy token first, as it is the smaller address.
+        assert_eq!(pool.reserves, (200, 100));
+        let (sell_reserve, buy_reserve, other) = pool.get_relative_reserves(sell_token);
+        assert_eq!((sell_reserve, buy_reserve), (100.into(), 200.into()));
+        assert_eq!(other, buy_token);
+    }
+
     #[test]
     fn pool_fetcher_forwards_node_error() {
         let fetched_pool = FetchedPool {
             reserves: Err(testing_node_error()),
             pair: Default::default(),
+            pool_token0: Ok(H160::from_low_u64_be(0)),
             token0_balance: Ok(1.into()),
             token1_balance: Ok(1.into()),
         };
         let pool_address = Default::default();
-        assert!(handle_results(fetched_pool, pool_address).is_err());
+        assert!(handle_results(fetched_pool, pool_address, Ratio::new(3, 1000)).is_err());
     }
 
     #[test]
@@ -511,12 +1204,15 @@ mod tests {
         let fetched_pool = FetchedPool {
             reserves: Err(testing_contract_error()),
             pair: Default::default(),
+            pool_token0: Ok(H160::from_low_u64_be(0)),
             token0_balance: Ok(1.into()),
             token1_balance: Ok(1.into()),
         };
         let pool_address = Default::default();
-        assert!(handle_results(fetched_pool, pool_address)
-            .unwrap()
-            .is_none())
+        assert!(
+            handle_results(fetched_pool, pool_address, Ratio::new(3, 1000))
+                .unwrap()
+                .is_none()
+        )
     }
 }
@@ -1,17 +1,146 @@
+use anyhow::{Context, Result};
 use ethcontract::H160;
+use serde::Deserialize;
+use std::path::Path;
+
+/// Version of a Circles hub contract. Tokens are registered with hubs of
+/// different versions differently, so they have to be verified differently.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, strum::EnumString)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum HubVersion {
+    /// Circles v1, where every member has their own ERC20 token.
+    #[default]
+    V1,
+    /// Circles v2, where ERC20 tokens wrap the balance of a registered avatar.
+    V2,
+}
 
 pub struct CirclesConfig {
-    pub known_hub_addresses: Vec<H160>,
+    pub known_hubs: Vec<(H160, HubVersion)>,
+    /// The addresses of `known_hubs`, in the same order.
+    hubs: Vec<H160>,
+    /// Whether orders trading CRC tokens of two different hubs get matched.
+    /// Disabled by default, so that only tokens of the same hub are matched.
+    pub cross_hub: bool,
+}
+
+/// A hub as configured in a JSON file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HubEntry {
+    address: String,
+    #[serde(default)]
+    version: HubVersion,
 }
 
 impl CirclesConfig {
+    /// Creates a config where all hubs are v1 hubs.
     pub fn new(known_hubs: Vec<H160>) -> Self {
-        Self { known_hub_addresses: known_hubs }
+        Self::with_versions(
+            known_hubs
+                .into_iter()
+                .map(|hub| (hub, HubVersion::default()))
+                .collect(),
+        )
+    }
+
+    /// Creates a config with the given hubs. Hubs listed more than once are
+    /// only kept the first time, with a warning.
+    pub fn with_versions(known_hubs: Vec<(H160, HubVersion)>) -> Self {
+        let mut deduplicated: Vec<(H160, HubVersion)> = Vec::with_capacity(known_hubs.len());
+        for (hub, version) in known_hubs {
+            match deduplicated.iter().find(|(known, _)| *known == hub) {
+                Some((_, kept)) => {
+                    tracing::warn!(?hub, ?version, ?kept, "ignoring duplicate Circles hub");
+                }
+                None => deduplicated.push((hub, version)),
+            }
+        }
+        Self {
+            hubs: deduplicated.iter().map(|(hub, _)| *hub).collect(),
+            known_hubs: deduplicated,
+            cross_hub: false,
+        }
+    }
+
+    /// Enables matching across hubs.
+    pub fn with_cross_hub(mut self, cross_hub: bool) -> Self {
+        self.cross_hub = cross_hub;
+        self
+    }
+
+    /// Loads the hubs from a JSON file containing a list of entries like
+    /// `{"address": "0x..", "version": "v2"}`. The version defaults to v1.
+    pub fn from_json(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read Circles config {}", path.display()))?;
+        Self::from_json_str(&contents)
+            .with_context(|| format!("invalid Circles config {}", path.display()))
+    }
+
+    fn from_json_str(contents: &str) -> Result<Self> {
+        let entries: Vec<HubEntry> = serde_json::from_str(contents)?;
+        let known_hubs = entries
+            .into_iter()
+            .map(|entry| Ok((parse_hub_address(&entry.address)?, entry.version)))
+            .collect::<Result<_>>()?;
+        Ok(Self::with_versions(known_hubs))
+    }
+
+    /// Loads the hubs from the `{prefix}_HUBS` environment variable, a comma
+    /// separated list of hub addresses, each optionally followed by `:v1` or
+    /// `:v2`. A missing or empty variable yields a config without hubs.
+    pub fn from_env(prefix: &str) -> Result<Self> {
+        let name = format!("{prefix}_HUBS");
+        let value = match std::env::var(&name) {
+            Ok(value) => value,
+            Err(std::env::VarError::NotPresent) => return Ok(Self::with_versions(vec![])),
+            Err(err) => return Err(err).with_context(|| format!("failed to read {name}")),
+        };
+        let known_hubs = value
+            .split(',')
+            .map(str::trim)
+            .filter(|hub| !hub.is_empty())
+            .map(|hub| {
+                let (address, version) = match hub.split_once(':') {
+                    Some((address, version)) => (
+                        address,
+                        version
+                            .parse()
+                            .with_context(|| format!("invalid hub version {version:?}"))?,
+                    ),
+                    None => (hub, HubVersion::default()),
+                };
+                Ok((parse_hub_address(address)?, version))
+            })
+            .collect::<Result<_>>()
+            .with_context(|| format!("invalid {name}"))?;
+        Ok(Self::with_versions(known_hubs))
+    }
+
+    /// The addresses of the known hubs, in the configured order.
+    pub fn hubs(&self) -> &[H160] {
+        &self.hubs
     }
 
     pub fn is_known_hub(&self, hub_addr: H160) -> bool {
-        self.known_hub_addresses.contains(&hub_addr)
+        self.hub_version(hub_addr).is_some()
     }
+
+    /// Returns the version of the hub, or `None` if it isn't a known hub.
+    pub fn hub_version(&self, hub_addr: H160) -> Option<HubVersion> {
+        self.known_hubs
+            .iter()
+            .find(|(hub, _)| *hub == hub_addr)
+            .map(|(_, version)| *version)
+    }
+}
+
+fn parse_hub_address(address: &str) -> Result<H160> {
+    address
+        .parse()
+        .with_context(|| format!("invalid hub address {address:?}"))
 }
 
 #[cfg(test)]
@@ -29,4 +158,88 @@ mod tests {
         let unknown: H160 = "0x3333333333333333333333333333333333333333".parse().unwrap();
         assert!(!config.is_known_hub(unknown));
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_hub_version() {
+        let hub1: H160 = "0x1111111111111111111111111111111111111111".parse().unwrap();
+        let hub2: H160 = "0x2222222222222222222222222222222222222222".parse().unwrap();
+        let unknown: H160 = "0x3333333333333333333333333333333333333333".parse().unwrap();
+
+        let config = CirclesConfig::new(vec![hub1]);
+        assert_eq!(config.hub_version(hub1), Some(HubVersion::V1));
+
+        let config =
+            CirclesConfig::with_versions(vec![(hub1, HubVersion::V1), (hub2, HubVersion::V2)]);
+        assert_eq!(config.hub_version(hub1), Some(HubVersion::V1));
+        assert_eq!(config.hub_version(hub2), Some(HubVersion::V2));
+        assert_eq!(config.hub_version(unknown), None);
+    }
+
+    #[test]
+    fn test_deduplicates_hubs() {
+        let hub1: H160 = "0x1111111111111111111111111111111111111111".parse().unwrap();
+        let hub2: H160 = "0x2222222222222222222222222222222222222222".parse().unwrap();
+
+        let config = CirclesConfig::new(vec![hub1, hub2, hub1]);
+        assert_eq!(config.hubs(), [hub1, hub2]);
+
+        // The first entry of a hub wins.
+        let config =
+            CirclesConfig::with_versions(vec![(hub1, HubVersion::V2), (hub1, HubVersion::V1)]);
+        assert_eq!(config.known_hubs, vec![(hub1, HubVersion::V2)]);
+    }
+
+    #[test]
+    fn test_from_json() {
+        let hub1: H160 = "0x1111111111111111111111111111111111111111".parse().unwrap();
+        let hub2: H160 = "0x2222222222222222222222222222222222222222".parse().unwrap();
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(
+            file.path(),
+            r#"[
+                {"address": "0x1111111111111111111111111111111111111111"},
+                {"address": "0x2222222222222222222222222222222222222222", "version": "v2"}
+            ]"#,
+        )
+        .unwrap();
+
+        let config = CirclesConfig::from_json(file.path()).unwrap();
+        assert_eq!(
+            config.known_hubs,
+            vec![(hub1, HubVersion::V1), (hub2, HubVersion::V2)]
+        );
+    }
+
+    #[test]
+    fn test_from_json_rejects_malformed_address() {
+        let err = CirclesConfig::from_json_str(r#"[{"address": "0x1234"}]"#).unwrap_err();
+        assert!(format!("{err:#}").contains("invalid hub address \"0x1234\""));
+        let unknown_version = r#"[
+            {"address": "0x1111111111111111111111111111111111111111", "version": "v3"}
+        ]"#;
+        assert!(CirclesConfig::from_json_str(unknown_version).is_err());
+        assert!(CirclesConfig::from_json(Path::new("/nonexistent/circles.json")).is_err());
+    }
+
+    #[test]
+    fn test_from_env() {
+        let hub1: H160 = "0x1111111111111111111111111111111111111111".parse().unwrap();
+        let hub2: H160 = "0x2222222222222222222222222222222222222222".parse().unwrap();
+        std::env::set_var(
+            "CIRCLES_TEST_VALID_HUBS",
+            "0x1111111111111111111111111111111111111111, \
+             0x2222222222222222222222222222222222222222:V2",
+        );
+        let config = CirclesConfig::from_env("CIRCLES_TEST_VALID").unwrap();
+        assert_eq!(
+            config.known_hubs,
+            vec![(hub1, HubVersion::V1), (hub2, HubVersion::V2)]
+        );
+
+        std::env::set_var("CIRCLES_TEST_INVALID_HUBS", "0x1111,0x2222");
+        assert!(CirclesConfig::from_env("CIRCLES_TEST_INVALID").is_err());
+
+        let config = CirclesConfig::from_env("CIRCLES_TEST_MISSING").unwrap();
+        assert!(config.known_hubs.is_empty());
+    }
+}
@@ -4,3 +4,6 @@ pub mod liquidity_collector;
 pub mod settlement;
 pub mod solver;
 pub mod config;
+
+#[cfg(test)]
+mod test_util;
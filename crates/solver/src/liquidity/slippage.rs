@@ -3,11 +3,19 @@
 use {
     super::AmmOrderExecution,
     anyhow::{Context as _, Result},
-    ethcontract::U256,
-    num::{BigInt, BigRational, CheckedDiv, Integer as _, ToPrimitive as _},
+    ethcontract::{H160, U256},
+    model::order::OrderKind,
+    num::{BigInt, BigRational, CheckedDiv, Integer as _, One as _, ToPrimitive as _, Zero as _},
+    number::serialization::HexOrDecimalU256,
     once_cell::sync::OnceCell,
-    shared::{external_prices::ExternalPrices, http_solver::model::TokenAmount},
-    std::{borrow::Cow, cmp},
+    serde::{Deserialize, Serialize},
+    serde_with::{serde_as, DisplayFromStr},
+    shared::{
+        external_prices::ExternalPrices,
+        http_solver::model::TokenAmount,
+        token_info::TokenInfo,
+    },
+    std::{borrow::Cow, cmp, collections::HashMap},
 };
 
 /// Constant maximum slippage of 10 BPS (0.1%) to use for on-chain liquidity.
@@ -16,20 +24,74 @@ const DEFAULT_MAX_SLIPPAGE_BPS: u32 = 10;
 /// Basis points in 100%.
 const BPS_BASE: u32 = 10000;
 
+/// Trade values above one billion ether (in wei), several times the total
+/// ether supply, can only be caused by a broken price, e.g. one expressed in
+/// the wrong units.
+const MAX_SANE_NATIVE_VALUE: u128 = 10_u128.pow(27);
+
+/// Decimals of the native token that absolute slippage limits are
+/// denominated in.
+const NATIVE_DECIMALS: u8 = 18;
+
 /// A per-auction context for computing slippage.
 pub struct SlippageContext<'a> {
     prices: &'a ExternalPrices,
-    calculator: &'a SlippageCalculator,
+    calculator: Cow<'a, SlippageCalculator>,
+    /// Decimals and symbols of the auction tokens, for logging amounts in
+    /// whole tokens.
+    token_infos: Option<&'a HashMap<H160, TokenInfo>>,
 }
 
-impl SlippageContext<'_> {
+impl<'a> SlippageContext<'a> {
+    /// Logs amounts of the tokens in `token_infos` in whole tokens with their
+    /// symbol instead of in base units.
+    pub fn with_token_infos(mut self, token_infos: &'a HashMap<H160, TokenInfo>) -> Self {
+        self.token_infos = Some(token_infos);
+        self
+    }
+
+    /// Computes the limit amounts of a swap with the auction prices, see
+    /// `SlippageCalculator::bounds`.
+    pub fn bounds(&self, sell: &TokenAmount, buy: &TokenAmount) -> Result<SwapBounds> {
+        let bounds = self.calculator.bounds(sell, buy, self.prices)?;
+        tracing::debug!(
+            sell = %self.display(sell),
+            max_sell = %self.display(&TokenAmount::new(sell.token, bounds.max_sell)),
+            buy = %self.display(buy),
+            min_buy = %self.display(&TokenAmount::new(buy.token, bounds.min_buy)),
+            "computed swap bounds",
+        );
+        Ok(bounds)
+    }
+
+    /// Formats an amount for logging, in whole tokens if the decimals of the
+    /// token are known and in base units otherwise.
+    fn display(&self, amount: &TokenAmount) -> String {
+        let info = self.token_infos.and_then(|infos| infos.get(&amount.token));
+        let Some(decimals) = info.and_then(|info| info.decimals) else {
+            return amount.amount.to_string();
+        };
+        let value = format_amount(amount.amount, decimals);
+        match info.and_then(|info| info.symbol.as_deref()) {
+            Some(symbol) => format!("{value} {symbol}"),
+            None => value,
+        }
+    }
+
     /// Applies slippage to the specified AMM execution.
+    ///
+    /// Only the sell side tolerance applies, to `input_max`. AMM executions
+    /// are settled as exact output swaps, so `output` is the amount the
+    /// settlement needs and lowering it by the buy side tolerance would leave
+    /// the orders short. Use `SlippageCalculator::bounds` for swaps whose
+    /// bought amount may vary.
     pub fn apply_to_amm_execution(
         &self,
         mut execution: AmmOrderExecution,
     ) -> Result<AmmOrderExecution> {
         let relative_ratio = |token_amount: &TokenAmount| -> Result<Cow<BigRational>> {
             let (relative, _) = self.calculator.compute(
+                &token_amount.token,
                 self.prices.price(&token_amount.token),
                 number::conversions::u256_to_big_int(&token_amount.amount),
             )?;
@@ -63,7 +125,7 @@ impl SlippageContext<'_> {
             Cow::Borrowed(&self.calculator.relative)
         };
 
-        let absolute = absolute_slippage_amount(
+        let absolute = self.calculator.rounding.absolute_slippage_amount(
             &relative,
             &number::conversions::u256_to_big_int(&execution.input_max.amount),
         );
@@ -72,7 +134,7 @@ impl SlippageContext<'_> {
         if *relative < self.calculator.relative {
             tracing::debug!(
                 input_token = ?execution.input_max.token,
-                input_amount = ?execution.input_max.token,
+                input_amount = %self.display(&execution.input_max),
                 relative = ?slippage.relative,
                 absolute = %slippage.absolute,
                 "capping AMM slippage to respect maximum absolute amount",
@@ -80,6 +142,11 @@ impl SlippageContext<'_> {
         }
 
         execution.input_max.amount = slippage.add_to_amount(execution.input_max.amount);
+        tracing::debug!(
+            input_max = %self.display(&execution.input_max),
+            output = %self.display(&execution.output),
+            "applied AMM slippage",
+        );
         Ok(execution)
     }
 }
@@ -88,47 +155,396 @@ impl Default for SlippageContext<'static> {
     fn default() -> Self {
         static CONTEXT: OnceCell<(ExternalPrices, SlippageCalculator)> = OnceCell::new();
         let (prices, calculator) = CONTEXT.get_or_init(Default::default);
-        Self { prices, calculator }
+        Self {
+            prices,
+            calculator: Cow::Borrowed(calculator),
+            token_infos: None,
+        }
     }
 }
 
 /// Component used for computing negative slippage limits for internal solvers.
-#[derive(Clone, Debug)]
+///
+/// Serializes relative factors as exact `numerator/denominator` strings and
+/// absolute amounts as decimal strings, so that a calculator survives a round
+/// trip unchanged.
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct SlippageCalculator {
     /// The maximum relative slippage factor.
+    #[serde_as(as = "DisplayFromStr")]
     pub relative: BigRational,
+    /// The maximum relative slippage factor for the buy side of a swap, when
+    /// it differs from the sell side. Defaults to `relative`.
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub buy_relative: Option<BigRational>,
+    /// The minimum relative slippage factor, for venues that revert
+    /// transactions with less slippage tolerance. Applies after the absolute
+    /// caps, so it takes precedence over them.
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub min_relative: Option<BigRational>,
     /// The maximum absolute slippage in native tokens.
+    #[serde_as(as = "Option<DisplayFromStr>")]
     pub absolute: Option<BigInt>,
+    /// The maximum absolute slippage in a USD stable token, converted into
+    /// native tokens with the auction prices. Replaces `absolute`.
+    pub usd_absolute: Option<UsdAbsolute>,
+    /// The maximum absolute slippage for specific tokens, denominated in the
+    /// token itself. These caps take precedence over `absolute` and do not
+    /// require a price for the token.
+    #[serde_as(as = "HashMap<_, DisplayFromStr>")]
+    pub token_absolute: HashMap<H160, BigInt>,
+    /// Relative slippage factors for specific tokens, e.g. rebasing or
+    /// illiquid ones. They replace `relative` and `buy_relative` for swaps of
+    /// these tokens, while the absolute caps still apply.
+    #[serde_as(as = "HashMap<_, DisplayFromStr>")]
+    pub token_relative: HashMap<H160, BigRational>,
+    /// How fractional absolute slippage amounts are rounded.
+    pub rounding: RoundingMode,
+}
+
+/// An absolute slippage cap denominated in a USD stable token.
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsdAbsolute {
+    /// The cap in units of `token`.
+    #[serde_as(as = "DisplayFromStr")]
+    pub amount: BigInt,
+    /// The stable token the cap is denominated in, e.g. USDC.
+    pub token: H160,
+}
+
+/// Rounding mode for converting fractional slippage amounts into token
+/// amounts.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RoundingMode {
+    /// Round towards zero.
+    Floor,
+    /// Round away from zero, so that slippage amounts are never understated.
+    #[default]
+    Ceil,
+    /// Round to the nearest integer, with ties going to the even one.
+    HalfEven,
+}
+
+/// Errors that can occur when configuring slippage limits.
+#[derive(Debug, thiserror::Error)]
+pub enum SlippageError {
+    #[error("relative slippage {value} is outside of the range [0, 1]")]
+    RelativeOutOfRange { value: BigRational },
+    #[error("minimum relative slippage {min} exceeds the relative slippage {relative}")]
+    MinAboveRelative {
+        min: BigRational,
+        relative: BigRational,
+    },
+}
+
+/// The absolute slippage for an amount does not fit into a `U256`.
+#[derive(Debug, thiserror::Error)]
+#[error("absolute slippage of {relative} for amount {amount} overflows")]
+pub struct SlippageOverflow {
+    pub relative: BigRational,
+    pub amount: U256,
 }
 
 impl SlippageCalculator {
+    /// Creates a new slippage calculator, verifying that the relative slippage
+    /// factor is within `[0, 1]`.
+    pub fn new(relative: BigRational, absolute: Option<U256>) -> Result<Self, SlippageError> {
+        if relative < BigRational::zero() || relative > BigRational::one() {
+            return Err(SlippageError::RelativeOutOfRange { value: relative });
+        }
+        Ok(Self {
+            relative,
+            absolute: absolute.map(|value| number::conversions::u256_to_big_int(&value)),
+            usd_absolute: None,
+            token_absolute: Default::default(),
+            token_relative: Default::default(),
+            buy_relative: None,
+            min_relative: None,
+            rounding: Default::default(),
+        })
+    }
+
+    /// Returns a builder for calculators using several of the optional
+    /// settings. Unlike `new`, the builder also validates the buy side factor.
+    pub fn builder() -> SlippageCalculatorBuilder {
+        SlippageCalculatorBuilder(Self::from_bps(DEFAULT_MAX_SLIPPAGE_BPS, None))
+    }
+
     pub fn from_bps(relative_bps: u32, absolute: Option<U256>) -> Self {
         Self {
             relative: BigRational::new(relative_bps.into(), BPS_BASE.into()),
             absolute: absolute.map(|value| number::conversions::u256_to_big_int(&value)),
+            usd_absolute: None,
+            token_absolute: Default::default(),
+            token_relative: Default::default(),
+            buy_relative: None,
+            min_relative: None,
+            rounding: Default::default(),
         }
     }
 
+    /// Like `from_bps` but rejects relative slippage above 10000 basis points,
+    /// i.e. 100%. Prefer this for operator provided configuration.
+    pub fn from_basis_points(
+        relative_bps: u32,
+        absolute: Option<U256>,
+    ) -> Result<Self, SlippageError> {
+        Self::new(
+            BigRational::new(relative_bps.into(), BPS_BASE.into()),
+            absolute,
+        )
+    }
+
+    /// Caps the absolute slippage for `token` to `amount` units of that token.
+    /// This allows capping slippage for tokens without a native price.
+    pub fn with_token_absolute(mut self, token: H160, amount: U256) -> Self {
+        self.token_absolute
+            .insert(token, number::conversions::u256_to_big_int(&amount));
+        self
+    }
+
+    /// Caps the absolute slippage to `usd_amount` units of the USD stable token
+    /// `usd_token` instead of a native token amount. The cap is converted with
+    /// the auction prices when creating a `SlippageContext`.
+    pub fn with_usd_absolute(mut self, usd_amount: U256, usd_token: H160) -> Self {
+        self.absolute = None;
+        self.usd_absolute = Some(UsdAbsolute {
+            amount: number::conversions::u256_to_big_int(&usd_amount),
+            token: usd_token,
+        });
+        self
+    }
+
+    /// Uses `relative_bps` as the relative slippage for swaps of `token` on
+    /// both sides, instead of the global factors.
+    pub fn with_token_relative_bps(mut self, token: H160, relative_bps: u32) -> Self {
+        self.token_relative.insert(
+            token,
+            BigRational::new(relative_bps.into(), BPS_BASE.into()),
+        );
+        self
+    }
+
+    /// Uses a separate relative slippage factor for the buy side of swaps.
+    pub fn with_buy_relative_bps(mut self, relative_bps: u32) -> Self {
+        self.buy_relative = Some(BigRational::new(relative_bps.into(), BPS_BASE.into()));
+        self
+    }
+
+    /// Uses the specified rounding mode for absolute slippage amounts.
+    pub fn with_rounding(mut self, rounding: RoundingMode) -> Self {
+        self.rounding = rounding;
+        self
+    }
+
+    /// Returns a calculator for routes through `hops` pools, where the
+    /// slippage of every hop compounds, i.e. the relative factors become
+    /// `1 - (1 - s)^hops`, bounded to `[0, 1]`. Absolute caps apply to the
+    /// route as a whole and are kept as they are.
+    pub fn compound(&self, hops: usize) -> Self {
+        let compound = |relative: &BigRational| {
+            let relative = relative
+                .clone()
+                .clamp(BigRational::zero(), BigRational::one());
+            BigRational::one() - num::pow(BigRational::one() - relative, hops)
+        };
+        Self {
+            relative: compound(&self.relative),
+            buy_relative: self.buy_relative.as_ref().map(compound),
+            token_relative: self
+                .token_relative
+                .iter()
+                .map(|(token, relative)| (*token, compound(relative)))
+                .collect(),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the maximum relative slippage factor for the buy side of a swap.
+    pub fn buy_relative(&self) -> &BigRational {
+        self.buy_relative.as_ref().unwrap_or(&self.relative)
+    }
+
+    /// Returns the uncapped absolute slippage for `amount` on the sell side of
+    /// a swap. Unlike the saturating arithmetic of `SlippageAmount`, this
+    /// fails if the result does not fit into a `U256`, which allows validation
+    /// code to reject nonsensical slippage configurations.
+    pub fn checked_abs(&self, amount: U256) -> Result<U256, SlippageOverflow> {
+        let absolute = self.rounding.absolute_slippage_amount(
+            &self.relative,
+            &number::conversions::u256_to_big_int(&amount),
+        );
+        number::conversions::big_int_to_u256(&absolute).map_err(|_| SlippageOverflow {
+            relative: self.relative.clone(),
+            amount,
+        })
+    }
+
     pub fn context<'a>(&'a self, prices: &'a ExternalPrices) -> SlippageContext<'a> {
         SlippageContext {
             prices,
-            calculator: self,
+            calculator: self.for_prices(prices),
+            token_infos: None,
         }
     }
 
-    /// Computes the capped slippage amount for the specified token price and
-    /// amount.
+    /// Returns a calculator where the USD denominated absolute cap, if any, is
+    /// converted into a native one with the price of the USD token. Without a
+    /// price for it, only the relative limits apply.
+    pub fn for_prices(&self, prices: &ExternalPrices) -> Cow<Self> {
+        let Some(usd_absolute) = &self.usd_absolute else {
+            return Cow::Borrowed(self);
+        };
+        let absolute = match prices.price(&usd_absolute.token) {
+            Some(price) => {
+                let absolute =
+                    (price * BigRational::from_integer(usd_absolute.amount.clone())).to_integer();
+                if let Ok(native) = number::conversions::big_int_to_u256(&absolute) {
+                    tracing::debug!(
+                        token = ?usd_absolute.token,
+                        native = %format_amount(native, NATIVE_DECIMALS),
+                        "converted USD absolute slippage limit",
+                    );
+                }
+                Some(absolute)
+            }
+            None => {
+                tracing::warn!(
+                    token = ?usd_absolute.token,
+                    "missing USD token price; ignoring absolute slippage limit"
+                );
+                None
+            }
+        };
+        Cow::Owned(Self {
+            absolute,
+            usd_absolute: None,
+            ..self.clone()
+        })
+    }
+
+    /// Computes the capped slippage amount for the specified token, price and
+    /// amount on the sell side of a swap.
     pub fn compute(
         &self,
+        token: &H160,
         price: Option<&BigRational>,
         amount: BigInt,
     ) -> Result<(Cow<BigRational>, BigInt)> {
-        let relative = if let Some(max_absolute_native_token) = self.absolute.clone() {
+        let relative = self.token_relative.get(token).unwrap_or(&self.relative);
+        self.compute_with_relative(relative, token, price, amount)
+    }
+
+    /// Computes the capped slippage amount for the specified token, price and
+    /// amount on the buy side of a swap.
+    pub fn compute_buy(
+        &self,
+        token: &H160,
+        price: Option<&BigRational>,
+        amount: BigInt,
+    ) -> Result<(Cow<BigRational>, BigInt)> {
+        let relative = self
+            .token_relative
+            .get(token)
+            .unwrap_or_else(|| self.buy_relative());
+        self.compute_with_relative(relative, token, price, amount)
+    }
+
+    /// Computes the limit amounts of a swap of `sell` for `buy`: the maximum
+    /// amount sold, with the sell side slippage added, and the minimum amount
+    /// bought, with the buy side slippage subtracted.
+    pub fn bounds(
+        &self,
+        sell: &TokenAmount,
+        buy: &TokenAmount,
+        prices: &ExternalPrices,
+    ) -> Result<SwapBounds> {
+        let calculator = self.for_prices(prices);
+        let (relative, absolute) = calculator.compute(
+            &sell.token,
+            prices.price(&sell.token),
+            number::conversions::u256_to_big_int(&sell.amount),
+        )?;
+        let sell_slippage = SlippageAmount::from_num(&relative, &absolute)?;
+        let (relative, absolute) = calculator.compute_buy(
+            &buy.token,
+            prices.price(&buy.token),
+            number::conversions::u256_to_big_int(&buy.amount),
+        )?;
+        let buy_slippage = SlippageAmount::from_num(&relative, &absolute)?;
+        Ok(SwapBounds {
+            max_sell: sell_slippage.add_to_amount(sell.amount),
+            min_buy: buy_slippage.sub_from_amount(buy.amount),
+        })
+    }
+
+    /// Computes the capped slippage amount for the sell side of a swap,
+    /// widening the relative tolerance for trades whose value is small
+    /// compared to the gas needed to execute them:
+    ///
+    /// `relative' = min(relative + gas_cost / (amount * price), 1)`
+    ///
+    /// where `gas_cost` is denominated in native token. The widened tolerance
+    /// is still capped by the configured absolute slippage limits.
+    pub fn compute_with_gas(
+        &self,
+        token: &H160,
+        price: Option<&BigRational>,
+        amount: BigInt,
+        gas_cost: U256,
+    ) -> Result<(BigRational, BigInt)> {
+        let price = price.context("missing token price")?;
+        let value = price * BigRational::from_integer(amount.clone());
+        let gas_cost = BigRational::from_integer(number::conversions::u256_to_big_int(&gas_cost));
+        let gas_relative = gas_cost
+            .checked_div(&value)
+            .context("trade value is zero")?;
+        let relative = self.token_relative.get(token).unwrap_or(&self.relative);
+        let relative = cmp::min(relative + gas_relative, BigRational::one());
+
+        let (relative, absolute) =
+            self.compute_with_relative(&relative, token, Some(price), amount)?;
+        Ok((relative.into_owned(), absolute))
+    }
+
+    fn compute_with_relative<'a>(
+        &'a self,
+        relative: &'a BigRational,
+        token: &H160,
+        price: Option<&BigRational>,
+        amount: BigInt,
+    ) -> Result<(Cow<'a, BigRational>, BigInt)> {
+        let max_absolute_slippage = if let Some(max_absolute_token) = self.token_absolute.get(token)
+        {
+            Some(BigRational::from_integer(max_absolute_token.clone()))
+        } else if let Some(max_absolute_native_token) = self.absolute.clone() {
             let price = price.context("missing token price")?;
-            let max_absolute_slippage = BigRational::new(max_absolute_native_token, 1.into())
-                .checked_div(price)
-                .context("price is zero")?;
+            let value = price * BigRational::from_integer(amount.clone());
+            if value > BigRational::from_integer(MAX_SANE_NATIVE_VALUE.into()) {
+                tracing::warn!(
+                    ?token,
+                    %price,
+                    %amount,
+                    "implausible trade value; ignoring absolute slippage limit"
+                );
+                None
+            } else {
+                Some(
+                    BigRational::new(max_absolute_native_token, 1.into())
+                        .checked_div(price)
+                        .context("price is zero")?,
+                )
+            }
+        } else {
+            None
+        };
 
+        let relative = if let Some(max_absolute_slippage) = max_absolute_slippage {
             let amount = BigRational::new(amount.clone(), 1.into());
 
             let max_relative_slippage_respecting_absolute_limit = max_absolute_slippage
@@ -137,17 +553,120 @@ impl SlippageCalculator {
 
             cmp::min(
                 Cow::Owned(max_relative_slippage_respecting_absolute_limit),
-                Cow::Borrowed(&self.relative),
+                Cow::Borrowed(relative),
             )
         } else {
-            Cow::Borrowed(&self.relative)
+            Cow::Borrowed(relative)
+        };
+        let relative = match &self.min_relative {
+            Some(min_relative) if *relative < *min_relative => Cow::Borrowed(min_relative),
+            _ => relative,
         };
-        let absolute = absolute_slippage_amount(&relative, &amount);
+        let absolute = self.rounding.absolute_slippage_amount(&relative, &amount);
 
         Ok((relative, absolute))
     }
 }
 
+/// Builder for a `SlippageCalculator`, starting with the default settings.
+#[derive(Clone, Debug)]
+pub struct SlippageCalculatorBuilder(SlippageCalculator);
+
+impl SlippageCalculatorBuilder {
+    pub fn relative(mut self, relative: BigRational) -> Self {
+        self.0.relative = relative;
+        self
+    }
+
+    pub fn relative_bps(self, relative_bps: u32) -> Self {
+        self.relative(BigRational::new(relative_bps.into(), BPS_BASE.into()))
+    }
+
+    pub fn buy_relative(mut self, relative: BigRational) -> Self {
+        self.0.buy_relative = Some(relative);
+        self
+    }
+
+    pub fn buy_relative_bps(self, relative_bps: u32) -> Self {
+        self.buy_relative(BigRational::new(relative_bps.into(), BPS_BASE.into()))
+    }
+
+    pub fn absolute(mut self, absolute: U256) -> Self {
+        self.0.absolute = Some(number::conversions::u256_to_big_int(&absolute));
+        self
+    }
+
+    pub fn usd_absolute(mut self, amount: U256, token: H160) -> Self {
+        self.0 = self.0.with_usd_absolute(amount, token);
+        self
+    }
+
+    pub fn token_absolute(mut self, token: H160, amount: U256) -> Self {
+        self.0 = self.0.with_token_absolute(token, amount);
+        self
+    }
+
+    pub fn token_relative(mut self, token: H160, relative: BigRational) -> Self {
+        self.0.token_relative.insert(token, relative);
+        self
+    }
+
+    pub fn token_relative_bps(self, token: H160, relative_bps: u32) -> Self {
+        self.token_relative(
+            token,
+            BigRational::new(relative_bps.into(), BPS_BASE.into()),
+        )
+    }
+
+    pub fn min_relative(mut self, min_relative: BigRational) -> Self {
+        self.0.min_relative = Some(min_relative);
+        self
+    }
+
+    pub fn min_relative_bps(self, min_relative_bps: u32) -> Self {
+        self.min_relative(BigRational::new(min_relative_bps.into(), BPS_BASE.into()))
+    }
+
+    pub fn rounding(mut self, rounding: RoundingMode) -> Self {
+        self.0.rounding = rounding;
+        self
+    }
+
+    /// Verifies that the relative slippage factors are within `[0, 1]` and
+    /// that the minimum doesn't exceed them.
+    pub fn build(self) -> Result<SlippageCalculator, SlippageError> {
+        for relative in [
+            Some(&self.0.relative),
+            self.0.buy_relative.as_ref(),
+            self.0.min_relative.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .chain(self.0.token_relative.values())
+        {
+            if *relative < BigRational::zero() || *relative > BigRational::one() {
+                return Err(SlippageError::RelativeOutOfRange {
+                    value: relative.clone(),
+                });
+            }
+        }
+        if let Some(min) = &self.0.min_relative {
+            let relatives = [&self.0.relative, self.0.buy_relative()]
+                .into_iter()
+                .chain(self.0.token_relative.values());
+            for relative in relatives {
+                if min > relative {
+                    return Err(SlippageError::MinAboveRelative {
+                        min: min.clone(),
+                        relative: relative.clone(),
+                    });
+                }
+            }
+        }
+        Ok(self.0)
+    }
+}
+
 impl Default for SlippageCalculator {
     fn default() -> Self {
         Self::from_bps(DEFAULT_MAX_SLIPPAGE_BPS, None)
@@ -156,35 +675,134 @@ impl Default for SlippageCalculator {
 
 /// A result of a slippage computation containing both relative and absolute
 /// slippage amounts.
-#[derive(Clone, Copy, Debug, Default)]
+#[serde_as]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct SlippageAmount {
     /// The relative slippage amount factor.
-    relative: f64,
+    #[serde_as(as = "DisplayFromStr")]
+    relative: BigRational,
     /// The absolute slippage amount in the token it was computed for.
+    #[serde_as(as = "HexOrDecimalU256")]
     absolute: U256,
 }
 
 impl SlippageAmount {
     /// Computes a slippage amount from arbitrary precision `num` values.
     fn from_num(relative: &BigRational, absolute: &BigInt) -> Result<Self> {
-        let relative = relative
-            .to_f64()
-            .context("relative slippage ratio is not a number")?;
         let absolute = number::conversions::big_int_to_u256(absolute)?;
 
-        Ok(Self { relative, absolute })
+        Ok(Self {
+            relative: relative.clone(),
+            absolute,
+        })
+    }
+
+    /// Returns the exact relative slippage factor, e.g. `1/100` for 1%.
+    pub fn as_factor(&self) -> &BigRational {
+        &self.relative
+    }
+
+    /// Returns the relative slippage in basis points, rounded to the nearest
+    /// integer.
+    pub fn as_basis_points(&self) -> u32 {
+        let bps = &self.relative * BigRational::from_integer(BPS_BASE.into());
+        bps.round().to_integer().to_u32().unwrap_or(u32::MAX)
     }
 
     /// Increase the specified amount by the constant slippage.
     pub fn add_to_amount(&self, amount: U256) -> U256 {
         amount.saturating_add(self.absolute)
     }
+
+    /// Decrease the specified amount by the constant slippage.
+    pub fn sub_from_amount(&self, amount: U256) -> U256 {
+        amount.saturating_sub(self.absolute)
+    }
+
+    /// Loosens the limit amount of an order of the specified kind, i.e. the
+    /// sell amount of sell orders is increased and the buy amount of buy orders
+    /// decreased.
+    pub fn apply_to_amount(&self, amount: U256, kind: OrderKind) -> U256 {
+        match kind {
+            OrderKind::Sell => self.add_to_amount(amount),
+            OrderKind::Buy => self.sub_from_amount(amount),
+        }
+    }
+}
+
+/// The limit amounts of a swap, see `SlippageCalculator::bounds`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SwapBounds {
+    /// The maximum amount of the sell token to be sold.
+    pub max_sell: U256,
+    /// The minimum amount of the buy token to be bought.
+    pub min_buy: U256,
 }
 
-fn absolute_slippage_amount(relative: &BigRational, amount: &BigInt) -> BigInt {
-    let ratio = relative * amount;
-    // Perform a ceil division so that we round up with slippage amount
-    ratio.numer().div_ceil(ratio.denom())
+/// Slippage realized by a settled trade, i.e. how much the amount actually
+/// received deviated from the expected amount. Positive values mean the trade
+/// did better than expected, negative values that it did worse.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RealizedSlippage {
+    /// The deviation in token units, `actual - expected`.
+    pub absolute: BigInt,
+    /// The deviation in basis points of the expected amount. Infinite if
+    /// nothing was expected but something was received.
+    pub basis_points: f64,
+}
+
+/// Computes the slippage realized when `actual` was received instead of
+/// `expected`.
+pub fn compute_realized(expected: U256, actual: U256) -> RealizedSlippage {
+    let expected = number::conversions::u256_to_big_int(&expected);
+    let absolute = number::conversions::u256_to_big_int(&actual) - &expected;
+    let basis_points = if absolute.is_zero() {
+        0.
+    } else if expected.is_zero() {
+        f64::INFINITY
+    } else {
+        BigRational::new(&absolute * BPS_BASE, expected)
+            .to_f64()
+            .unwrap_or(f64::NAN)
+    };
+    RealizedSlippage {
+        absolute,
+        basis_points,
+    }
+}
+
+/// Formats a token amount in base units as a decimal number of whole tokens
+/// for logging, e.g. `1010500000000000000` with 18 decimals as `1.0105`.
+/// Trailing zeros of the fractional part are omitted.
+pub fn format_amount(amount: U256, decimals: u8) -> String {
+    let digits = amount.to_string();
+    let decimals = usize::from(decimals);
+    let digits = format!("{digits:0>width$}", width = decimals + 1);
+    let (integer, fraction) = digits.split_at(digits.len() - decimals);
+    match fraction.trim_end_matches('0') {
+        "" => integer.to_owned(),
+        fraction => format!("{integer}.{fraction}"),
+    }
+}
+
+impl RoundingMode {
+    fn absolute_slippage_amount(&self, relative: &BigRational, amount: &BigInt) -> BigInt {
+        let ratio = relative * amount;
+        let (numer, denom) = (ratio.numer(), ratio.denom());
+        match self {
+            Self::Floor => numer.div_floor(denom),
+            Self::Ceil => numer.div_ceil(denom),
+            Self::HalfEven => {
+                let (quotient, remainder) = numer.div_mod_floor(denom);
+                match (remainder * 2_u32).cmp(denom) {
+                    cmp::Ordering::Less => quotient,
+                    cmp::Ordering::Greater => quotient + 1,
+                    cmp::Ordering::Equal if quotient.is_even() => quotient,
+                    cmp::Ordering::Equal => quotient + 1,
+                }
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -192,6 +810,7 @@ mod tests {
     use {
         super::*,
         shared::externalprices,
+        std::time::{Duration, Instant},
         testlib::tokens::{GNO, USDC, WETH},
     };
 
@@ -233,4 +852,668 @@ mod tests {
             assert_eq!(execution.input_max.amount, expected);
         }
     }
+
+    #[test]
+    fn from_basis_points() {
+        let calculator = SlippageCalculator::from_basis_points(100, None).unwrap();
+        assert_eq!(calculator.relative, BigRational::new(1.into(), 100.into()));
+        assert_eq!(
+            SlippageCalculator::from_basis_points(10_000, None)
+                .unwrap()
+                .relative,
+            BigRational::one()
+        );
+
+        assert!(matches!(
+            SlippageCalculator::from_basis_points(10_001, None),
+            Err(SlippageError::RelativeOutOfRange { value })
+                if value == BigRational::new(10_001.into(), 10_000.into())
+        ));
+    }
+
+    #[test]
+    fn rejects_out_of_range_relative_slippage() {
+        for value in [
+            BigRational::new((-1).into(), 100.into()),
+            BigRational::new(101.into(), 100.into()),
+        ] {
+            assert!(matches!(
+                SlippageCalculator::new(value.clone(), None),
+                Err(SlippageError::RelativeOutOfRange { value: v }) if v == value
+            ));
+        }
+
+        for value in [BigRational::zero(), BigRational::one()] {
+            assert!(SlippageCalculator::new(value, None).is_ok());
+        }
+    }
+
+    #[test]
+    fn compound_slippage() {
+        let calculator = SlippageCalculator::from_bps(100, None).with_buy_relative_bps(50);
+
+        let single = calculator.compound(1);
+        assert_eq!(single.relative, calculator.relative);
+        assert_eq!(single.buy_relative, calculator.buy_relative);
+
+        // 1 - 0.99^2 = 0.0199
+        let double = calculator.compound(2);
+        assert_eq!(double.relative, BigRational::new(199.into(), 10_000.into()));
+        // 1 - 0.995^2 = 0.009975
+        assert_eq!(
+            double.buy_relative,
+            Some(BigRational::new(9_975.into(), 1_000_000.into()))
+        );
+
+        let many = calculator.compound(1_000);
+        assert!(many.relative > BigRational::new(9_999.into(), 10_000.into()));
+        assert!(many.relative <= BigRational::one());
+
+        // Out of range factors are clamped before compounding.
+        let calculator = SlippageCalculator {
+            relative: BigRational::from_integer(2.into()),
+            ..Default::default()
+        };
+        assert_eq!(calculator.compound(3).relative, BigRational::one());
+        assert_eq!(calculator.compound(0).relative, BigRational::zero());
+    }
+
+    #[test]
+    fn builds_calculator() {
+        let calculator = SlippageCalculator::builder()
+            .relative_bps(100)
+            .buy_relative_bps(50)
+            .absolute(1_000.into())
+            .token_absolute(USDC, 10.into())
+            .rounding(RoundingMode::Floor)
+            .build()
+            .unwrap();
+        assert_eq!(calculator.relative, BigRational::new(1.into(), 100.into()));
+        assert_eq!(
+            calculator.buy_relative(),
+            &BigRational::new(1.into(), 200.into())
+        );
+        assert_eq!(calculator.absolute, Some(1_000.into()));
+        assert_eq!(calculator.token_absolute[&USDC], 10.into());
+        assert_eq!(calculator.rounding, RoundingMode::Floor);
+
+        let defaults = SlippageCalculator::builder().build().unwrap();
+        assert_eq!(defaults.relative, SlippageCalculator::default().relative);
+
+        for builder in [
+            SlippageCalculator::builder().relative_bps(10_001),
+            SlippageCalculator::builder().buy_relative(BigRational::new((-1).into(), 100.into())),
+        ] {
+            assert!(matches!(
+                builder.build(),
+                Err(SlippageError::RelativeOutOfRange { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn swap_bounds() {
+        let prices = externalprices! {
+            native_token: WETH,
+            GNO => BigRational::new(1.into(), 20.into()),
+        };
+        let calculator =
+            SlippageCalculator::from_bps(100, Some(U256::exp10(17))).with_buy_relative_bps(50);
+        let sell = TokenAmount::new(GNO, 10_000_000_000_000_000_000_u128);
+        let buy = TokenAmount::new(WETH, 500_000_000_000_000_000_u128);
+
+        let bounds = calculator.bounds(&sell, &buy, &prices).unwrap();
+
+        let (relative, absolute) = calculator
+            .compute(
+                &sell.token,
+                prices.price(&sell.token),
+                number::conversions::u256_to_big_int(&sell.amount),
+            )
+            .unwrap();
+        let max_sell = SlippageAmount::from_num(&relative, &absolute)
+            .unwrap()
+            .add_to_amount(sell.amount);
+        let (relative, absolute) = calculator
+            .compute_buy(
+                &buy.token,
+                prices.price(&buy.token),
+                number::conversions::u256_to_big_int(&buy.amount),
+            )
+            .unwrap();
+        let min_buy = SlippageAmount::from_num(&relative, &absolute)
+            .unwrap()
+            .sub_from_amount(buy.amount);
+        assert_eq!(bounds, SwapBounds { max_sell, min_buy });
+        assert_eq!(
+            bounds,
+            SwapBounds {
+                max_sell: 10_100_000_000_000_000_000_u128.into(),
+                min_buy: 497_500_000_000_000_000_u128.into(),
+            }
+        );
+    }
+
+    #[test]
+    fn asymmetric_slippage() {
+        let prices = externalprices! { native_token: WETH };
+        let amount = U256::from(1_000_000_u128);
+        let slippage = |calculator: &SlippageCalculator| {
+            let (relative, absolute) = calculator
+                .compute(
+                    &USDC,
+                    prices.price(&USDC),
+                    number::conversions::u256_to_big_int(&amount),
+                )
+                .unwrap();
+            let sell = SlippageAmount::from_num(&relative, &absolute).unwrap();
+            let (relative, absolute) = calculator
+                .compute_buy(
+                    &USDC,
+                    prices.price(&USDC),
+                    number::conversions::u256_to_big_int(&amount),
+                )
+                .unwrap();
+            let buy = SlippageAmount::from_num(&relative, &absolute).unwrap();
+            (sell.add_to_amount(amount), buy.sub_from_amount(amount))
+        };
+
+        // Without a buy side factor, both sides use the same tolerance.
+        let symmetric = SlippageCalculator::from_bps(100, None);
+        assert_eq!(
+            slippage(&symmetric),
+            (1_010_000_u128.into(), 990_000_u128.into())
+        );
+
+        let asymmetric = SlippageCalculator::from_bps(100, None).with_buy_relative_bps(200);
+        assert_eq!(
+            slippage(&asymmetric),
+            (1_010_000_u128.into(), 980_000_u128.into())
+        );
+    }
+
+    #[test]
+    fn token_denominated_absolute_slippage() {
+        // USDC has no native price, so only the token denominated cap of 1 USDC
+        // can be applied to it.
+        let calculator = SlippageCalculator::from_bps(100, Some(U256::exp10(18)))
+            .with_token_absolute(USDC, 1_000_000_u128.into());
+        let prices = externalprices! { native_token: WETH };
+
+        let (relative, absolute) = calculator
+            .compute(&USDC, prices.price(&USDC), 200_000_000.into())
+            .unwrap();
+        assert_eq!(*relative, BigRational::new(1.into(), 200.into()));
+        assert_eq!(absolute, 1_000_000.into());
+
+        let slippage = calculator.context(&prices);
+        let execution = slippage
+            .apply_to_amm_execution(AmmOrderExecution {
+                input_max: TokenAmount::new(USDC, 200_000_000_u128),
+                output: TokenAmount::new(GNO, 2_000_000_000_000_000_000_u128),
+                internalizable: false,
+            })
+            .unwrap();
+        assert_eq!(execution.input_max.amount, 201_000_000_u128.into());
+
+        // Tokens without a token denominated cap still require a price.
+        assert!(calculator
+            .compute(&GNO, prices.price(&GNO), 200_000_000.into())
+            .is_err());
+    }
+
+    #[test]
+    fn stale_prices_fall_back_to_relative_slippage() {
+        // The absolute limit of 0.001 WETH caps slippage on 10 GNO worth 1 WETH
+        // to 0.1%.
+        let calculator = SlippageCalculator::from_bps(100, Some(U256::exp10(15)));
+        let prices = externalprices! {
+            native_token: WETH,
+            GNO => BigRational::new(1.into(), 10.into()),
+        }
+        .with_updated_at([(
+            GNO,
+            Instant::now()
+                .checked_sub(Duration::from_secs(120))
+                .expect("monotonic clock too early to backdate a price by 2 minutes"),
+        )]);
+        let execution = AmmOrderExecution {
+            input_max: TokenAmount::new(GNO, 10_000_000_000_000_000_000_u128),
+            output: TokenAmount::new(USDC, 1_000_000_u128),
+            internalizable: false,
+        };
+
+        let capped = calculator
+            .context(&prices)
+            .apply_to_amm_execution(execution.clone())
+            .unwrap();
+        assert_eq!(
+            capped.input_max.amount,
+            10_010_000_000_000_000_000_u128.into()
+        );
+
+        // Once the GNO price is too old it can't be used for the absolute
+        // limit, so only the relative one applies.
+        let prices = prices.with_max_age(Duration::from_secs(60));
+        let uncapped = calculator
+            .context(&prices)
+            .apply_to_amm_execution(execution)
+            .unwrap();
+        assert_eq!(
+            uncapped.input_max.amount,
+            10_100_000_000_000_000_000_u128.into()
+        );
+    }
+
+    #[test]
+    fn implausible_prices_fall_back_to_relative_slippage() {
+        let calculator = SlippageCalculator::from_bps(100, Some(U256::exp10(18)));
+        let amount = BigInt::from(10_u128.pow(18));
+
+        // 1 GNO worth 0.1 WETH stays well within the absolute limit of 1 WETH.
+        let price = BigRational::new(1.into(), 10.into());
+        let (relative, absolute) = calculator
+            .compute(&GNO, Some(&price), amount.clone())
+            .unwrap();
+        assert_eq!(*relative, BigRational::new(1.into(), 100.into()));
+        assert_eq!(absolute, BigInt::from(10_u128.pow(16)));
+
+        // A price in the wrong units values the trade at 10^30 ETH, which would
+        // cap the slippage to almost nothing.
+        let price = BigRational::from_integer(BigInt::from(10_u128.pow(30)));
+        let (events, _guard) = crate::test_util::Events::capture();
+        let (relative, absolute) = calculator.compute(&GNO, Some(&price), amount).unwrap();
+        assert_eq!(*relative, calculator.relative);
+        assert_eq!(absolute, BigInt::from(10_u128.pow(16)));
+        assert!(events.warned("implausible trade value"));
+    }
+
+    #[test]
+    fn usd_absolute_slippage() {
+        // 1 ETH = 2000 USD and 1 GNO = 100 USD.
+        let prices = externalprices! {
+            native_token: WETH,
+            USDC => BigRational::from_integer(500_000_000.into()),
+            GNO => BigRational::new(1.into(), 20.into()),
+        };
+        let usd = SlippageCalculator::from_bps(100, None)
+            .with_usd_absolute(U256::from(1_000_000_000_u128), USDC);
+        let native = SlippageCalculator::from_bps(100, Some(U256::exp10(17) * 5));
+
+        let amount = BigInt::from(2_000_u128 * 10_u128.pow(18));
+        let compute = |calculator: &SlippageCalculator| {
+            let (relative, absolute) = calculator
+                .for_prices(&prices)
+                .compute(&GNO, prices.price(&GNO), amount.clone())
+                .unwrap();
+            (relative.into_owned(), absolute)
+        };
+        let (relative, absolute) = compute(&usd);
+        assert_eq!(relative, BigRational::new(1.into(), 200.into()));
+        assert_eq!(absolute, BigInt::from(10_u128.pow(19)));
+        assert_eq!((relative, absolute), compute(&native));
+
+        // Without a USDC price, only the relative limit applies.
+        let prices = externalprices! {
+            native_token: WETH,
+            GNO => BigRational::new(1.into(), 20.into()),
+        };
+        let (relative, _) = usd
+            .for_prices(&prices)
+            .compute(&GNO, prices.price(&GNO), amount)
+            .unwrap();
+        assert_eq!(*relative, BigRational::new(1.into(), 100.into()));
+    }
+
+    #[test]
+    fn token_relative_slippage() {
+        let prices = externalprices! { native_token: WETH };
+        let calculator = SlippageCalculator::builder()
+            .relative_bps(100)
+            .buy_relative_bps(50)
+            .token_relative_bps(GNO, 500)
+            .token_absolute(GNO, 100_000.into())
+            .build()
+            .unwrap();
+        let amount = BigInt::from(1_000_000);
+
+        // The override replaces both the sell and the buy side factor.
+        let (relative, absolute) = calculator
+            .compute(&GNO, prices.price(&GNO), amount.clone())
+            .unwrap();
+        assert_eq!(*relative, BigRational::new(5.into(), 100.into()));
+        assert_eq!(absolute, 50_000.into());
+        let (relative, _) = calculator
+            .compute_buy(&GNO, prices.price(&GNO), amount.clone())
+            .unwrap();
+        assert_eq!(*relative, BigRational::new(5.into(), 100.into()));
+
+        // But the absolute cap still applies.
+        let (relative, absolute) = calculator
+            .compute(&GNO, prices.price(&GNO), BigInt::from(10_000_000))
+            .unwrap();
+        assert_eq!(*relative, BigRational::new(1.into(), 100.into()));
+        assert_eq!(absolute, 100_000.into());
+
+        // Other tokens keep using the global factors.
+        let (relative, _) = calculator
+            .compute(&WETH, prices.price(&WETH), amount.clone())
+            .unwrap();
+        assert_eq!(*relative, BigRational::new(1.into(), 100.into()));
+        let (relative, _) = calculator
+            .compute_buy(&WETH, prices.price(&WETH), amount)
+            .unwrap();
+        assert_eq!(*relative, BigRational::new(1.into(), 200.into()));
+    }
+
+    #[test]
+    fn minimum_relative_slippage() {
+        let prices = externalprices! { native_token: WETH };
+        let calculator = SlippageCalculator::builder()
+            .relative_bps(100)
+            .min_relative_bps(5)
+            .absolute(U256::exp10(15))
+            .build()
+            .unwrap();
+
+        // The absolute cap of 0.001 WETH limits a 100 WETH trade to 0.1 bps,
+        // which gets raised to the floor of 5 bps.
+        let (relative, absolute) = calculator
+            .compute(
+                &WETH,
+                prices.price(&WETH),
+                BigInt::from(100) * BigInt::from(10).pow(18),
+            )
+            .unwrap();
+        assert_eq!(*relative, BigRational::new(5.into(), 10_000.into()));
+        assert_eq!(absolute, BigInt::from(5) * BigInt::from(10).pow(16));
+
+        // Small trades aren't affected.
+        let (relative, _) = calculator
+            .compute(&WETH, prices.price(&WETH), BigInt::from(10).pow(15))
+            .unwrap();
+        assert_eq!(*relative, BigRational::new(1.into(), 100.into()));
+
+        for builder in [
+            SlippageCalculator::builder()
+                .relative_bps(4)
+                .min_relative_bps(5),
+            SlippageCalculator::builder()
+                .relative_bps(10)
+                .buy_relative_bps(4)
+                .min_relative_bps(5),
+        ] {
+            assert!(matches!(
+                builder.build(),
+                Err(SlippageError::MinAboveRelative { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn gas_aware_slippage() {
+        let prices = externalprices! { native_token: WETH };
+        let gas_cost = U256::exp10(15);
+        let calculator = SlippageCalculator::from_bps(100, None);
+
+        // Gas is negligible compared to a 1000 WETH trade.
+        let (relative, _) = calculator
+            .compute_with_gas(
+                &WETH,
+                prices.price(&WETH),
+                BigInt::from(10).pow(21),
+                gas_cost,
+            )
+            .unwrap();
+        assert_eq!(relative, BigRational::new(10_001.into(), 1_000_000.into()));
+
+        // For a 0.01 WETH trade the same gas cost widens the tolerance by 10%.
+        let (relative, absolute) = calculator
+            .compute_with_gas(
+                &WETH,
+                prices.price(&WETH),
+                BigInt::from(10).pow(16),
+                gas_cost,
+            )
+            .unwrap();
+        assert_eq!(relative, BigRational::new(11.into(), 100.into()));
+        assert_eq!(absolute, BigInt::from(11) * BigInt::from(10).pow(14));
+
+        // The widened tolerance never exceeds 100%...
+        let (relative, _) = calculator
+            .compute_with_gas(
+                &WETH,
+                prices.price(&WETH),
+                BigInt::from(10).pow(14),
+                gas_cost,
+            )
+            .unwrap();
+        assert_eq!(relative, BigRational::one());
+
+        // ... and is still capped by the absolute limit.
+        let capped = SlippageCalculator::from_bps(100, Some(U256::from(5) * U256::exp10(14)));
+        let (relative, absolute) = capped
+            .compute_with_gas(
+                &WETH,
+                prices.price(&WETH),
+                BigInt::from(10).pow(16),
+                gas_cost,
+            )
+            .unwrap();
+        assert_eq!(relative, BigRational::new(5.into(), 100.into()));
+        assert_eq!(absolute, BigInt::from(5) * BigInt::from(10).pow(14));
+    }
+
+    #[test]
+    fn slippage_amount_accessors() {
+        let slippage =
+            SlippageAmount::from_num(&BigRational::new(1.into(), 100.into()), &10.into()).unwrap();
+        assert_eq!(
+            *slippage.as_factor(),
+            BigRational::new(1.into(), 100.into())
+        );
+        assert_eq!(slippage.as_basis_points(), 100);
+
+        // Tolerances below half a basis point round to zero.
+        let slippage =
+            SlippageAmount::from_num(&BigRational::new(1.into(), 100_000.into()), &0.into())
+                .unwrap();
+        assert_eq!(slippage.as_basis_points(), 0);
+    }
+
+    #[test]
+    fn apply_slippage_by_order_kind() {
+        let slippage =
+            SlippageAmount::from_num(&BigRational::new(1.into(), 100.into()), &10.into()).unwrap();
+        for amount in [0.into(), 1_000.into(), U256::MAX] {
+            assert_eq!(
+                slippage.apply_to_amount(amount, OrderKind::Sell),
+                slippage.add_to_amount(amount)
+            );
+            assert_eq!(
+                slippage.apply_to_amount(amount, OrderKind::Buy),
+                slippage.sub_from_amount(amount)
+            );
+        }
+    }
+
+    #[test]
+    fn serialization_roundtrip() {
+        let calculator = SlippageCalculator::from_bps(10, None);
+        let json = serde_json::to_value(&calculator).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "relative": "1/1000",
+                "buyRelative": null,
+                "minRelative": null,
+                "absolute": null,
+                "usdAbsolute": null,
+                "tokenAbsolute": {},
+                "tokenRelative": {},
+                "rounding": "ceil",
+            })
+        );
+        assert_eq!(
+            serde_json::from_value::<SlippageCalculator>(json).unwrap(),
+            calculator
+        );
+
+        let calculator = SlippageCalculator::from_bps(100, Some(U256::exp10(18)))
+            .with_buy_relative_bps(50)
+            .with_token_absolute(USDC, 1_000_000.into())
+            .with_rounding(RoundingMode::HalfEven)
+            .compound(3);
+        let json = serde_json::to_string(&calculator).unwrap();
+        assert_eq!(
+            serde_json::from_str::<SlippageCalculator>(&json).unwrap(),
+            calculator
+        );
+
+        let slippage =
+            SlippageAmount::from_num(&BigRational::new(1.into(), 100.into()), &10.into()).unwrap();
+        let json = serde_json::to_value(&slippage).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "relative": "1/100", "absolute": "10" })
+        );
+        assert_eq!(
+            serde_json::from_value::<SlippageAmount>(json).unwrap(),
+            slippage
+        );
+    }
+
+    #[test]
+    fn checked_absolute_slippage() {
+        let calculator = SlippageCalculator::from_bps(100, None);
+        assert_eq!(calculator.checked_abs(1_000.into()).unwrap(), 10.into());
+        assert_eq!(
+            calculator.checked_abs(U256::MAX).unwrap(),
+            U256::MAX / 100 + 1
+        );
+
+        // A factor of 200% bypasses the validation of `SlippageCalculator::new`.
+        let calculator = SlippageCalculator {
+            relative: BigRational::from_integer(2.into()),
+            ..Default::default()
+        };
+        assert!(calculator.checked_abs(U256::MAX).is_err());
+
+        // The hot path keeps saturating.
+        let slippage = SlippageAmount {
+            relative: BigRational::from_integer(2.into()),
+            absolute: U256::MAX,
+        };
+        assert_eq!(slippage.add_to_amount(U256::MAX), U256::MAX);
+        assert_eq!(slippage.sub_from_amount(1.into()), U256::zero());
+    }
+
+    #[test]
+    fn realized_slippage() {
+        // Better than expected.
+        let realized = compute_realized(1_000.into(), 1_010.into());
+        assert_eq!(realized.absolute, 10.into());
+        assert_eq!(realized.basis_points, 100.);
+
+        // Worse than expected.
+        let realized = compute_realized(1_000.into(), 995.into());
+        assert_eq!(realized.absolute, (-5).into());
+        assert_eq!(realized.basis_points, -50.);
+
+        // Exact fill.
+        let realized = compute_realized(1_000.into(), 1_000.into());
+        assert_eq!(realized.absolute, 0.into());
+        assert_eq!(realized.basis_points, 0.);
+
+        // Differences beyond the range of `U256` are still signed correctly.
+        let realized = compute_realized(U256::MAX, U256::zero());
+        assert_eq!(
+            realized.absolute,
+            -number::conversions::u256_to_big_int(&U256::MAX)
+        );
+        assert_eq!(realized.basis_points, -10_000.);
+
+        assert_eq!(compute_realized(0.into(), 0.into()).basis_points, 0.);
+        assert_eq!(
+            compute_realized(0.into(), 1.into()).basis_points,
+            f64::INFINITY
+        );
+    }
+
+    #[test]
+    fn rounding_modes() {
+        let prices = externalprices! { native_token: WETH };
+        let absolute = |rounding: RoundingMode, amount: u32| {
+            let (_, absolute) = SlippageCalculator::from_bps(100, None)
+                .with_rounding(rounding)
+                .compute(&WETH, prices.price(&WETH), amount.into())
+                .unwrap();
+            absolute
+        };
+
+        // 1% of 1050 is 10.5 and 1% of 1150 is 11.5.
+        assert_eq!(absolute(RoundingMode::Ceil, 1050), 11.into());
+        assert_eq!(absolute(RoundingMode::Floor, 1050), 10.into());
+        assert_eq!(absolute(RoundingMode::HalfEven, 1050), 10.into());
+        assert_eq!(absolute(RoundingMode::HalfEven, 1150), 12.into());
+
+        // 1% of 1040 is 10.4 and 1% of 1060 is 10.6.
+        assert_eq!(absolute(RoundingMode::Ceil, 1040), 11.into());
+        assert_eq!(absolute(RoundingMode::Floor, 1060), 10.into());
+        assert_eq!(absolute(RoundingMode::HalfEven, 1040), 10.into());
+        assert_eq!(absolute(RoundingMode::HalfEven, 1060), 11.into());
+
+        // Exact values are not affected by rounding.
+        for rounding in [
+            RoundingMode::Floor,
+            RoundingMode::Ceil,
+            RoundingMode::HalfEven,
+        ] {
+            assert_eq!(absolute(rounding, 1000), 10.into());
+        }
+    }
+
+    #[test]
+    fn logs_amounts_in_whole_tokens() {
+        let prices = externalprices! { native_token: WETH };
+        let calculator = SlippageCalculator::from_bps(100, None);
+        let token_infos = HashMap::from([(
+            WETH,
+            TokenInfo {
+                decimals: Some(18),
+                symbol: Some("WETH".to_owned()),
+            },
+        )]);
+        let context = calculator.context(&prices).with_token_infos(&token_infos);
+
+        assert_eq!(
+            context.display(&TokenAmount::new(WETH, 1_010_500_000_000_000_000_u128)),
+            "1.0105 WETH"
+        );
+        // Tokens without decimals are shown in base units.
+        assert_eq!(context.display(&TokenAmount::new(GNO, 1_000_u128)), "1000");
+
+        let (sell, buy) = (
+            TokenAmount::new(WETH, 1_000_000_u128),
+            TokenAmount::new(GNO, 1_000_000_u128),
+        );
+        assert_eq!(
+            context.bounds(&sell, &buy).unwrap(),
+            calculator.bounds(&sell, &buy, &prices).unwrap()
+        );
+    }
+
+    #[test]
+    fn formats_amounts_with_decimals() {
+        assert_eq!(format_amount(U256::exp10(18), 18), "1");
+        assert_eq!(format_amount(U256::exp10(6), 6), "1");
+        assert_eq!(
+            format_amount(1_010_500_000_000_000_000_u128.into(), 18),
+            "1.0105"
+        );
+        assert_eq!(format_amount(1_500_000.into(), 6), "1.5");
+        assert_eq!(format_amount(1.into(), 6), "0.000001");
+        assert_eq!(format_amount(0.into(), 18), "0");
+        assert_eq!(format_amount(42.into(), 0), "42");
+    }
 }
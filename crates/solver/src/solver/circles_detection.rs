@@ -1,27 +1,413 @@
-use anyhow::Result;
-use ethcontract::{H160, web3::Transport};
+use anyhow::{Context, Result};
+use futures::stream::{self, StreamExt};
+use ethcontract::{H160, H256, U256, web3::Transport};
+use hex_literal::hex;
 use web3::types::{CallRequest, Bytes};
-use crate::config::circles_config::CirclesConfig;
+use crate::config::circles_config::{CirclesConfig, HubVersion};
 use crate::solver::web3_provider::Web3Provider;
 use model::order::Order;
 use model::order::OrderData;
+use model::order::OrderKind;
+use model::order::OrderUid;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::Duration;
+use ttl_cache::TtlCache;
+
+/// Upper bound on the number of edges explored by `match_crc_rings`, so that
+/// dense order graphs cannot stall the solver.
+const MAX_RING_SEARCH_STEPS: usize = 10_000;
+
+/// Selector of the CRC token `hub()` function.
+const HUB_SELECTOR: [u8; 4] = hex!("365a86fc");
+
+/// Selector of the v1 hub `tokenToUser(address)` function.
+const TOKEN_TO_USER_SELECTOR: [u8; 4] = hex!("a18b506b");
+
+/// Selector of the v2 ERC20 wrapper `avatar()` function.
+const AVATAR_SELECTOR: [u8; 4] = hex!("5aef7de6");
+
+/// Selector of the v2 hub `avatars(address)` function.
+const AVATARS_SELECTOR: [u8; 4] = hex!("edeeb93c");
 
 #[derive(Clone, Debug)]
 pub struct CRCOrderInfo {
     pub order: Order,
     pub sell_is_crc: bool,
     pub buy_is_crc: bool,
+    /// The hub the sell token is registered with, if it is a CRC token.
+    pub sell_hub: Option<H160>,
+    /// The hub the buy token is registered with, if it is a CRC token.
+    pub buy_hub: Option<H160>,
+}
+
+impl CRCOrderInfo {
+    fn new(order: Order, sell_hub: Option<H160>, buy_hub: Option<H160>) -> Self {
+        Self {
+            order,
+            sell_is_crc: sell_hub.is_some(),
+            buy_is_crc: buy_hub.is_some(),
+            sell_hub,
+            buy_hub,
+        }
+    }
+
+    /// Whether the order sells an exact amount (`Sell`) or buys an exact
+    /// amount (`Buy`). The other amount is only a limit.
+    pub fn kind(&self) -> OrderKind {
+        self.order.data.kind
+    }
+
+    /// Whether the order trades CRC tokens registered with different hubs.
+    pub fn spans_hubs(&self) -> bool {
+        matches!((self.sell_hub, self.buy_hub), (Some(sell), Some(buy)) if sell != buy)
+    }
+}
+
+/// Two CRC orders trading in opposite directions over the same tokens.
+#[derive(Clone, Debug)]
+pub struct CRCPair {
+    pub first: CRCOrderInfo,
+    pub second: CRCOrderInfo,
+    /// The token sold by `first` and bought by `second`, followed by the token
+    /// sold by `second` and bought by `first`.
+    pub tokens: (H160, H160),
+    /// The amount of `tokens.0` that can be exchanged between the two orders,
+    /// i.e. the overlap of what `first` sells and `second` buys.
+    pub matchable_amount: U256,
+}
+
+impl CRCPair {
+    fn new(first: &CRCOrderInfo, second: &CRCOrderInfo) -> Self {
+        // `first` sells at most its sell amount, exactly that for sell orders.
+        // A buy order takes exactly its buy amount, while the buy amount of a
+        // sell order is only the minimum it accepts for selling all of its
+        // tokens, so it doesn't limit the exchange. What does limit it is how
+        // much `first` pays for all of the sell order's tokens at its own
+        // limit price.
+        let (first_sell, first_buy) = (first.order.data.sell_amount, first.order.data.buy_amount);
+        let matchable_amount = match second.kind() {
+            OrderKind::Buy => first_sell.min(second.order.data.buy_amount),
+            OrderKind::Sell => second
+                .order
+                .data
+                .sell_amount
+                .checked_mul(first_sell)
+                .and_then(|amount| amount.checked_div(first_buy))
+                .map_or(first_sell, |amount| first_sell.min(amount)),
+        };
+        Self {
+            first: first.clone(),
+            second: second.clone(),
+            tokens: (first.order.data.sell_token, first.order.data.buy_token),
+            matchable_amount,
+        }
+    }
+
+    /// Creates the pair of two orders given in input order, putting the order
+    /// with the smaller uid first so that the pair doesn't depend on the
+    /// order of the input.
+    fn ordered(earlier: &CRCOrderInfo, later: &CRCOrderInfo) -> Self {
+        if later.order.metadata.uid < earlier.order.metadata.uid {
+            Self::new(later, earlier)
+        } else {
+            Self::new(earlier, later)
+        }
+    }
+
+    /// Identifies the match independently of which order comes first, so
+    /// that resubmitted orders don't produce the same pair twice.
+    fn key(&self) -> (OrderUid, OrderUid) {
+        let (a, b) = (self.first.order.metadata.uid, self.second.order.metadata.uid);
+        (a.min(b), a.max(b))
+    }
+}
+
+/// A ring of CRC orders (A→B, B→C, ..., X→A) together with how well its token
+/// flows net out.
+#[derive(Clone, Debug)]
+pub struct CRCRing {
+    pub orders: Vec<CRCOrderInfo>,
+    /// For every token of the ring, the amount that is both sold and bought by
+    /// its orders and can therefore be exchanged within the ring.
+    pub matched_volume: HashMap<H160, U256>,
+    /// The tokens the orders of the ring sell more of than they buy, with the
+    /// excess amount, which would have to be sold to external liquidity.
+    /// Empty if the ring nets out perfectly.
+    pub surplus: HashMap<H160, U256>,
+    /// The tokens the orders of the ring buy more of than they sell, with the
+    /// missing amount, which would have to be bought from external liquidity.
+    /// Empty if the ring nets out perfectly.
+    pub deficit: HashMap<H160, U256>,
+}
+
+impl CRCRing {
+    fn new(orders: Vec<CRCOrderInfo>) -> Self {
+        let mut sold: HashMap<H160, U256> = HashMap::new();
+        let mut bought: HashMap<H160, U256> = HashMap::new();
+        for o in &orders {
+            let sell = sold.entry(o.order.data.sell_token).or_default();
+            *sell = sell.saturating_add(o.order.data.sell_amount);
+            let buy = bought.entry(o.order.data.buy_token).or_default();
+            *buy = buy.saturating_add(o.order.data.buy_amount);
+        }
+        // Every token of a ring is both sold and bought.
+        let mut matched_volume = HashMap::new();
+        let (mut surplus, mut deficit) = (HashMap::new(), HashMap::new());
+        for (token, sold) in sold {
+            let bought = bought.get(&token).copied().unwrap_or_default();
+            matched_volume.insert(token, sold.min(bought));
+            if sold > bought {
+                surplus.insert(token, sold - bought);
+            } else if bought > sold {
+                deficit.insert(token, bought - sold);
+            }
+        }
+        Self { orders, matched_volume, surplus, deficit }
+    }
+}
+
+/// A CRC order that is filled by several smaller orders trading in the
+/// opposite direction.
+#[derive(Clone, Debug)]
+pub struct PartialMatch {
+    pub order: CRCOrderInfo,
+    pub fills: Vec<Fill>,
 }
 
+/// The part of a `PartialMatch` provided by a single counter-order.
+#[derive(Clone, Debug)]
+pub struct Fill {
+    pub counter: CRCOrderInfo,
+    /// The amount of the matched order's sell token exchanged with the
+    /// counter-order.
+    pub amount: U256,
+}
+
+impl PartialMatch {
+    /// The total amount of the order's sell token that gets filled.
+    pub fn filled_amount(&self) -> U256 {
+        self.fills
+            .iter()
+            .fold(U256::zero(), |total, fill| total.saturating_add(fill.amount))
+    }
+}
+
+/// Checks whether `token` is a CRC token: it has to report one of the known
+/// hubs from `hub()` and be registered with that hub according to the hub's
+/// version.
 pub async fn is_crc_token<T: Transport>(
     web3: &Web3Provider<T>,
     circles_config: &CirclesConfig,
     token: H160,
 ) -> Result<bool> {
-    let data = hex::decode("f9d4e0da").unwrap(); // hub() selector
+    Ok(crc_token_hub(web3, circles_config, token).await?.is_some())
+}
+
+/// Returns the hub `token` is registered with, or `None` if it isn't a CRC
+/// token according to `is_crc_token`.
+pub async fn crc_token_hub<T: Transport>(
+    web3: &Web3Provider<T>,
+    circles_config: &CirclesConfig,
+    token: H160,
+) -> Result<Option<H160>> {
+    match explain_token(web3, circles_config, token).await? {
+        TokenExplanation::Crc { hub } => Ok(Some(hub)),
+        _ => Ok(None),
+    }
+}
+
+/// Why a token is or isn't a CRC token, see `explain_token`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TokenExplanation {
+    /// The token doesn't report a hub, e.g. because it has no `hub()`.
+    NotCrcToken,
+    /// The token reports a hub that isn't configured.
+    HubNotInConfig { hub: H160 },
+    /// The token reports a known hub but isn't registered with it.
+    NotRegistered { hub: H160 },
+    /// The token is a CRC token of `hub`.
+    Crc { hub: H160 },
+}
+
+/// Why an order is or isn't classified as a CRC order, see
+/// `explain_classification`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ClassificationExplanation {
+    /// The order sells and buys the same token.
+    SelfReferential,
+    /// The order has a zero sell or buy amount.
+    ZeroAmount,
+    /// Neither token is a CRC token.
+    NotCrc {
+        sell: TokenExplanation,
+        buy: TokenExplanation,
+    },
+    /// At least one token is a CRC token. Holds the hubs of the CRC tokens.
+    Classified {
+        sell_hub: Option<H160>,
+        buy_hub: Option<H160>,
+    },
+}
+
+/// Checks `token` like `crc_token_hub` but returns the reason for the outcome.
+pub async fn explain_token<T: Transport>(
+    web3: &Web3Provider<T>,
+    circles_config: &CirclesConfig,
+    token: H160,
+) -> Result<TokenExplanation> {
+    let Some(hub) = call_for_address(web3, token, HUB_SELECTOR.to_vec()).await? else {
+        return Ok(TokenExplanation::NotCrcToken);
+    };
+    let is_registered = match circles_config.hub_version(hub) {
+        Some(HubVersion::V1) => is_hub_member_token(web3, hub, token).await?,
+        Some(HubVersion::V2) => is_hub_avatar_token(web3, hub, token).await?,
+        None => return Ok(TokenExplanation::HubNotInConfig { hub }),
+    };
+    Ok(match is_registered {
+        true => TokenExplanation::Crc { hub },
+        false => TokenExplanation::NotRegistered { hub },
+    })
+}
+
+/// Explains how `identify_crc_orders` classifies `order`, for debugging why
+/// an order doesn't get matched. Looks up the tokens without any caching, so
+/// it is not meant for the solving hot path.
+pub async fn explain_classification<T: Transport>(
+    web3: &Web3Provider<T>,
+    circles_config: &CirclesConfig,
+    order: &Order,
+) -> Result<ClassificationExplanation> {
+    if order.data.sell_token == order.data.buy_token {
+        return Ok(ClassificationExplanation::SelfReferential);
+    }
+    if order.data.sell_amount.is_zero() || order.data.buy_amount.is_zero() {
+        return Ok(ClassificationExplanation::ZeroAmount);
+    }
+    let sell = explain_token(web3, circles_config, order.data.sell_token).await?;
+    let buy = explain_token(web3, circles_config, order.data.buy_token).await?;
+    let hub = |explanation| match explanation {
+        TokenExplanation::Crc { hub } => Some(hub),
+        _ => None,
+    };
+    Ok(match (hub(sell), hub(buy)) {
+        (None, None) => ClassificationExplanation::NotCrc { sell, buy },
+        (sell_hub, buy_hub) => ClassificationExplanation::Classified { sell_hub, buy_hub },
+    })
+}
+
+/// The classification of a token: the hub it is registered with, `None` if it
+/// isn't a CRC token.
+pub type Classification = Option<H160>;
+
+/// Storage for token classifications, so that solving many auctions doesn't
+/// look up the same tokens over and over again. Implementations backed by a
+/// shared store, e.g. Redis, let several solver instances share them.
+#[async_trait::async_trait]
+pub trait ClassificationCache: Send + Sync {
+    /// Returns the classification of `token` if it is cached and not expired.
+    async fn get(&self, token: H160) -> Option<Classification>;
+
+    /// Caches the classification of `token` for `ttl`.
+    async fn put(&self, token: H160, classification: Classification, ttl: Duration);
+}
+
+/// In-memory `ClassificationCache` holding at most `capacity` tokens, evicting
+/// the oldest ones first.
+pub struct CrcTokenCache {
+    /// The hub of every looked up token, `None` for tokens that aren't CRC.
+    cache: Mutex<TtlCache<H160, Classification>>,
+}
+
+impl CrcTokenCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            cache: Mutex::new(TtlCache::new(capacity)),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ClassificationCache for CrcTokenCache {
+    async fn get(&self, token: H160) -> Option<Classification> {
+        self.cache.lock().unwrap().get(&token).copied()
+    }
+
+    async fn put(&self, token: H160, classification: Classification, ttl: Duration) {
+        self.cache.lock().unwrap().insert(token, classification, ttl);
+    }
+}
+
+/// Like `is_crc_token` but answers from `cache` if possible. Only successful
+/// lookups are cached, for `ttl`, so failed ones get retried.
+pub async fn is_crc_token_cached<T: Transport>(
+    web3: &Web3Provider<T>,
+    circles_config: &CirclesConfig,
+    cache: &dyn ClassificationCache,
+    ttl: Duration,
+    token: H160,
+) -> Result<bool> {
+    Ok(crc_token_hub_cached(web3, circles_config, cache, ttl, token).await?.is_some())
+}
+
+/// Like `crc_token_hub` but answers from `cache` if possible. Only successful
+/// lookups are cached, for `ttl`, so failed ones get retried.
+pub async fn crc_token_hub_cached<T: Transport>(
+    web3: &Web3Provider<T>,
+    circles_config: &CirclesConfig,
+    cache: &dyn ClassificationCache,
+    ttl: Duration,
+    token: H160,
+) -> Result<Option<H160>> {
+    if let Some(hub) = cache.get(token).await {
+        return Ok(hub);
+    }
+    let hub = crc_token_hub(web3, circles_config, token).await?;
+    cache.put(token, hub, ttl).await;
+    Ok(hub)
+}
+
+/// Checks on the v1 hub contract that `token` was issued to one of its members.
+pub async fn is_hub_member_token<T: Transport>(
+    web3: &Web3Provider<T>,
+    hub: H160,
+    token: H160,
+) -> Result<bool> {
+    let mut data = TOKEN_TO_USER_SELECTOR.to_vec();
+    data.extend_from_slice(H256::from(token).as_bytes());
+    let user = call_for_address(web3, hub, data).await?;
+    Ok(user.is_some_and(|user| !user.is_zero()))
+}
+
+/// Checks that the v2 ERC20 `token` wraps the balance of an avatar registered
+/// with the hub.
+pub async fn is_hub_avatar_token<T: Transport>(
+    web3: &Web3Provider<T>,
+    hub: H160,
+    token: H160,
+) -> Result<bool> {
+    let avatar = match call_for_address(web3, token, AVATAR_SELECTOR.to_vec()).await? {
+        Some(avatar) if !avatar.is_zero() => avatar,
+        _ => return Ok(false),
+    };
+    let mut data = AVATARS_SELECTOR.to_vec();
+    data.extend_from_slice(H256::from(avatar).as_bytes());
+    // Registered avatars form a linked list, so their entry is never zero.
+    let next = call_for_address(web3, hub, data).await?;
+    Ok(next.is_some_and(|next| !next.is_zero()))
+}
+
+/// Calls a contract function returning an address. Returns `None` if the call
+/// reverts or returns too little data.
+async fn call_for_address<T: Transport>(
+    web3: &Web3Provider<T>,
+    to: H160,
+    data: Vec<u8>,
+) -> Result<Option<H160>> {
     let req = CallRequest {
         from: None,
-        to: Some(token),
+        to: Some(to),
         gas: None,
         gas_price: None,
         value: None,
@@ -32,39 +418,158 @@ pub async fn is_crc_token<T: Transport>(
         max_priority_fee_per_gas: None,
     };
 
-    let response = match web3.web3().eth().call(req, None).await {
+    let response = match web3.call(req, None).await {
         Ok(res) => res,
+        Err(err) if err.is_transient() => {
+            return Err(err).with_context(|| format!("call to {to:?} failed"));
+        }
         Err(_) => {
             // If it reverts, probably not CRC
-            return Ok(false);
+            return Ok(None);
         }
     };
 
     if response.0.len() < 32 {
-        return Ok(false);
+        return Ok(None);
     }
 
-    let returned = &response.0[12..32];
-    let hub_addr = H160::from_slice(returned);
-    Ok(circles_config.is_known_hub(hub_addr))
+    Ok(Some(H160::from_slice(&response.0[12..32])))
 }
 
+/// Classifies the sell and buy tokens of every order.
+///
+/// Orders whose token lookups fail are skipped with a warning so that solving
+/// can proceed on the remaining ones. An error is only returned if the lookups
+/// failed for every order, which indicates that the node is unreachable.
+///
+/// Every token is only looked up once per call, with up to `concurrency`
+/// lookups in flight at a time. The classified orders are sorted by uid so
+/// that the result doesn't depend on the order in which lookups complete.
 pub async fn identify_crc_orders<T: Transport>(
     web3: &Web3Provider<T>,
     circles_config: &CirclesConfig,
     orders: Vec<Order>,
+    concurrency: usize,
 ) -> Result<Vec<CRCOrderInfo>> {
+    classify_orders(web3, circles_config, orders, concurrency, None).await
+}
+
+/// Like `identify_crc_orders` but skips the lookup of tokens found in `cache`
+/// and caches the classification of the looked up ones for `ttl`.
+pub async fn identify_crc_orders_cached<T: Transport>(
+    web3: &Web3Provider<T>,
+    circles_config: &CirclesConfig,
+    orders: Vec<Order>,
+    concurrency: usize,
+    cache: &dyn ClassificationCache,
+    ttl: Duration,
+) -> Result<Vec<CRCOrderInfo>> {
+    classify_orders(web3, circles_config, orders, concurrency, Some((cache, ttl))).await
+}
+
+async fn classify_orders<T: Transport>(
+    web3: &Web3Provider<T>,
+    circles_config: &CirclesConfig,
+    orders: Vec<Order>,
+    concurrency: usize,
+    cache: Option<(&dyn ClassificationCache, Duration)>,
+) -> Result<Vec<CRCOrderInfo>> {
+    let orders: Vec<_> = orders
+        .into_iter()
+        .filter(|order| !is_self_referential(order) && !has_zero_amount(order))
+        .collect();
+    let tokens: HashSet<_> = orders
+        .iter()
+        .flat_map(|order| [order.data.sell_token, order.data.buy_token])
+        .collect();
+    let lookups: HashMap<H160, Result<Option<H160>>> = stream::iter(tokens)
+        .map(|token| async move {
+            let hub = match cache {
+                Some((cache, ttl)) => {
+                    crc_token_hub_cached(web3, circles_config, cache, ttl, token).await
+                }
+                None => crc_token_hub(web3, circles_config, token).await,
+            };
+            (token, hub)
+        })
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await;
+
     let mut result = Vec::with_capacity(orders.len());
     for order in orders {
-        let sell_is_crc = is_crc_token(web3, circles_config, order.data.sell_token).await.unwrap_or(false);
-        let buy_is_crc = is_crc_token(web3, circles_config, order.data.buy_token).await.unwrap_or(false);
-        result.push(CRCOrderInfo { order, sell_is_crc, buy_is_crc });
+        let (sell_token, buy_token) = (order.data.sell_token, order.data.buy_token);
+        match (&lookups[&sell_token], &lookups[&buy_token]) {
+            (Ok(sell_hub), Ok(buy_hub)) => {
+                result.push(CRCOrderInfo::new(order, *sell_hub, *buy_hub))
+            }
+            (Err(err), _) | (_, Err(err)) => {
+                tracing::warn!(
+                    ?err,
+                    uid = ?order.metadata.uid,
+                    "skipping order with failed CRC lookup"
+                );
+            }
+        }
+    }
+    if result.is_empty() {
+        if let Some(err) = lookups.into_values().find_map(Result::err) {
+            return Err(err.context("failed to classify any order"));
+        }
     }
+    result.sort_by_key(|info| info.order.metadata.uid);
     Ok(result)
 }
 
-pub fn match_crc_pairs(crc_orders: &[CRCOrderInfo]) -> Vec<(CRCOrderInfo, CRCOrderInfo)> {
+/// Orders selling and buying the same token can never be part of a CRC cycle
+/// and would only add self-loops to the matching graph.
+fn is_self_referential(order: &Order) -> bool {
+    if order.data.sell_token != order.data.buy_token {
+        return false;
+    }
+    tracing::warn!(uid = ?order.metadata.uid, "skipping self-referential CRC order");
+    true
+}
+
+/// Orders without a sell or buy amount can't contribute to a real match and
+/// would only add spurious edges to the matching graph.
+fn has_zero_amount(order: &Order) -> bool {
+    if !order.data.sell_amount.is_zero() && !order.data.buy_amount.is_zero() {
+        return false;
+    }
+    tracing::debug!(uid = ?order.metadata.uid, "skipping CRC order with zero amount");
+    true
+}
+
+/// Drops orders trading CRC tokens of two different hubs unless `cross_hub` is
+/// set, so that by default only tokens of the same hub get matched with each
+/// other. With `cross_hub` such orders connect the tokens of their hubs and
+/// pairs and rings can span several hubs.
+pub fn filter_cross_hub(orders: Vec<CRCOrderInfo>, cross_hub: bool) -> Vec<CRCOrderInfo> {
+    if cross_hub {
+        return orders;
+    }
+    orders
+        .into_iter()
+        .filter(|order| {
+            if !order.spans_hubs() {
+                return true;
+            }
+            tracing::debug!(uid = ?order.order.metadata.uid, "skipping cross-hub CRC order");
+            false
+        })
+        .collect()
+}
+
+/// Matches CRC orders trading in opposite directions over the same tokens.
+/// Every pair of order uids is matched at most once, even if the orders
+/// appear several times.
+///
+/// The pairs are sorted by the uids of their orders, so that the result and
+/// the settlements built from it don't depend on the order of the input.
+pub fn match_crc_pairs(crc_orders: &[CRCOrderInfo]) -> Vec<CRCPair> {
     let mut pairs = Vec::new();
+    let mut seen = HashSet::new();
     for (i, o1) in crc_orders.iter().enumerate() {
         // Must be CRC order
         if !(o1.sell_is_crc || o1.buy_is_crc) {
@@ -78,84 +583,780 @@ pub fn match_crc_pairs(crc_orders: &[CRCOrderInfo]) -> Vec<(CRCOrderInfo, CRCOrd
             let cycle = o1.order.data.buy_token == o2.order.data.sell_token &&
                         o2.order.data.buy_token == o1.order.data.sell_token;
             if cycle {
-                pairs.push((o1.clone(), o2.clone()));
+                let pair = CRCPair::ordered(o1, o2);
+                if seen.insert(pair.key()) {
+                    pairs.push(pair);
+                }
             }
         }
     }
+    pairs.sort_by_key(CRCPair::key);
     pairs
 }
 
+/// Matches orders into CRC pairs incrementally, as the CRC status of their
+/// tokens becomes known.
+///
+/// Feeding it the lookup results of all tokens yields the same pairs as
+/// `identify_crc_orders` followed by `filter_cross_hub` and `match_crc_pairs`,
+/// but every pair is produced as soon as both of its orders are classified,
+/// regardless of lookups for unrelated tokens.
+pub struct IncrementalMatcher {
+    /// Orders that are not classified yet.
+    pending: Vec<Option<Order>>,
+    /// Orders waiting for the lookup of a token.
+    waiting: HashMap<H160, Vec<usize>>,
+    /// The hubs of looked up tokens, `None` if the lookup failed.
+    tokens: HashMap<H160, Option<Option<H160>>>,
+    /// Classified orders with a CRC token.
+    classified: Vec<CRCOrderInfo>,
+    /// Keys of the pairs returned so far.
+    matched: HashSet<(OrderUid, OrderUid)>,
+    /// Whether orders spanning two hubs are matched, see `filter_cross_hub`.
+    cross_hub: bool,
+}
+
+impl IncrementalMatcher {
+    pub fn new(mut orders: Vec<Order>) -> Self {
+        orders.retain(|order| !is_self_referential(order) && !has_zero_amount(order));
+        let mut waiting: HashMap<H160, Vec<usize>> = HashMap::new();
+        for (i, order) in orders.iter().enumerate() {
+            waiting.entry(order.data.sell_token).or_default().push(i);
+            waiting.entry(order.data.buy_token).or_default().push(i);
+        }
+        Self {
+            pending: orders.into_iter().map(Some).collect(),
+            waiting,
+            tokens: HashMap::new(),
+            classified: Vec::new(),
+            matched: HashSet::new(),
+            cross_hub: false,
+        }
+    }
+
+    /// Also matches orders trading CRC tokens of different hubs.
+    pub fn with_cross_hub(mut self, cross_hub: bool) -> Self {
+        self.cross_hub = cross_hub;
+        self
+    }
+
+    /// The tokens that need to be looked up.
+    pub fn tokens(&self) -> Vec<H160> {
+        self.waiting.keys().copied().collect()
+    }
+
+    /// Records the hub lookup result for `token` (see `crc_token_hub`) and
+    /// returns the pairs formed by orders that got fully classified with it.
+    /// Orders with a failed lookup are skipped with a warning.
+    pub fn resolve(&mut self, token: H160, hub: Result<Option<H160>>) -> Vec<CRCPair> {
+        let hub = match hub {
+            Ok(hub) => Some(hub),
+            Err(err) => {
+                tracing::warn!(?err, ?token, "skipping orders with failed CRC lookup");
+                None
+            }
+        };
+        self.tokens.insert(token, hub);
+
+        let mut pairs = Vec::new();
+        for i in self.waiting.remove(&token).unwrap_or_default() {
+            let Some(order) = &self.pending[i] else {
+                continue;
+            };
+            let (Some(&sell), Some(&buy)) = (
+                self.tokens.get(&order.data.sell_token),
+                self.tokens.get(&order.data.buy_token),
+            ) else {
+                continue;
+            };
+            let order = self.pending[i].take().unwrap();
+            let (Some(sell_hub), Some(buy_hub)) = (sell, buy) else {
+                continue;
+            };
+            let info = CRCOrderInfo::new(order, sell_hub, buy_hub);
+            if !(info.sell_is_crc || info.buy_is_crc) {
+                continue;
+            }
+            if info.spans_hubs() && !self.cross_hub {
+                tracing::debug!(uid = ?info.order.metadata.uid, "skipping cross-hub CRC order");
+                continue;
+            }
+            for other in &self.classified {
+                let cycle = info.order.data.buy_token == other.order.data.sell_token
+                    && other.order.data.buy_token == info.order.data.sell_token;
+                if cycle {
+                    // Puts the smaller uid first, like `match_crc_pairs` does.
+                    let pair = CRCPair::ordered(other, &info);
+                    if self.matched.insert(pair.key()) {
+                        pairs.push(pair);
+                    }
+                }
+            }
+            self.classified.push(info);
+        }
+        pairs
+    }
+}
+
+/// Which orders win when several of them compete for the same counter-orders
+/// in `match_crc_partial_with_priority`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum MatchPriority {
+    /// Orders with the largest sell amount first.
+    #[default]
+    Largest,
+    /// The oldest orders first.
+    Fifo,
+    /// Orders with the highest fee first.
+    HighestFee,
+}
+
+impl MatchPriority {
+    /// Orders `a` before `b` if it takes precedence.
+    fn compare(self, a: &Order, b: &Order) -> std::cmp::Ordering {
+        match self {
+            Self::Largest => b.data.sell_amount.cmp(&a.data.sell_amount),
+            Self::Fifo => a.metadata.creation_date.cmp(&b.metadata.creation_date),
+            Self::HighestFee => b.data.fee_amount.cmp(&a.data.fee_amount),
+        }
+    }
+}
+
+/// Splits CRC orders against several smaller counter-orders trading in the
+/// opposite direction.
+///
+/// Orders are matched from the largest sell amount down. Every order is filled
+/// with the largest remaining counter-orders until its sell amount is used up,
+/// where each counter-order can absorb up to its buy amount. Counter-orders
+/// can contribute to several matches but are never allocated more than their
+/// buy amount in total, and orders that got matched are not used as
+/// counter-orders anymore (and vice versa).
+pub fn match_crc_partial(orders: &[CRCOrderInfo]) -> Vec<PartialMatch> {
+    match_crc_partial_with_priority(orders, MatchPriority::default())
+}
+
+/// Like `match_crc_partial` but matches orders, and picks counter-orders, in
+/// the order given by `priority` instead of by size. Ties keep the order of
+/// the input.
+pub fn match_crc_partial_with_priority(
+    orders: &[CRCOrderInfo],
+    priority: MatchPriority,
+) -> Vec<PartialMatch> {
+    let mut candidates: Vec<usize> = (0..orders.len())
+        .filter(|&i| orders[i].sell_is_crc || orders[i].buy_is_crc)
+        .collect();
+    candidates.sort_by(|&a, &b| priority.compare(&orders[a].order, &orders[b].order));
+
+    // The amount every order can still absorb as a counter-order.
+    let mut capacity: Vec<U256> = orders.iter().map(|o| o.order.data.buy_amount).collect();
+    let mut is_counter = vec![false; orders.len()];
+    let mut is_matched = vec![false; orders.len()];
+
+    let mut matches = Vec::new();
+    for &i in &candidates {
+        if is_counter[i] {
+            continue;
+        }
+        let order = &orders[i].order.data;
+        let mut remaining = order.sell_amount;
+        let mut fills = Vec::new();
+        for &j in &candidates {
+            if remaining.is_zero() {
+                break;
+            }
+            let counter = &orders[j].order.data;
+            let opposite =
+                counter.sell_token == order.buy_token && counter.buy_token == order.sell_token;
+            if j == i || is_matched[j] || !opposite || capacity[j].is_zero() {
+                continue;
+            }
+            let amount = remaining.min(capacity[j]);
+            remaining -= amount;
+            capacity[j] -= amount;
+            is_counter[j] = true;
+            fills.push(Fill { counter: orders[j].clone(), amount });
+        }
+        if !fills.is_empty() {
+            is_matched[i] = true;
+            matches.push(PartialMatch { order: orders[i].clone(), fills });
+        }
+    }
+    matches
+}
+
+/// Finds rings of CRC orders (A→B, B→C, ..., X→A) whose token flows close on
+/// themselves and can therefore be settled without external liquidity.
+///
+/// Rings contain between 2 and `max_ring_len` distinct orders, visit every
+/// token at most once and are reported once, starting from the order that
+/// appears first in `orders`. The search is a depth-first traversal of the
+/// directed sell→buy token graph which gives up after `MAX_RING_SEARCH_STEPS`
+/// edges.
+///
+/// Only the first `max_degree` orders selling any one token are considered, so
+/// a single heavily traded token cannot make the search explode.
+pub fn match_crc_rings(
+    orders: &[CRCOrderInfo],
+    max_ring_len: usize,
+    max_degree: usize,
+) -> Vec<CRCRing> {
+    let is_crc = |o: &CRCOrderInfo| o.sell_is_crc || o.buy_is_crc;
+
+    let mut by_sell_token: HashMap<H160, Vec<usize>> = HashMap::new();
+    for (i, o) in orders.iter().enumerate() {
+        if is_crc(o) {
+            by_sell_token.entry(o.order.data.sell_token).or_default().push(i);
+        }
+    }
+    for (token, edges) in &mut by_sell_token {
+        if edges.len() > max_degree {
+            tracing::warn!(
+                ?token,
+                degree = edges.len(),
+                max_degree,
+                "truncating CRC ring search edges"
+            );
+            edges.truncate(max_degree);
+        }
+    }
+
+    let mut rings = Vec::new();
+    let mut steps = 0;
+    for (start, o) in orders.iter().enumerate() {
+        if !is_crc(o) {
+            continue;
+        }
+        let mut path = vec![start];
+        search_rings(orders, &by_sell_token, max_ring_len, &mut path, &mut steps, &mut rings);
+        if steps >= MAX_RING_SEARCH_STEPS {
+            tracing::debug!(steps, "CRC ring search budget exhausted");
+            break;
+        }
+    }
+
+    rings
+        .into_iter()
+        .map(|ring| CRCRing::new(ring.into_iter().map(|i| orders[i].clone()).collect()))
+        .collect()
+}
+
+fn search_rings(
+    orders: &[CRCOrderInfo],
+    by_sell_token: &HashMap<H160, Vec<usize>>,
+    max_ring_len: usize,
+    path: &mut Vec<usize>,
+    steps: &mut usize,
+    rings: &mut Vec<Vec<usize>>,
+) {
+    let start = path[0];
+    let last = path[path.len() - 1];
+    let token = orders[last].order.data.buy_token;
+    let Some(next) = by_sell_token.get(&token) else {
+        return;
+    };
+    // Back at the first token the path can only close the ring, continuing
+    // would walk through several smaller rings.
+    let closing = token == orders[start].order.data.sell_token;
+
+    for &next in next {
+        if *steps >= MAX_RING_SEARCH_STEPS {
+            return;
+        }
+        *steps += 1;
+
+        if next == start {
+            if path.len() >= 2 {
+                rings.push(path.clone());
+            }
+            continue;
+        }
+        // Only extend through orders after the start so every ring is found
+        // exactly once, from its first order, and never through a token the
+        // path already visited.
+        let buy_token = orders[next].order.data.buy_token;
+        let revisits = path[1..].iter().any(|&i| orders[i].order.data.sell_token == buy_token);
+        if closing
+            || revisits
+            || next < start
+            || path.len() >= max_ring_len
+            || path.contains(&next)
+        {
+            continue;
+        }
+        path.push(next);
+        search_rings(orders, by_sell_token, max_ring_len, path, steps, rings);
+        path.pop();
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use ethcontract::web3::transports::Http;
-    use ethcontract::web3::Web3;
+    use ethcontract::web3::{self, error::TransportError, Web3};
+    use ethrpc::mock::MockTransport;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn address_response(address: H160) -> serde_json::Value {
+        json!(format!("0x{:0>64}", hex::encode(address)))
+    }
+
+    /// Mocks calls to tokens and to `known_hub`. `hub()` returns `known_hub`
+    /// for `members` and `non_members`, but only `members` are registered with
+    /// the hub. Calls to `failing` tokens fail with a transient error and all
+    /// other calls revert. Also returns a counter of the calls made.
+    fn mock_provider(
+        known_hub: H160,
+        members: Vec<H160>,
+        non_members: Vec<H160>,
+        failing: Vec<H160>,
+    ) -> (Web3Provider<MockTransport>, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let transport = MockTransport::new();
+        transport.mock().expect_execute().returning({
+            let calls = calls.clone();
+            move |method, params| {
+                assert_eq!(method, "eth_call");
+                calls.fetch_add(1, Ordering::SeqCst);
+                let to: H160 = serde_json::from_value(params[0]["to"].clone()).unwrap();
+                let data: Bytes = serde_json::from_value(params[0]["data"].clone()).unwrap();
+                if to == known_hub {
+                    assert_eq!(data.0[..4], TOKEN_TO_USER_SELECTOR);
+                    let token = H160::from_slice(&data.0[16..36]);
+                    let user = if members.contains(&token) {
+                        H160::from_low_u64_be(0xffff)
+                    } else {
+                        H160::zero()
+                    };
+                    Ok(address_response(user))
+                } else if members.contains(&to) || non_members.contains(&to) {
+                    assert_eq!(data.0, HUB_SELECTOR);
+                    Ok(address_response(known_hub))
+                } else if failing.contains(&to) {
+                    Err(web3::Error::Transport(TransportError::Code(503)))
+                } else {
+                    Err(web3::Error::Rpc(ethcontract::jsonrpc::Error {
+                        code: ethcontract::jsonrpc::ErrorCode::ServerError(3),
+                        message: "execution reverted".to_string(),
+                        data: None,
+                    }))
+                }
+            }
+        });
+        (Web3Provider::new(Web3::new(transport)), calls)
+    }
 
     #[tokio::test]
     async fn test_is_crc_token_mock() {
-        // This test would normally require a mock or a real endpoint.
-        // For simplicity, we assume we have a mock transport that returns a known hub address.
-
         let known_hub: H160 = "0x1111111111111111111111111111111111111111".parse().unwrap();
         let config = CirclesConfig::new(vec![known_hub]);
+        let crc: H160 = "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".parse().unwrap();
+        let other: H160 = "0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb".parse().unwrap();
+        let (web3, _) = mock_provider(known_hub, vec![crc], vec![], vec![]);
+
+        assert!(is_crc_token(&web3, &config, crc).await.unwrap());
+        // Reverting `hub()` calls mean the token is not CRC.
+        assert!(!is_crc_token(&web3, &config, other).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_is_crc_token_checks_hub_membership() {
+        let known_hub = H160::from_low_u64_be(0x1111);
+        let config = CirclesConfig::new(vec![known_hub]);
+        let member = H160::from_low_u64_be(0xa);
+        let impostor = H160::from_low_u64_be(0xb);
+        let (web3, _) = mock_provider(known_hub, vec![member], vec![impostor], vec![]);
+
+        assert!(is_hub_member_token(&web3, known_hub, member).await.unwrap());
+        assert!(!is_hub_member_token(&web3, known_hub, impostor).await.unwrap());
+
+        // Both tokens claim the known hub but only the member is CRC.
+        assert!(is_crc_token(&web3, &config, member).await.unwrap());
+        assert!(!is_crc_token(&web3, &config, impostor).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_explain_classification() {
+        let known_hub = H160::from_low_u64_be(0x1111);
+        let config = CirclesConfig::new(vec![known_hub]);
+        let crc = H160::from_low_u64_be(0xa);
+        let impostor = H160::from_low_u64_be(0xb);
+        let other = H160::from_low_u64_be(0xc);
+        let (web3, _) = mock_provider(known_hub, vec![crc], vec![impostor], vec![]);
 
-        // Mock web3 or use a Ganache dev chain with a token implementing hub().
-        // Here we just outline the structure.
-        let transport = Http::new("http://localhost:8545").unwrap();
+        let order = |sell_token, buy_token, sell_amount: u64| Order {
+            data: OrderData {
+                sell_token,
+                buy_token,
+                sell_amount: sell_amount.into(),
+                buy_amount: 1.into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let explain = |order| {
+            let (web3, config) = (&web3, &config);
+            async move { explain_classification(web3, config, &order).await.unwrap() }
+        };
+
+        assert_eq!(
+            explain(order(crc, crc, 1)).await,
+            ClassificationExplanation::SelfReferential
+        );
+        assert_eq!(
+            explain(order(crc, other, 0)).await,
+            ClassificationExplanation::ZeroAmount
+        );
+        assert_eq!(
+            explain(order(crc, other, 1)).await,
+            ClassificationExplanation::Classified {
+                sell_hub: Some(known_hub),
+                buy_hub: None,
+            }
+        );
+        assert_eq!(
+            explain(order(other, impostor, 1)).await,
+            ClassificationExplanation::NotCrc {
+                sell: TokenExplanation::NotCrcToken,
+                buy: TokenExplanation::NotRegistered { hub: known_hub },
+            }
+        );
+
+        // The same token is not CRC if its hub is not configured.
+        let unconfigured = CirclesConfig::new(vec![]);
+        assert_eq!(
+            explain_classification(&web3, &unconfigured, &order(crc, other, 1))
+                .await
+                .unwrap(),
+            ClassificationExplanation::NotCrc {
+                sell: TokenExplanation::HubNotInConfig { hub: known_hub },
+                buy: TokenExplanation::NotCrcToken,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_identify_crc_orders_with_hub_versions() {
+        let v1_hub = H160::from_low_u64_be(0x1111);
+        let v2_hub = H160::from_low_u64_be(0x2222);
+        let config = CirclesConfig::with_versions(vec![
+            (v1_hub, HubVersion::V1),
+            (v2_hub, HubVersion::V2),
+        ]);
+        let v1_token = H160::from_low_u64_be(0xa);
+        let v2_token = H160::from_low_u64_be(0xb);
+        // A v2 token claiming the v1 hub, which doesn't know about it.
+        let mislabeled = H160::from_low_u64_be(0xc);
+        let avatar = H160::from_low_u64_be(0xaaaa);
+
+        let transport = MockTransport::new();
+        transport.mock().expect_execute().returning(move |_, params| {
+            let to: H160 = serde_json::from_value(params[0]["to"].clone()).unwrap();
+            let data: Bytes = serde_json::from_value(params[0]["data"].clone()).unwrap();
+            let (selector, arg) = (&data.0[..4], data.0.get(16..36).map(H160::from_slice));
+            let response = if to == v1_hub && selector == TOKEN_TO_USER_SELECTOR {
+                if arg == Some(v1_token) {
+                    H160::from_low_u64_be(0xffff)
+                } else {
+                    H160::zero()
+                }
+            } else if to == v2_hub && selector == AVATARS_SELECTOR {
+                if arg == Some(avatar) {
+                    H160::from_low_u64_be(1)
+                } else {
+                    H160::zero()
+                }
+            } else if (to == v1_token || to == mislabeled) && selector == HUB_SELECTOR {
+                v1_hub
+            } else if to == v2_token && selector == HUB_SELECTOR {
+                v2_hub
+            } else if (to == v2_token || to == mislabeled) && selector == AVATAR_SELECTOR {
+                avatar
+            } else {
+                return Err(web3::Error::Rpc(ethcontract::jsonrpc::Error {
+                    code: ethcontract::jsonrpc::ErrorCode::ServerError(3),
+                    message: "execution reverted".to_string(),
+                    data: None,
+                }));
+            };
+            Ok(address_response(response))
+        });
         let web3 = Web3Provider::new(Web3::new(transport));
 
-        let token_addr: H160 = "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".parse().unwrap();
+        let order = |sell_token, buy_token| Order {
+            data: OrderData {
+                sell_token,
+                buy_token,
+                sell_amount: 1.into(),
+                buy_amount: 1.into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let orders = vec![order(v1_token, v2_token), order(mislabeled, v1_token)];
+
+        let result = identify_crc_orders(&web3, &config, orders, 4).await.unwrap();
+        assert!(result[0].sell_is_crc && result[0].buy_is_crc);
+        assert!(!result[1].sell_is_crc && result[1].buy_is_crc);
+    }
+
+    #[tokio::test]
+    async fn test_identify_crc_orders_caches_lookups() {
+        let known_hub = H160::from_low_u64_be(0x1111);
+        let config = CirclesConfig::new(vec![known_hub]);
+        let member = H160::from_low_u64_be(0xa);
+        let other = H160::from_low_u64_be(0xc);
+        let (web3, calls) = mock_provider(known_hub, vec![member], vec![], vec![]);
+
+        let order = Order {
+            data: OrderData {
+                sell_token: member,
+                buy_token: other,
+                sell_amount: 1.into(),
+                buy_amount: 1.into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let orders = vec![order.clone(), order.clone(), order];
 
-        // In a real test, we'd deploy a mock CRC token contract and call.
-        // Here we just show structure:
-        let result = is_crc_token(&web3, &config, token_addr).await;
-        // Without real contract, can't fully verify. But we can assert no panic.
-        assert!(result.is_ok());
+        let result = identify_crc_orders(&web3, &config, orders, 4).await.unwrap();
+        assert_eq!(result.len(), 3);
+        assert!(result.iter().all(|o| o.sell_is_crc && !o.buy_is_crc));
+        // `hub()` and `tokenToUser()` for the member and `hub()` for the other
+        // token, each only once.
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_crc_token_cache() {
+        let cache = CrcTokenCache::new(2);
+        let (crc, other, third) = (
+            H160::from_low_u64_be(0xa),
+            H160::from_low_u64_be(0xb),
+            H160::from_low_u64_be(0xc),
+        );
+        let hub = Some(H160::from_low_u64_be(0x1111));
+
+        assert_eq!(cache.get(crc).await, None);
+        cache.put(crc, hub, Duration::from_secs(60)).await;
+        cache.put(other, None, Duration::from_secs(60)).await;
+        assert_eq!(cache.get(crc).await, Some(hub));
+        // Tokens that aren't CRC are cached as well.
+        assert_eq!(cache.get(other).await, Some(None));
+
+        // The oldest token is evicted once the capacity is exceeded.
+        cache.put(third, None, Duration::from_secs(60)).await;
+        assert_eq!(cache.get(crc).await, None);
+        assert_eq!(cache.get(third).await, Some(None));
+    }
+
+    #[tokio::test]
+    async fn test_crc_token_cache_expires_entries() {
+        let cache = CrcTokenCache::new(10);
+        let crc = H160::from_low_u64_be(0xa);
+        let hub = Some(H160::from_low_u64_be(0x1111));
+
+        cache.put(crc, hub, Duration::from_millis(10)).await;
+        assert_eq!(cache.get(crc).await, Some(hub));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(cache.get(crc).await, None);
     }
 
     #[tokio::test]
     async fn test_identify_crc_orders() {
         let known_hub: H160 = "0x1111111111111111111111111111111111111111".parse().unwrap();
         let config = CirclesConfig::new(vec![known_hub]);
-
-        let transport = Http::new("http://localhost:8545").unwrap();
-        let web3 = Web3Provider::new(Web3::new(transport));
+        let (web3, _) = mock_provider(known_hub, vec![], vec![], vec![]);
 
         let order = Order {
             data: OrderData {
                 sell_token: H160::zero(),
-                buy_token: H160::zero(),
+                buy_token: H160::from_low_u64_be(1),
+                sell_amount: 1.into(),
+                buy_amount: 1.into(),
                 ..Default::default()
             },
             ..Default::default()
         };
 
         let orders = vec![order.clone()];
-        let result = identify_crc_orders(&web3, &config, orders).await.unwrap();
+        let result = identify_crc_orders(&web3, &config, orders, 4).await.unwrap();
         assert_eq!(result.len(), 1);
-        // Without a real contract, we can't assert true or false reliably,
-        // but we ensure no panic and correct structure.
         assert!(!result[0].sell_is_crc);
         assert!(!result[0].buy_is_crc);
     }
 
+    #[tokio::test]
+    async fn test_identify_crc_orders_skips_self_referential_orders() {
+        let known_hub = H160::from_low_u64_be(0x1111);
+        let config = CirclesConfig::new(vec![known_hub]);
+        let crc = H160::from_low_u64_be(0xa);
+        let other = H160::from_low_u64_be(0xc);
+        let (web3, _) = mock_provider(known_hub, vec![crc], vec![], vec![]);
+
+        let order = |sell_token, buy_token| Order {
+            data: OrderData {
+                sell_token,
+                buy_token,
+                sell_amount: 1.into(),
+                buy_amount: 1.into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let orders = vec![order(crc, crc), order(crc, other)];
+
+        let result = identify_crc_orders(&web3, &config, orders, 4).await.unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].order.data.buy_token, other);
+    }
+
+    #[tokio::test]
+    async fn test_identify_crc_orders_skips_zero_amount_orders() {
+        let known_hub = H160::from_low_u64_be(0x1111);
+        let config = CirclesConfig::new(vec![known_hub]);
+        let crc = H160::from_low_u64_be(0xa);
+        let other = H160::from_low_u64_be(0xc);
+        let (web3, _) = mock_provider(known_hub, vec![crc], vec![], vec![]);
+
+        let order = |sell_token, buy_token, sell_amount: u64| Order {
+            data: OrderData {
+                sell_token,
+                buy_token,
+                sell_amount: sell_amount.into(),
+                buy_amount: 1.into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let orders = vec![order(crc, other, 1), order(other, crc, 0)];
+
+        let result = identify_crc_orders(&web3, &config, orders, 4).await.unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].order.data.sell_token, crc);
+        assert!(match_crc_pairs(&result).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_identify_crc_orders_sorts_by_uid() {
+        let known_hub = H160::from_low_u64_be(0x1111);
+        let config = CirclesConfig::new(vec![known_hub]);
+        let crc = H160::from_low_u64_be(0xa);
+        let (web3, _) = mock_provider(known_hub, vec![crc], vec![], vec![]);
+
+        // Every order uses its own token, so the lookups complete in an order
+        // unrelated to the order uids.
+        let orders: Vec<_> = (0..50u8)
+            .rev()
+            .map(|i| Order {
+                data: OrderData {
+                    sell_token: crc,
+                    buy_token: H160::from_low_u64_be(0x100 + u64::from(i)),
+                    sell_amount: 1.into(),
+                    buy_amount: 1.into(),
+                    ..Default::default()
+                },
+                metadata: model::order::OrderMetadata {
+                    uid: OrderUid([i; 56]),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .collect();
+
+        for concurrency in [1, 8, 64] {
+            let result = identify_crc_orders(&web3, &config, orders.clone(), concurrency)
+                .await
+                .unwrap();
+            let uids: Vec<_> = result.iter().map(|o| o.order.metadata.uid).collect();
+            let expected: Vec<_> = (0..50u8).map(|i| OrderUid([i; 56])).collect();
+            assert_eq!(uids, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_identify_crc_orders_skips_failed_lookups() {
+        let known_hub = H160::from_low_u64_be(0x1111);
+        let config = CirclesConfig::new(vec![known_hub]);
+        let crc = H160::from_low_u64_be(0xa);
+        let broken = H160::from_low_u64_be(0xb);
+        let other = H160::from_low_u64_be(0xc);
+        let (web3, _) = mock_provider(known_hub, vec![crc], vec![], vec![broken]);
+
+        let order = |sell_token, buy_token| Order {
+            data: OrderData {
+                sell_token,
+                buy_token,
+                sell_amount: 1.into(),
+                buy_amount: 1.into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let orders = vec![order(crc, other), order(broken, other), order(other, crc)];
+
+        let result = identify_crc_orders(&web3, &config, orders, 4).await.unwrap();
+        assert_eq!(result.len(), 2);
+        assert!(result[0].sell_is_crc && !result[0].buy_is_crc);
+        assert!(!result[1].sell_is_crc && result[1].buy_is_crc);
+    }
+
+    #[tokio::test]
+    async fn test_identify_crc_orders_fails_when_all_lookups_fail() {
+        let known_hub = H160::from_low_u64_be(0x1111);
+        let config = CirclesConfig::new(vec![known_hub]);
+        let broken = H160::from_low_u64_be(0xb);
+        let also_broken = H160::from_low_u64_be(0xd);
+        let (web3, _) = mock_provider(known_hub, vec![], vec![], vec![broken, also_broken]);
+
+        let orders = vec![Order {
+            data: OrderData {
+                sell_token: broken,
+                buy_token: also_broken,
+                sell_amount: 1.into(),
+                buy_amount: 1.into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }];
+
+        assert!(identify_crc_orders(&web3, &config, orders, 4).await.is_err());
+    }
+
     #[cfg(test)]
     mod pair_tests {
         use super::*;
         use model::order::{Order, OrderData};
 
         fn mock_crc_order(sell: H160, buy: H160, sell_is_crc: bool, buy_is_crc: bool) -> CRCOrderInfo {
+            mock_crc_order_with_amounts(sell, buy, 0.into(), 0.into(), sell_is_crc, buy_is_crc)
+        }
+
+        fn mock_crc_order_with_amounts(
+            sell: H160,
+            buy: H160,
+            sell_amount: U256,
+            buy_amount: U256,
+            sell_is_crc: bool,
+            buy_is_crc: bool,
+        ) -> CRCOrderInfo {
             CRCOrderInfo {
                 order: Order {
                     data: OrderData {
                         sell_token: sell,
                         buy_token: buy,
+                        sell_amount,
+                        buy_amount,
                         ..Default::default()
                     },
                     ..Default::default()
                 },
                 sell_is_crc,
                 buy_is_crc,
+                sell_hub: None,
+                buy_hub: None,
             }
         }
 
@@ -172,6 +1373,143 @@ mod tests {
             assert_eq!(pairs.len(), 1);
         }
 
+        #[test]
+        fn test_match_crc_pairs_partial_overlap() {
+            let a: H160 = "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".parse().unwrap();
+            let b: H160 = "0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb".parse().unwrap();
+            // The first order sells more of `a` than the second order buys.
+            let o1 = mock_crc_order_with_amounts(a, b, 100.into(), 50.into(), true, true);
+            let o2 = mock_crc_order_with_amounts(b, a, 40.into(), 60.into(), true, true);
+
+            let pairs = match_crc_pairs(&[o1.clone(), o2.clone()]);
+            assert_eq!(pairs.len(), 1);
+            assert_eq!(pairs[0].tokens, (a, b));
+            assert_eq!(pairs[0].matchable_amount, 60.into());
+
+            // And the other way around.
+            let o1 = mock_crc_order_with_amounts(a, b, 30.into(), 15.into(), true, true);
+            let pairs = match_crc_pairs(&[o1, o2]);
+            assert_eq!(pairs[0].matchable_amount, 30.into());
+        }
+
+        #[test]
+        fn test_match_crc_pairs_respects_order_kinds() {
+            let a = H160::from_low_u64_be(0xa);
+            let b = H160::from_low_u64_be(0xb);
+            let with_kind = |mut info: CRCOrderInfo, kind| {
+                info.order.data.kind = kind;
+                info
+            };
+
+            // The buy order takes exactly its buy amount of `a`.
+            let sell = mock_crc_order_with_amounts(a, b, 100.into(), 50.into(), true, true);
+            let buy = mock_crc_order_with_amounts(b, a, 40.into(), 60.into(), true, true);
+            let pairs = match_crc_pairs(&[
+                with_kind(sell, OrderKind::Sell),
+                with_kind(buy, OrderKind::Buy),
+            ]);
+            assert_eq!(pairs[0].matchable_amount, 60.into());
+
+            // The sell order's buy amount of `a` is only a minimum, but it
+            // sells only 40 `b`, which the buy order pays 80 `a` for.
+            let buy = mock_crc_order_with_amounts(a, b, 100.into(), 50.into(), true, true);
+            let sell = mock_crc_order_with_amounts(b, a, 40.into(), 60.into(), true, true);
+            let pairs = match_crc_pairs(&[
+                with_kind(buy, OrderKind::Buy),
+                with_kind(sell, OrderKind::Sell),
+            ]);
+            assert_eq!(pairs[0].matchable_amount, 80.into());
+
+            // Selling more `b` than the buy order wants leaves it the limit.
+            let buy = mock_crc_order_with_amounts(a, b, 100.into(), 50.into(), true, true);
+            let sell = mock_crc_order_with_amounts(b, a, 70.into(), 60.into(), true, true);
+            let pairs = match_crc_pairs(&[
+                with_kind(buy, OrderKind::Buy),
+                with_kind(sell, OrderKind::Sell),
+            ]);
+            assert_eq!(pairs[0].matchable_amount, 100.into());
+        }
+
+        #[test]
+        fn test_match_crc_pairs_deduplicates_resubmitted_orders() {
+            let a = H160::from_low_u64_be(0xa);
+            let b = H160::from_low_u64_be(0xb);
+            let with_uid = |mut info: CRCOrderInfo, uid: u8| {
+                info.order.metadata.uid = OrderUid([uid; 56]);
+                info
+            };
+            let o1 = with_uid(mock_crc_order(a, b, true, false), 1);
+            let o2 = with_uid(mock_crc_order(b, a, false, true), 2);
+            // The same pair-forming orders submitted twice.
+            let orders = vec![o1.clone(), o2.clone(), o1, o2];
+
+            let pairs = match_crc_pairs(&orders);
+            assert_eq!(pairs.len(), 1);
+            assert_eq!(pairs[0].first.order.metadata.uid, OrderUid([1; 56]));
+            assert_eq!(pairs[0].second.order.metadata.uid, OrderUid([2; 56]));
+        }
+
+        #[test]
+        fn test_match_crc_pairs_is_deterministic() {
+            let a = H160::from_low_u64_be(0xa);
+            let b = H160::from_low_u64_be(0xb);
+            let c = H160::from_low_u64_be(0xc);
+            let with_uid = |mut info: CRCOrderInfo, uid: u8| {
+                info.order.metadata.uid = OrderUid([uid; 56]);
+                info
+            };
+            let orders = vec![
+                with_uid(mock_crc_order(a, b, true, false), 5),
+                with_uid(mock_crc_order(b, a, false, true), 2),
+                with_uid(mock_crc_order(a, c, true, false), 4),
+                with_uid(mock_crc_order(c, a, false, true), 1),
+                with_uid(mock_crc_order(b, a, false, true), 3),
+            ];
+            let uids = |orders: &[CRCOrderInfo]| {
+                match_crc_pairs(orders)
+                    .into_iter()
+                    .map(|pair| (pair.first.order.metadata.uid, pair.second.order.metadata.uid))
+                    .collect::<Vec<_>>()
+            };
+
+            let expected = uids(&orders);
+            assert_eq!(
+                expected,
+                [(1, 4), (2, 5), (3, 5)]
+                    .map(|(first, second)| (OrderUid([first; 56]), OrderUid([second; 56])))
+            );
+            for shuffle in [[4, 3, 2, 1, 0], [2, 0, 4, 1, 3], [1, 4, 0, 3, 2]] {
+                let shuffled: Vec<_> = shuffle.iter().map(|&i| orders[i].clone()).collect();
+                assert_eq!(uids(&shuffled), expected);
+            }
+        }
+
+        #[test]
+        fn test_match_crc_pairs_respects_direction() {
+            let a = H160::from_low_u64_be(0xa);
+            let b = H160::from_low_u64_be(0xb);
+            let with_uid = |mut info: CRCOrderInfo, uid: u8| {
+                info.order.metadata.uid = OrderUid([uid; 56]);
+                info
+            };
+
+            // Both orders trade the same unordered token pair, but in the same
+            // direction, so they don't form a cycle.
+            let same_direction = [
+                with_uid(mock_crc_order(a, b, true, false), 1),
+                with_uid(mock_crc_order(a, b, true, false), 2),
+            ];
+            assert!(match_crc_pairs(&same_direction).is_empty());
+
+            let opposite_directions = [
+                with_uid(mock_crc_order(a, b, true, false), 1),
+                with_uid(mock_crc_order(b, a, false, true), 2),
+            ];
+            let pairs = match_crc_pairs(&opposite_directions);
+            assert_eq!(pairs.len(), 1);
+            assert_eq!(pairs[0].tokens, (a, b));
+        }
+
         #[test]
         fn test_no_pairs() {
             let a: H160 = "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".parse().unwrap();
@@ -185,5 +1523,225 @@ mod tests {
             let pairs = match_crc_pairs(&orders);
             assert!(pairs.is_empty());
         }
+
+        #[test]
+        fn test_match_crc_partial() {
+            let a = H160::from_low_u64_be(0xa);
+            let b = H160::from_low_u64_be(0xb);
+            let orders = vec![
+                mock_crc_order_with_amounts(b, a, 60.into(), 60.into(), false, true),
+                mock_crc_order_with_amounts(a, b, 100.into(), 100.into(), true, false),
+                mock_crc_order_with_amounts(b, a, 60.into(), 60.into(), false, true),
+            ];
+
+            let matches = match_crc_partial(&orders);
+            assert_eq!(matches.len(), 1);
+            let large = &matches[0];
+            assert_eq!(large.order.order.data.sell_amount, 100.into());
+            assert_eq!(large.filled_amount(), 100.into());
+
+            let amounts: Vec<_> = large.fills.iter().map(|fill| fill.amount).collect();
+            assert_eq!(amounts, vec![60.into(), 40.into()]);
+            // 20 units are left on the second counter-order.
+            let second = &large.fills[1];
+            assert_eq!(second.counter.order.data.buy_amount - second.amount, 20.into());
+        }
+
+        #[test]
+        fn test_match_crc_partial_respects_priority() {
+            let a = H160::from_low_u64_be(0xa);
+            let b = H160::from_low_u64_be(0xb);
+            let order = |sell_amount: u64, created: u64, fee_amount: u64, sell_is_crc| {
+                let (sell, buy) = if sell_is_crc { (a, b) } else { (b, a) };
+                let mut order = mock_crc_order_with_amounts(
+                    sell,
+                    buy,
+                    sell_amount.into(),
+                    100.into(),
+                    sell_is_crc,
+                    !sell_is_crc,
+                );
+                order.order.data.fee_amount = fee_amount.into();
+                order.order.metadata.creation_date =
+                    (std::time::UNIX_EPOCH + Duration::from_secs(created)).into();
+                order
+            };
+            // The first order comes first with every priority. Each of the
+            // others takes precedence with one of them and can fill it alone.
+            let orders = vec![
+                order(100, 0, 10, true),
+                order(50, 3, 1, false),
+                order(20, 1, 2, false),
+                order(30, 2, 5, false),
+            ];
+
+            for (priority, expected) in [
+                (MatchPriority::Largest, 50),
+                (MatchPriority::Fifo, 20),
+                (MatchPriority::HighestFee, 30),
+            ] {
+                let matches = match_crc_partial_with_priority(&orders, priority);
+                assert_eq!(matches.len(), 1);
+                assert_eq!(matches[0].order.order.data.sell_amount, 100.into());
+                let counters: Vec<_> = matches[0]
+                    .fills
+                    .iter()
+                    .map(|fill| fill.counter.order.data.sell_amount)
+                    .collect();
+                assert_eq!(counters, vec![expected.into()], "{priority:?}");
+            }
+            assert_eq!(
+                match_crc_partial(&orders)[0].fills[0].counter.order.data.sell_amount,
+                50.into()
+            );
+        }
+
+        #[test]
+        fn test_match_crc_partial_does_not_double_allocate() {
+            let a = H160::from_low_u64_be(0xa);
+            let b = H160::from_low_u64_be(0xb);
+            let orders = vec![
+                mock_crc_order_with_amounts(a, b, 100.into(), 100.into(), true, false),
+                mock_crc_order_with_amounts(a, b, 90.into(), 90.into(), true, false),
+                mock_crc_order_with_amounts(b, a, 60.into(), 130.into(), false, true),
+            ];
+
+            let matches = match_crc_partial(&orders);
+            assert_eq!(matches.len(), 2);
+            assert_eq!(matches[0].filled_amount(), 100.into());
+            // Only the 30 units the counter-order has left can go to the
+            // second order.
+            assert_eq!(matches[1].order.order.data.sell_amount, 90.into());
+            assert_eq!(matches[1].filled_amount(), 30.into());
+
+            let allocated = matches
+                .iter()
+                .flat_map(|m| &m.fills)
+                .fold(U256::zero(), |total, fill| total + fill.amount);
+            assert_eq!(allocated, 130.into());
+        }
+
+        #[test]
+        fn test_match_crc_rings_three_orders() {
+            let a = H160::from_low_u64_be(0xa);
+            let b = H160::from_low_u64_be(0xb);
+            let c = H160::from_low_u64_be(0xc);
+            let orders = vec![
+                mock_crc_order(a, b, true, true),
+                mock_crc_order(b, c, true, true),
+                mock_crc_order(c, a, true, true),
+            ];
+
+            let rings = match_crc_rings(&orders, 3, 10);
+            assert_eq!(rings.len(), 1);
+            let tokens: Vec<_> = rings[0]
+                .orders
+                .iter()
+                .map(|o| (o.order.data.sell_token, o.order.data.buy_token))
+                .collect();
+            assert_eq!(tokens, vec![(a, b), (b, c), (c, a)]);
+        }
+
+        #[test]
+        fn test_match_crc_rings_four_orders() {
+            let a = H160::from_low_u64_be(0xa);
+            let b = H160::from_low_u64_be(0xb);
+            let c = H160::from_low_u64_be(0xc);
+            let d = H160::from_low_u64_be(0xd);
+            let orders = vec![
+                mock_crc_order(c, d, true, false),
+                mock_crc_order(a, b, true, false),
+                mock_crc_order(d, a, true, false),
+                mock_crc_order(b, c, true, false),
+            ];
+
+            // The ring is too long for a limit of 3.
+            assert!(match_crc_rings(&orders, 3, 10).is_empty());
+
+            let rings = match_crc_rings(&orders, 4, 10);
+            assert_eq!(rings.len(), 1);
+            assert_eq!(rings[0].orders.len(), 4);
+            assert_eq!(rings[0].orders[0].order.data.sell_token, c);
+        }
+
+        #[test]
+        fn test_match_crc_rings_balanced_ring() {
+            let a = H160::from_low_u64_be(0xa);
+            let b = H160::from_low_u64_be(0xb);
+            let c = H160::from_low_u64_be(0xc);
+            let orders = vec![
+                mock_crc_order_with_amounts(a, b, 10.into(), 20.into(), true, true),
+                mock_crc_order_with_amounts(b, c, 20.into(), 30.into(), true, true),
+                mock_crc_order_with_amounts(c, a, 30.into(), 10.into(), true, true),
+            ];
+
+            let rings = match_crc_rings(&orders, 3, 10);
+            assert_eq!(rings.len(), 1);
+            let expected: [(H160, U256); 3] = [(a, 10.into()), (b, 20.into()), (c, 30.into())];
+            assert_eq!(rings[0].matched_volume, HashMap::from(expected));
+            assert!(rings[0].surplus.is_empty());
+            assert!(rings[0].deficit.is_empty());
+        }
+
+        #[test]
+        fn test_match_crc_rings_imbalanced_ring() {
+            let a = H160::from_low_u64_be(0xa);
+            let b = H160::from_low_u64_be(0xb);
+            let c = H160::from_low_u64_be(0xc);
+            // The ring sells 15 `a` but only buys 10, and buys 25 `b` but
+            // only sells 20.
+            let orders = vec![
+                mock_crc_order_with_amounts(a, b, 15.into(), 25.into(), true, true),
+                mock_crc_order_with_amounts(b, c, 20.into(), 30.into(), true, true),
+                mock_crc_order_with_amounts(c, a, 30.into(), 10.into(), true, true),
+            ];
+
+            let rings = match_crc_rings(&orders, 3, 10);
+            assert_eq!(rings.len(), 1);
+            let expected: [(H160, U256); 3] = [(a, 10.into()), (b, 20.into()), (c, 30.into())];
+            assert_eq!(rings[0].matched_volume, HashMap::from(expected));
+            assert_eq!(rings[0].surplus, HashMap::from([(a, 5.into())]));
+            assert_eq!(rings[0].deficit, HashMap::from([(b, 5.into())]));
+        }
+
+        #[test]
+        fn test_match_crc_rings_no_ring() {
+            let a = H160::from_low_u64_be(0xa);
+            let b = H160::from_low_u64_be(0xb);
+            let c = H160::from_low_u64_be(0xc);
+            let d = H160::from_low_u64_be(0xd);
+            let orders = vec![
+                mock_crc_order(a, b, true, false),
+                mock_crc_order(b, c, true, false),
+                mock_crc_order(c, d, true, false),
+            ];
+
+            assert!(match_crc_rings(&orders, 4, 10).is_empty());
+        }
+
+        #[test]
+        fn test_match_crc_rings_truncates_high_degree_tokens() {
+            // A star around `center`: every spoke token is sold for and bought
+            // with `center`, forming one two-order ring per spoke.
+            let center = H160::from_low_u64_be(0xc);
+            let orders: Vec<_> = (0..1_000)
+                .flat_map(|i| {
+                    let spoke = H160::from_low_u64_be(0x1000 + i);
+                    [
+                        mock_crc_order(center, spoke, true, false),
+                        mock_crc_order(spoke, center, false, true),
+                    ]
+                })
+                .collect();
+
+            // Only the first 10 orders selling `center` are explored.
+            let rings = match_crc_rings(&orders, 4, 10);
+            assert_eq!(rings.len(), 10);
+            for (i, ring) in rings.iter().enumerate() {
+                assert_eq!(ring.orders.len(), 2);
+                let spoke = H160::from_low_u64_be(0x1000 + i as u64);
+                assert_eq!(ring.orders[0].order.data.buy_token, spoke);
+            }
+        }
     }
 } 
\ No newline at end of file
@@ -1,66 +1,491 @@
 // File: crates/solver/src/solver/mod.rs
 
 pub mod circles_detection;
+pub mod residual_routing;
+pub mod simulation;
 pub mod web3_provider;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use futures::stream::{self, Stream, StreamExt};
+use num::BigRational;
+use std::cmp::Reverse;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use ethcontract::H160;
+use model::auction::AuctionId;
 use model::order::Order;
+use model::TokenPair;
+use shared::conversions::U256Ext;
+use shared::external_prices::ExternalPrices;
+use shared::sources::uniswap_v2::pair_provider::PairProvider;
 use crate::config::circles_config::CirclesConfig;
-use crate::solver::circles_detection::{identify_crc_orders, match_crc_pairs, CRCOrderInfo};
+use crate::solver::circles_detection::{
+    crc_token_hub_cached, filter_cross_hub, identify_crc_orders_cached, match_crc_pairs, CRCPair,
+    ClassificationCache, CrcTokenCache, IncrementalMatcher,
+};
+use crate::solver::simulation::{
+    encode_pair_settlement, PairSimulation, SimulationConfig, SimulationOutcome, SimulationReport,
+};
 use crate::solver::web3_provider::Web3Provider; // Assume this exists and is imported
 use ethcontract::web3::transports::Http;
-use ethcontract::web3::Web3;
+use ethcontract::web3::types::{BlockId, BlockNumber, Bytes, CallRequest};
+use ethcontract::web3::{Transport, Web3};
+
+/// Default maximum number of concurrent token lookups.
+const MAX_CONCURRENT_TOKEN_LOOKUPS: usize = 16;
+
+/// Default number of tokens whose classification is remembered.
+const DEFAULT_TOKEN_CACHE_SIZE: usize = 10_000;
+
+/// Default time after which a token gets classified again, so that newly
+/// registered CRC tokens are eventually picked up.
+const DEFAULT_TOKEN_CACHE_TTL: Duration = Duration::from_secs(600);
 
 // The solver struct or main entry could already exist, we add solve_orders logic here:
-pub struct Solver {
-    web3: Arc<Web3Provider<Http>>,
+pub struct Solver<T: Transport = Http, C: ClassificationCache = CrcTokenCache> {
+    web3: Arc<Web3Provider<T>>,
     circles_config: CirclesConfig,
+    metrics: Metrics,
+    simulation: Option<SimulationConfig>,
+    lookup_concurrency: usize,
+    liquidity_check: Option<LiquidityCheckConfig>,
+    token_cache: C,
+    token_cache_ttl: Duration,
+    max_orders: Option<usize>,
+}
+
+/// Where to look for Uniswap V2 like pools backing matched CRC pairs.
+pub struct LiquidityCheckConfig {
+    pub web3: ethrpc::Web3,
+    pub pair_provider: PairProvider,
 }
 
-impl Solver {
-    pub fn new(web3: Arc<Web3Provider<Http>>, circles_config: CirclesConfig) -> Self {
-        Solver { web3, circles_config }
+impl<T: Transport> Solver<T> {
+    pub fn new(web3: Arc<Web3Provider<T>>, circles_config: CirclesConfig) -> Self {
+        let metrics = Metrics::instance(observe::metrics::get_storage_registry())
+            .unwrap()
+            .clone();
+        Solver {
+            web3,
+            circles_config,
+            metrics,
+            simulation: None,
+            lookup_concurrency: MAX_CONCURRENT_TOKEN_LOOKUPS,
+            liquidity_check: None,
+            token_cache: CrcTokenCache::new(DEFAULT_TOKEN_CACHE_SIZE),
+            token_cache_ttl: DEFAULT_TOKEN_CACHE_TTL,
+            max_orders: None,
+        }
+    }
+
+    /// Creates a solver whose CRC detection metrics are registered with the
+    /// given registry instead of the global one.
+    pub fn with_metrics_registry(
+        web3: Arc<Web3Provider<T>>,
+        circles_config: CirclesConfig,
+        registry: &prometheus::Registry,
+    ) -> Result<Self> {
+        let metrics = Metrics::new(registry)?;
+        Ok(Solver {
+            web3,
+            circles_config,
+            metrics,
+            simulation: None,
+            lookup_concurrency: MAX_CONCURRENT_TOKEN_LOOKUPS,
+            liquidity_check: None,
+            token_cache: CrcTokenCache::new(DEFAULT_TOKEN_CACHE_SIZE),
+            token_cache_ttl: DEFAULT_TOKEN_CACHE_TTL,
+            max_orders: None,
+        })
+    }
+
+    /// Remembers the classification of up to `capacity` tokens for `ttl`
+    /// across `solve_orders` calls, replacing the previously cached ones.
+    pub fn with_token_cache(mut self, capacity: usize, ttl: Duration) -> Self {
+        self.token_cache = CrcTokenCache::new(capacity);
+        self.token_cache_ttl = ttl;
+        self
+    }
+}
+
+impl<T: Transport, C: ClassificationCache> Solver<T, C> {
+    /// Stores token classifications in `cache` for `ttl` instead of in memory,
+    /// e.g. to share them across solver instances.
+    pub fn with_classification_cache<D: ClassificationCache>(
+        self,
+        cache: D,
+        ttl: Duration,
+    ) -> Solver<T, D> {
+        Solver {
+            web3: self.web3,
+            circles_config: self.circles_config,
+            metrics: self.metrics,
+            simulation: self.simulation,
+            lookup_concurrency: self.lookup_concurrency,
+            liquidity_check: self.liquidity_check,
+            token_cache: cache,
+            token_cache_ttl: ttl,
+            max_orders: self.max_orders,
+        }
+    }
+
+    /// Limits how many token lookups run concurrently, to avoid overwhelming
+    /// the node.
+    pub fn with_lookup_concurrency(mut self, concurrency: usize) -> Self {
+        self.lookup_concurrency = concurrency;
+        self
+    }
+
+    /// Warns about matched pairs without a pool for their tokens, which could
+    /// settle the part of the cycle that doesn't net out. Such pairs are still
+    /// returned.
+    pub fn with_liquidity_check(mut self, config: LiquidityCheckConfig) -> Self {
+        self.liquidity_check = Some(config);
+        self
+    }
+
+    /// Simulates the settlement of every matched pair and drops the pairs
+    /// that would revert.
+    pub fn with_simulation(mut self, config: SimulationConfig) -> Self {
+        self.simulation = Some(config);
+        self
+    }
+
+    /// Only considers the `max_orders` orders of an auction with the largest
+    /// sell value, to bound the work spent on huge auctions.
+    pub fn with_max_orders(mut self, max_orders: usize) -> Self {
+        self.max_orders = Some(max_orders);
+        self
+    }
+
+    /// Drops the orders with the smallest sell value exceeding `max_orders`.
+    /// Orders are ranked by the native value of their sell amount, and orders
+    /// selling tokens without a price rank below all priced ones, by sell
+    /// amount.
+    fn truncate_orders(&self, mut orders: Vec<Order>, prices: &ExternalPrices) -> Vec<Order> {
+        let Some(max_orders) = self.max_orders else {
+            return orders;
+        };
+        if orders.len() <= max_orders {
+            return orders;
+        }
+        tracing::warn!(
+            orders = orders.len(),
+            max_orders,
+            "auction exceeds the maximum number of orders; dropping the smallest ones"
+        );
+        orders.sort_by_cached_key(|order| {
+            let native_value: Option<BigRational> = prices
+                .price(&order.data.sell_token)
+                .map(|price| order.data.sell_amount.to_big_rational() * price);
+            Reverse((native_value, order.data.sell_amount))
+        });
+        orders.truncate(max_orders);
+        orders
     }
 
     /// Integrate CRC order detection and matching into the solver pipeline.
-    /// For now, we only log/store the pairs.
-    pub async fn solve_orders(&self, orders: Vec<Order>) -> Result<()> {
+    /// Returns the matched pairs so that callers can build settlements from them.
+    ///
+    /// Runs in a span carrying `auction_id`, so that the logs of concurrently
+    /// solved auctions can be told apart. `prices` are the auction's native
+    /// prices, used to pick the orders to keep if there are more than
+    /// `max_orders`.
+    #[tracing::instrument(skip(self, orders, prices))]
+    pub async fn solve_orders(
+        &self,
+        auction_id: AuctionId,
+        orders: Vec<Order>,
+        prices: &ExternalPrices,
+    ) -> Result<Vec<CRCPair>> {
+        let start = Instant::now();
+        let orders = self.truncate_orders(orders, prices);
+        self.metrics.crc_orders_seen.inc_by(orders.len() as u64);
+
         // Step 1: Identify CRC orders
-        let crc_orders = identify_crc_orders(self.web3.as_ref(), &self.circles_config, orders).await?;
+        let crc_orders = identify_crc_orders_cached(
+            self.web3.as_ref(),
+            &self.circles_config,
+            orders,
+            self.lookup_concurrency,
+            &self.token_cache,
+            self.token_cache_ttl,
+        )
+        .await?;
+        let identified = crc_orders.iter().filter(|o| o.sell_is_crc || o.buy_is_crc).count();
+        self.metrics.crc_orders_identified.inc_by(identified as u64);
 
         // Step 2: Match CRC pairs
+        let crc_orders = filter_cross_hub(crc_orders, self.circles_config.cross_hub);
         let pairs = match_crc_pairs(&crc_orders);
+        self.metrics.crc_pairs_matched.inc_by(pairs.len() as u64);
+        self.metrics.crc_detection_seconds.observe(start.elapsed().as_secs_f64());
 
-        // Just log how many pairs we found (or store them in a variable)
-        println!("Found {} CRC pairs", pairs.len());
+        tracing::debug!(pairs = pairs.len(), "found CRC pairs");
+        self.check_liquidity(&pairs).await;
 
-        Ok(())
+        // Step 3: Drop pairs that would not settle
+        if self.simulation.is_none() {
+            return Ok(pairs);
+        }
+        let matched = pairs.len();
+        let pairs = self.simulate_pairs(&pairs).await?.successful_pairs();
+        tracing::debug!(dropped = matched - pairs.len(), "simulated CRC pairs");
+
+        Ok(pairs)
+    }
+
+    /// Like `solve_orders` but yields every pair as soon as both of its orders
+    /// are classified, so that settling can start before all tokens were
+    /// looked up. Token lookups run concurrently and a slow lookup only holds
+    /// back the pairs involving that token.
+    ///
+    /// Orders with failed lookups are skipped, and so are pairs failing
+    /// simulation if it is enabled. Unlike `solve_orders` the stream does not
+    /// fail if all lookups fail, it just ends without pairs.
+    ///
+    /// Every poll of the stream runs in a span carrying `auction_id`, like
+    /// `solve_orders` does.
+    pub fn solve_orders_streaming(
+        &self,
+        auction_id: AuctionId,
+        orders: Vec<Order>,
+        prices: &ExternalPrices,
+    ) -> impl Stream<Item = CRCPair> + '_ {
+        let span = tracing::info_span!("solve_orders_streaming", auction_id);
+        let orders = span.in_scope(|| self.truncate_orders(orders, prices));
+        self.metrics.crc_orders_seen.inc_by(orders.len() as u64);
+        let mut matcher =
+            IncrementalMatcher::new(orders).with_cross_hub(self.circles_config.cross_hub);
+        let pairs = stream::iter(matcher.tokens())
+            .map(move |token| async move {
+                let hub = crc_token_hub_cached(
+                    self.web3.as_ref(),
+                    &self.circles_config,
+                    &self.token_cache,
+                    self.token_cache_ttl,
+                    token,
+                )
+                .await;
+                (token, hub)
+            })
+            .buffer_unordered(self.lookup_concurrency.max(1))
+            .flat_map(move |(token, hub)| stream::iter(matcher.resolve(token, hub)))
+            .inspect(move |_| self.metrics.crc_pairs_matched.inc())
+            .then(move |pair| async move {
+                self.check_liquidity(std::slice::from_ref(&pair)).await;
+                pair
+            })
+            .filter_map(move |pair| async move {
+                if self.simulation.is_none() {
+                    return Some(pair);
+                }
+                match self.simulate_pairs(std::slice::from_ref(&pair)).await {
+                    Ok(report) => report.successful_pairs().pop(),
+                    Err(err) => {
+                        tracing::warn!(?err, "dropping CRC pair that failed to simulate");
+                        None
+                    }
+                }
+            });
+        let mut pairs = Box::pin(pairs);
+        stream::poll_fn(move |cx| {
+            let _entered = span.enter();
+            pairs.as_mut().poll_next(cx)
+        })
+    }
+
+    async fn check_liquidity(&self, pairs: &[CRCPair]) {
+        let Some(config) = &self.liquidity_check else {
+            return;
+        };
+        for pair in pairs {
+            let Some(tokens) = TokenPair::new(pair.tokens.0, pair.tokens.1) else {
+                continue;
+            };
+            match config.pair_provider.pair_address_checked(&config.web3, &tokens).await {
+                Ok(Some(_)) => {}
+                Ok(None) => {
+                    self.metrics.crc_pairs_without_liquidity.inc();
+                    tracing::warn!(
+                        first = ?pair.first.order.metadata.uid,
+                        second = ?pair.second.order.metadata.uid,
+                        ?tokens,
+                        "CRC pair has no external liquidity backstop"
+                    );
+                }
+                Err(err) => tracing::warn!(?err, ?tokens, "failed to check CRC pair liquidity"),
+            }
+        }
+    }
+
+    /// Simulates settling every pair on its own against the latest block.
+    /// Fails if simulation is not configured or the node cannot be reached.
+    pub async fn simulate_pairs(&self, pairs: &[CRCPair]) -> Result<SimulationReport> {
+        let config = self.simulation.context("settlement simulation is not configured")?;
+        let mut report = SimulationReport::default();
+        for pair in pairs {
+            let outcome = match encode_pair_settlement(pair, config.settlement_contract) {
+                Ok(calldata) => self.simulate_settlement(&config, calldata).await?,
+                Err(err) => SimulationOutcome::Failed(format!("{err:#}")),
+            };
+            report.pairs.push(PairSimulation { pair: pair.clone(), outcome });
+        }
+        Ok(report)
+    }
+
+    async fn simulate_settlement(
+        &self,
+        config: &SimulationConfig,
+        calldata: Bytes,
+    ) -> Result<SimulationOutcome> {
+        let req = CallRequest {
+            from: Some(config.solver),
+            to: Some(config.settlement_contract),
+            data: Some(calldata),
+            ..Default::default()
+        };
+        match self.web3.call(req, Some(BlockId::Number(BlockNumber::Latest))).await {
+            Ok(_) => Ok(SimulationOutcome::Success),
+            Err(err) if err.is_transient() => Err(err).context("settlement simulation failed"),
+            Err(err) => Ok(SimulationOutcome::Failed(err.to_string())),
+        }
     }
 }
 
+#[derive(Clone, prometheus_metric_storage::MetricStorage)]
+struct Metrics {
+    /// Number of orders checked for CRC tokens.
+    crc_orders_seen: prometheus::IntCounter,
+
+    /// Number of orders selling or buying a CRC token.
+    crc_orders_identified: prometheus::IntCounter,
+
+    /// Number of matched CRC order pairs.
+    crc_pairs_matched: prometheus::IntCounter,
+
+    /// Number of matched CRC order pairs without a pool for their tokens.
+    crc_pairs_without_liquidity: prometheus::IntCounter,
+
+    /// Time spent identifying and matching CRC orders.
+    crc_detection_seconds: prometheus::Histogram,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::config::circles_config::CirclesConfig;
     use crate::solver::web3_provider::Web3Provider;
-    use ethcontract::web3::transports::Http;
-    use model::order::{Order, OrderData};
+    use ethcontract::jsonrpc::{Call, Params};
+    use ethcontract::web3::RequestId;
+    use ethrpc::mock::MockTransport;
+    use futures::future::{BoxFuture, FutureExt};
+    use model::order::{Order, OrderData, OrderMetadata, OrderUid};
+    use serde_json::json;
     use std::str::FromStr;
 
-    // Mock web3 provider for testing
-    fn mock_web3_provider() -> Arc<Web3Provider<Http>> {
-        // In a real test you could use a local Ganache or a mock transport.
-        // For simplicity, we just create a provider that points to localhost.
-        let transport = Http::new("http://localhost:8545").unwrap();
-        Arc::new(Web3Provider::new(Web3::new(transport)))
+    // Mock web3 provider for testing: `hub()` returns `hub` for `crc_tokens`
+    // and reverts for every other token. The hub reports every token as
+    // belonging to a member.
+    fn mock_web3_provider(hub: H160, crc_tokens: Vec<H160>) -> Arc<Web3Provider<MockTransport>> {
+        mock_web3_provider_with_hubs(vec![(hub, crc_tokens)])
+    }
+
+    // Like `mock_web3_provider` but with several hubs, each with its own CRC
+    // tokens.
+    fn mock_web3_provider_with_hubs(
+        hubs: Vec<(H160, Vec<H160>)>,
+    ) -> Arc<Web3Provider<MockTransport>> {
+        Arc::new(Web3Provider::new(Web3::new(mock_transport_with_hubs(hubs))))
+    }
+
+    // The transport behind `mock_web3_provider_with_hubs`.
+    fn mock_transport_with_hubs(hubs: Vec<(H160, Vec<H160>)>) -> MockTransport {
+        let transport = MockTransport::new();
+        transport.mock().expect_execute().returning(move |_, params| {
+            let to: H160 = serde_json::from_value(params[0]["to"].clone()).unwrap();
+            let token_hub = hubs
+                .iter()
+                .find(|(_, crc_tokens)| crc_tokens.contains(&to))
+                .map(|(hub, _)| *hub);
+            if hubs.iter().any(|(hub, _)| *hub == to) {
+                Ok(json!(format!("0x{:0>64}", "ff")))
+            } else if let Some(hub) = token_hub {
+                Ok(json!(format!("0x{:0>64}", hex::encode(hub))))
+            } else {
+                Err(execution_reverted())
+            }
+        });
+        transport
+    }
+
+    // The error the node returns for a call that reverts.
+    fn execution_reverted() -> ethcontract::web3::Error {
+        ethcontract::web3::Error::Rpc(ethcontract::jsonrpc::Error {
+            code: ethcontract::jsonrpc::ErrorCode::ServerError(3),
+            message: "execution reverted".to_string(),
+            data: None,
+        })
+    }
+
+    // An order selling 1 `sell_token` for 1 `buy_token`, with a uid made of
+    // `uid` bytes. Tests that need other amounts set them on the result.
+    fn order(uid: u8, sell_token: H160, buy_token: H160) -> Order {
+        Order {
+            data: OrderData {
+                sell_token,
+                buy_token,
+                sell_amount: 1.into(),
+                buy_amount: 1.into(),
+                ..Default::default()
+            },
+            metadata: OrderMetadata {
+                uid: OrderUid([uid; 56]),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Answers like `inner` but delays the calls to `slow` by `delay`.
+    #[derive(Clone, Debug)]
+    struct DelayedTransport {
+        inner: MockTransport,
+        slow: H160,
+        delay: Duration,
+    }
+
+    impl Transport for DelayedTransport {
+        type Out = BoxFuture<'static, ethcontract::web3::Result<serde_json::Value>>;
+
+        fn prepare(&self, method: &str, params: Vec<serde_json::Value>) -> (RequestId, Call) {
+            self.inner.prepare(method, params)
+        }
+
+        fn send(&self, id: RequestId, call: Call) -> Self::Out {
+            let to = match &call {
+                Call::MethodCall(call) => match &call.params {
+                    Params::Array(params) => params
+                        .first()
+                        .and_then(|params| serde_json::from_value(params["to"].clone()).ok()),
+                    _ => None,
+                },
+                _ => None,
+            };
+            let delay = if to == Some(self.slow) { self.delay } else { Duration::ZERO };
+            let response = self.inner.send(id, call);
+            async move {
+                tokio::time::sleep(delay).await;
+                response.await
+            }
+            .boxed()
+        }
     }
 
     #[tokio::test]
     async fn test_solve_orders_no_crc() {
-        let web3 = mock_web3_provider();
+        let web3 = mock_web3_provider(H160::zero(), vec![]);
         let config = CirclesConfig::new(vec![]);
         let solver = Solver::new(web3, config);
 
@@ -69,33 +494,34 @@ mod tests {
             data: OrderData {
                 sell_token: H160::from_str("0x0000000000000000000000000000000000000001").unwrap(),
                 buy_token: H160::from_str("0x0000000000000000000000000000000000000002").unwrap(),
+                sell_amount: 1.into(),
+                buy_amount: 1.into(),
                 ..Default::default()
             },
             ..Default::default()
         };
 
         let orders = vec![order];
-        let result = solver.solve_orders(orders).await;
-        assert!(result.is_ok());
-        // Just ensure it doesn't panic and prints "Found 0 CRC pairs".
+        let pairs = solver.solve_orders(1, orders, &Default::default()).await.unwrap();
         // Since no CRC token/hub known, no pairs found.
+        assert!(pairs.is_empty());
     }
 
     #[tokio::test]
     async fn test_solve_orders_crc_pairs() {
-        let web3 = mock_web3_provider();
-        // Suppose we have a known hub, and identify_crc_orders might detect CRC tokens.
-        // In practice, you'd mock or set up the scenario. For this test, we rely on the logic
-        // that if identify_crc_orders and match_crc_pairs run, we won't panic.
         let known_hub: H160 = H160::from_low_u64_be(0x1111);
+        let crc_token: H160 = H160::from_low_u64_be(0x3333);
+        let web3 = mock_web3_provider(known_hub, vec![crc_token]);
         let config = CirclesConfig::new(vec![known_hub]);
         let solver = Solver::new(web3, config);
 
         // Create orders that might form a CRC pair.
         let order1 = Order {
             data: OrderData {
-                sell_token: known_hub, // pretend this token maps to CRC
+                sell_token: crc_token,
                 buy_token: H160::from_low_u64_be(0x2222),
+                sell_amount: 1.into(),
+                buy_amount: 1.into(),
                 ..Default::default()
             },
             ..Default::default()
@@ -104,16 +530,416 @@ mod tests {
         let order2 = Order {
             data: OrderData {
                 sell_token: H160::from_low_u64_be(0x2222),
-                buy_token: known_hub, // opposite direction, forms a cycle
+                buy_token: crc_token, // opposite direction, forms a cycle
+                sell_amount: 1.into(),
+                buy_amount: 1.into(),
                 ..Default::default()
             },
             ..Default::default()
         };
 
         let orders = vec![order1, order2];
-        let result = solver.solve_orders(orders).await;
-        assert!(result.is_ok());
-        // If CRC pairs are identified, it prints how many pairs. Check no panic.
+        let pairs = solver.solve_orders(1, orders, &Default::default()).await.unwrap();
+        assert_eq!(pairs.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_solve_orders_drops_pairs_failing_simulation() {
+        let known_hub = H160::from_low_u64_be(0x1111);
+        let settlement_contract = H160::from_low_u64_be(0x9999);
+        let tokens = [0xaa, 0xbb, 0xcc, 0xdd].map(H160::repeat_byte);
+        let reverting = tokens[3];
+
+        // Like `mock_web3_provider` but settlements containing `reverting`
+        // revert and all others succeed.
+        let transport = MockTransport::new();
+        transport.mock().expect_execute().returning(move |_, params| {
+            let to: H160 = serde_json::from_value(params[0]["to"].clone()).unwrap();
+            let data: Bytes = serde_json::from_value(params[0]["data"].clone()).unwrap();
+            if to == known_hub {
+                Ok(json!(format!("0x{:0>64}", "ff")))
+            } else if tokens.contains(&to) {
+                Ok(json!(format!("0x{:0>64}", hex::encode(known_hub))))
+            } else if to == settlement_contract
+                && !data.0.windows(20).any(|word| word == reverting.as_bytes())
+            {
+                Ok(json!("0x"))
+            } else {
+                Err(execution_reverted())
+            }
+        });
+        let web3 = Arc::new(Web3Provider::new(Web3::new(transport)));
+        let config = CirclesConfig::new(vec![known_hub]);
+        let solver = Solver::new(web3, config).with_simulation(SimulationConfig {
+            settlement_contract,
+            solver: H160::from_low_u64_be(0x5555),
+        });
+
+        let orders = vec![
+            order(1, tokens[0], tokens[1]),
+            order(2, tokens[1], tokens[0]),
+            order(3, tokens[2], reverting),
+            order(4, reverting, tokens[2]),
+        ];
+
+        let pairs = solver.solve_orders(1, orders, &Default::default()).await.unwrap();
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].tokens, (tokens[0], tokens[1]));
+    }
+
+    #[tokio::test]
+    async fn test_solve_orders_streaming_matches_batch() {
+        let known_hub = H160::from_low_u64_be(0x1111);
+        let crc_a = H160::from_low_u64_be(0xa);
+        let crc_b = H160::from_low_u64_be(0xb);
+        let other = H160::from_low_u64_be(0xc);
+        let web3 = mock_web3_provider(known_hub, vec![crc_a, crc_b]);
+        let solver = Solver::new(web3, CirclesConfig::new(vec![known_hub]));
+
+        let orders: Vec<_> = [
+            (crc_a, other),
+            (other, crc_b),
+            (other, crc_a),
+            (crc_b, other),
+            (crc_a, other),
+            (other, H160::from_low_u64_be(0xd)),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, (sell_token, buy_token))| order(i as u8, sell_token, buy_token))
+        .collect();
+
+        let uids = |pairs: Vec<CRCPair>| {
+            let mut uids: Vec<_> = pairs
+                .into_iter()
+                .map(|pair| (pair.first.order.metadata.uid, pair.second.order.metadata.uid))
+                .collect();
+            uids.sort();
+            uids
+        };
+        let prices = ExternalPrices::default();
+        let batch = solver.solve_orders(1, orders.clone(), &prices).await.unwrap();
+        let streamed: Vec<_> = solver.solve_orders_streaming(1, orders, &prices).collect().await;
+        assert_eq!(batch.len(), 3);
+        assert_eq!(uids(streamed), uids(batch));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_solve_orders_streaming_does_not_wait_for_slow_lookups() {
+        let known_hub = H160::from_low_u64_be(0x1111);
+        let (crc, other, slow) = (
+            H160::from_low_u64_be(0xa),
+            H160::from_low_u64_be(0xb),
+            H160::from_low_u64_be(0xc),
+        );
+        let delay = Duration::from_secs(10);
+        let transport = DelayedTransport {
+            inner: mock_transport_with_hubs(vec![(known_hub, vec![crc])]),
+            slow,
+            delay,
+        };
+        let web3 = Arc::new(Web3Provider::new(Web3::new(transport)));
+        let solver = Solver::new(web3, CirclesConfig::new(vec![known_hub]));
+
+        // The pair of the slow token comes first in the auction.
+        let orders = vec![
+            order(1, crc, slow),
+            order(2, slow, crc),
+            order(3, crc, other),
+            order(4, other, crc),
+        ];
+        let prices = ExternalPrices::default();
+        let start = tokio::time::Instant::now();
+        let mut pairs = Box::pin(solver.solve_orders_streaming(1, orders, &prices));
+        let uids = |pair: &CRCPair| (pair.first.order.metadata.uid, pair.second.order.metadata.uid);
+
+        let first = pairs.next().await.unwrap();
+        assert_eq!(uids(&first), (OrderUid([3; 56]), OrderUid([4; 56])));
+        assert!(start.elapsed() < delay);
+
+        let second = pairs.next().await.unwrap();
+        assert_eq!(uids(&second), (OrderUid([1; 56]), OrderUid([2; 56])));
+        assert!(start.elapsed() >= delay);
+        assert!(pairs.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_solve_orders_matches_across_hubs_if_enabled() {
+        let (hub_a, hub_b) = (H160::from_low_u64_be(0x1111), H160::from_low_u64_be(0x2222));
+        let (crc_a, crc_b) = (H160::from_low_u64_be(0xa), H160::from_low_u64_be(0xb));
+        // Both orders trade a token of one hub for a token of the other.
+        let orders = vec![order(1, crc_a, crc_b), order(2, crc_b, crc_a)];
+        let prices = ExternalPrices::default();
+
+        for cross_hub in [false, true] {
+            let web3 =
+                mock_web3_provider_with_hubs(vec![(hub_a, vec![crc_a]), (hub_b, vec![crc_b])]);
+            let config = CirclesConfig::new(vec![hub_a, hub_b]).with_cross_hub(cross_hub);
+            let solver = Solver::new(web3, config);
+
+            let pairs = solver.solve_orders(1, orders.clone(), &prices).await.unwrap();
+            let streamed: Vec<_> =
+                solver.solve_orders_streaming(1, orders.clone(), &prices).collect().await;
+            let expected = if cross_hub { 1 } else { 0 };
+            assert_eq!(pairs.len(), expected, "cross_hub: {cross_hub}");
+            assert_eq!(streamed.len(), expected, "cross_hub: {cross_hub}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_solve_orders_warns_about_pairs_without_liquidity() {
+        let known_hub = H160::from_low_u64_be(0x1111);
+        let (crc, other) = (H160::from_low_u64_be(0xa), H160::from_low_u64_be(0xb));
+        let web3 = mock_web3_provider(known_hub, vec![crc]);
+
+        // No pool is deployed for any pair.
+        let transport = MockTransport::new();
+        transport.mock().expect_execute().returning(|method, _| {
+            assert_eq!(method, "eth_getCode");
+            Ok(json!("0x"))
+        });
+        let registry = prometheus::Registry::new();
+        let config = CirclesConfig::new(vec![known_hub]);
+        let solver = Solver::with_metrics_registry(web3, config, &registry)
+            .unwrap()
+            .with_liquidity_check(LiquidityCheckConfig {
+                web3: ethrpc::Web3::new(ethrpc::Web3Transport::new(transport)),
+                pair_provider: PairProvider::new(H160::from_low_u64_be(0xfa), [0; 32]),
+            });
+
+        let orders = vec![order(1, crc, other), order(2, other, crc)];
+
+        let (events, _guard) = crate::test_util::Events::capture();
+        let pairs = solver.solve_orders(1, orders, &Default::default()).await.unwrap();
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(solver.metrics.crc_pairs_without_liquidity.get(), 1);
+        assert!(events.warned("CRC pair has no external liquidity backstop"));
+    }
+
+    #[tokio::test]
+    async fn test_solve_orders_caches_token_lookups() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let known_hub = H160::from_low_u64_be(0x1111);
+        let crc = H160::from_low_u64_be(0xa);
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        // Like `mock_web3_provider` but counts the calls.
+        let transport = MockTransport::new();
+        let counter = calls.clone();
+        transport.mock().expect_execute().returning(move |_, params| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let to: H160 = serde_json::from_value(params[0]["to"].clone()).unwrap();
+            if to == known_hub {
+                Ok(json!(format!("0x{:0>64}", "ff")))
+            } else if to == crc {
+                Ok(json!(format!("0x{:0>64}", hex::encode(known_hub))))
+            } else {
+                Err(execution_reverted())
+            }
+        });
+        let web3 = Arc::new(Web3Provider::new(Web3::new(transport)));
+        let solver = Solver::new(web3, CirclesConfig::new(vec![known_hub]));
+
+        let (other, new) = (H160::from_low_u64_be(0xb), H160::from_low_u64_be(0xc));
+        let prices = ExternalPrices::default();
+
+        solver
+            .solve_orders(1, vec![order(1, crc, other), order(2, other, crc)], &prices)
+            .await
+            .unwrap();
+        let first = calls.swap(0, Ordering::SeqCst);
+        // The CRC token needs its `hub()` and the hub's membership check.
+        assert_eq!(first, 3);
+
+        let orders = vec![order(3, crc, other), order(4, other, crc), order(5, crc, new)];
+        let pairs = solver.solve_orders(2, orders, &prices).await.unwrap();
+        assert_eq!(pairs.len(), 1);
+        // Only the new token is looked up.
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_solve_orders_truncates_large_auctions() {
+        let registry = prometheus::Registry::new();
+        let known_hub = H160::from_low_u64_be(0x1111);
+        let crc = H160::from_low_u64_be(0xa);
+        let (large, small) = (H160::from_low_u64_be(0xb), H160::from_low_u64_be(0xc));
+        let web3 = mock_web3_provider(known_hub, vec![crc]);
+        let config = CirclesConfig::new(vec![known_hub]);
+        let solver = Solver::with_metrics_registry(web3, config, &registry)
+            .unwrap()
+            .with_max_orders(2);
+
+        let mut orders = vec![
+            order(1, crc, small),
+            order(2, crc, large),
+            order(3, small, crc),
+            order(4, large, crc),
+        ];
+        // The orders trading `large` are ten times as large as the others.
+        for order in &mut orders {
+            if [order.data.sell_token, order.data.buy_token].contains(&large) {
+                order.data.sell_amount = 10.into();
+                order.data.buy_amount = 10.into();
+            }
+        }
+
+        // Without prices the orders are ranked by sell amount.
+        let pairs = solver.solve_orders(1, orders, &Default::default()).await.unwrap();
+        // Only the two largest orders are considered, so the small ones are
+        // not matched.
+        assert_eq!(pairs.len(), 1);
+        let mut uids = [pairs[0].first.order.metadata.uid, pairs[0].second.order.metadata.uid];
+        uids.sort();
+        assert_eq!(uids, [OrderUid([2; 56]), OrderUid([4; 56])]);
+        assert_eq!(solver.metrics.crc_orders_seen.get(), 2);
+    }
+
+    #[test]
+    fn test_truncation_ranks_orders_by_native_value() {
+        let registry = prometheus::Registry::new();
+        let web3 = mock_web3_provider(H160::zero(), vec![]);
+        let solver = Solver::with_metrics_registry(web3, CirclesConfig::new(vec![]), &registry)
+            .unwrap()
+            .with_max_orders(2);
+
+        let (usdc, cheap, unpriced) = (
+            H160::from_low_u64_be(0xa),
+            H160::from_low_u64_be(0xb),
+            H160::from_low_u64_be(0xc),
+        );
+        let prices = ExternalPrices::new(
+            H160::from_low_u64_be(0xee),
+            [
+                // A token with 6 decimals worth 1/2000 ETH.
+                (usdc, BigRational::new(1.into(), 2_000_000_000.into())),
+                // A token with 18 decimals worth 1/1_000_000 ETH.
+                (cheap, BigRational::new(1.into(), 10_u128.pow(24).into())),
+            ]
+            .into(),
+        )
+        .unwrap();
+        let orders: Vec<_> = [
+            // Worth 0.001 ETH.
+            (order(1, cheap, usdc), 1_000 * 10_u128.pow(18)),
+            // The largest amount, but without a price.
+            (order(2, unpriced, usdc), 10_u128.pow(30)),
+            // Worth 1 ETH.
+            (order(3, usdc, cheap), 2_000 * 10_u128.pow(6)),
+        ]
+        .into_iter()
+        .map(|(mut order, sell_amount)| {
+            order.data.sell_amount = sell_amount.into();
+            order
+        })
+        .collect();
+
+        let kept = solver.truncate_orders(orders, &prices);
+        let uids: Vec<_> = kept.iter().map(|order| order.metadata.uid).collect();
+        assert_eq!(uids, [OrderUid([3; 56]), OrderUid([1; 56])]);
+    }
+
+    #[tokio::test]
+    async fn test_solve_orders_records_metrics() {
+        let registry = prometheus::Registry::new();
+        let config = CirclesConfig::new(vec![]);
+        let web3 = mock_web3_provider(H160::zero(), vec![]);
+        let solver = Solver::with_metrics_registry(web3, config, &registry).unwrap();
+
+        let orders = vec![Order::default(), Order::default()];
+        solver.solve_orders(1, orders, &Default::default()).await.unwrap();
+
+        let families = registry.gather();
+        let metric = |name: &str| {
+            families
+                .iter()
+                .find(|family| family.get_name() == name)
+                .unwrap()
+                .get_metric()[0]
+                .clone()
+        };
+        assert_eq!(metric("crc_orders_seen").get_counter().get_value(), 2.);
+        assert_eq!(metric("crc_orders_identified").get_counter().get_value(), 0.);
+        assert_eq!(metric("crc_pairs_matched").get_counter().get_value(), 0.);
+        assert_eq!(
+            metric("crc_detection_seconds")
+                .get_histogram()
+                .get_sample_count(),
+            1
+        );
+    }
+
+    /// Records the fields of every span created while it is installed.
+    #[derive(Clone, Default)]
+    struct SpanFields(Arc<std::sync::Mutex<Vec<(String, String, String)>>>);
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for SpanFields {
+        fn on_new_span(
+            &self,
+            attrs: &tracing::span::Attributes<'_>,
+            _: &tracing::span::Id,
+            _: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            struct Visitor<'a>(&'a str, &'a mut Vec<(String, String, String)>);
+            impl tracing::field::Visit for Visitor<'_> {
+                fn record_debug(
+                    &mut self,
+                    field: &tracing::field::Field,
+                    value: &dyn std::fmt::Debug,
+                ) {
+                    let (span, field) = (self.0.to_string(), field.name().to_string());
+                    self.1.push((span, field, format!("{value:?}")));
+                }
+            }
+            let mut fields = self.0.lock().unwrap();
+            attrs.record(&mut Visitor(attrs.metadata().name(), &mut fields));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_solve_orders_span_carries_auction_id() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let spans = SpanFields::default();
+        let _guard = tracing::subscriber::set_default(
+            tracing_subscriber::registry().with(spans.clone()),
+        );
+        let web3 = mock_web3_provider(H160::zero(), vec![]);
+        let solver = Solver::new(web3, CirclesConfig::new(vec![]));
+
+        solver.solve_orders(42, vec![Order::default()], &Default::default()).await.unwrap();
+
+        let spans = spans.0.lock().unwrap();
+        assert!(spans.contains(&(
+            "solve_orders".to_string(),
+            "auction_id".to_string(),
+            "42".to_string()
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_solve_orders_streaming_span_carries_auction_id() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let spans = SpanFields::default();
+        let _guard = tracing::subscriber::set_default(
+            tracing_subscriber::registry().with(spans.clone()),
+        );
+        let web3 = mock_web3_provider(H160::zero(), vec![]);
+        let solver = Solver::new(web3, CirclesConfig::new(vec![]));
+
+        let orders = vec![Order::default()];
+        let pairs: Vec<_> =
+            solver.solve_orders_streaming(42, orders, &Default::default()).collect().await;
+        assert!(pairs.is_empty());
+
+        let spans = spans.0.lock().unwrap();
+        assert!(spans.contains(&(
+            "solve_orders_streaming".to_string(),
+            "auction_id".to_string(),
+            "42".to_string()
+        )));
     }
 }
 
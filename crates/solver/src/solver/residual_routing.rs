@@ -0,0 +1,223 @@
+use anyhow::Result;
+use ethcontract::{H160, U256};
+use model::TokenPair;
+use shared::baseline_solver::BaselineSolvable;
+use shared::external_prices::ExternalPrices;
+use shared::http_solver::model::TokenAmount;
+use shared::recent_block_cache::Block;
+use shared::sources::uniswap_v2::pool_fetching::PoolFetching;
+use std::collections::HashMap;
+use crate::liquidity::slippage::SlippageCalculator;
+use crate::solver::circles_detection::CRCRing;
+
+/// A swap through an external pool that settles the residual imbalance of a
+/// CRC ring.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ResidualSwap {
+    /// The pool swapped through.
+    pub pool: H160,
+    /// The surplus of the ring, which is sold in full.
+    pub sell: TokenAmount,
+    /// The amount the pool returns for `sell`.
+    pub buy: TokenAmount,
+    /// The amount bought after the buy side slippage, which still covers the
+    /// deficit of the ring.
+    pub min_buy: U256,
+}
+
+/// How the residual imbalance of a CRC ring can be settled.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ResidualRoute {
+    /// The ring nets out by itself.
+    Balanced,
+    /// The residual is settled by swapping the surplus for the deficit.
+    Swap(ResidualSwap),
+    /// There is no pool for the surplus and deficit tokens.
+    NoLiquidity { sell_token: H160, buy_token: H160 },
+    /// The best pool doesn't cover the deficit once slippage is accounted for.
+    ExceedsSlippage { min_buy: U256, deficit: TokenAmount },
+    /// The residual spans more than one surplus or deficit token, which would
+    /// need more than a single swap.
+    Unsupported,
+}
+
+/// A CRC ring together with the external swap needed to settle it.
+#[derive(Clone, Debug)]
+pub struct RoutedRing {
+    pub ring: CRCRing,
+    pub route: ResidualRoute,
+}
+
+impl RoutedRing {
+    /// Whether the ring can be settled, either on its own or with its swap.
+    pub fn is_settleable(&self) -> bool {
+        matches!(self.route, ResidualRoute::Balanced | ResidualRoute::Swap(_))
+    }
+}
+
+/// Routes the residual imbalance of `ring` through the baseline pools of
+/// `pools`. The surplus token the ring sells more of than it buys is swapped
+/// in full through the pool returning the most of the deficit token, and the
+/// swap settles the ring if what it returns still covers the deficit after
+/// the buy side slippage of `slippage`.
+pub async fn route_ring_residual(
+    ring: CRCRing,
+    pools: &dyn PoolFetching,
+    slippage: &SlippageCalculator,
+    prices: &ExternalPrices,
+    block: Block,
+) -> Result<RoutedRing> {
+    let route = route_residual(&ring, pools, slippage, prices, block).await?;
+    Ok(RoutedRing { ring, route })
+}
+
+async fn route_residual(
+    ring: &CRCRing,
+    pools: &dyn PoolFetching,
+    slippage: &SlippageCalculator,
+    prices: &ExternalPrices,
+    block: Block,
+) -> Result<ResidualRoute> {
+    let amounts = |residual: &HashMap<H160, U256>| -> Vec<TokenAmount> {
+        residual.iter().map(|(&token, &amount)| TokenAmount::new(token, amount)).collect()
+    };
+    let (surplus, deficit) = (amounts(&ring.surplus), amounts(&ring.deficit));
+    let (surplus, deficit) = match (surplus.as_slice(), deficit.as_slice()) {
+        ([], []) => return Ok(ResidualRoute::Balanced),
+        ([surplus], [deficit]) => (surplus, deficit),
+        _ => return Ok(ResidualRoute::Unsupported),
+    };
+    let no_liquidity = ResidualRoute::NoLiquidity {
+        sell_token: surplus.token,
+        buy_token: deficit.token,
+    };
+    let Some(pair) = TokenPair::new(surplus.token, deficit.token) else {
+        return Ok(no_liquidity);
+    };
+
+    let best = pools
+        .fetch([pair].into(), block)
+        .await?
+        .into_iter()
+        .filter_map(|pool| {
+            let input = (surplus.amount, surplus.token);
+            let out = BaselineSolvable::get_amount_out(&pool, deficit.token, input)?;
+            Some((pool.address, out))
+        })
+        .max_by_key(|(_, out)| *out);
+    let Some((pool, out)) = best else {
+        return Ok(no_liquidity);
+    };
+
+    let buy = TokenAmount::new(deficit.token, out);
+    let min_buy = slippage.bounds(surplus, &buy, prices)?.min_buy;
+    if min_buy < deficit.amount {
+        tracing::debug!(
+            ?pool,
+            %min_buy,
+            deficit = %deficit.amount,
+            "CRC residual exceeds slippage"
+        );
+        return Ok(ResidualRoute::ExceedsSlippage { min_buy, deficit: deficit.clone() });
+    }
+    Ok(ResidualRoute::Swap(ResidualSwap { pool, sell: surplus.clone(), buy, min_buy }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::circles_detection::{match_crc_rings, CRCOrderInfo};
+    use model::order::{Order, OrderData};
+    use shared::sources::uniswap_v2::pool_fetching::{test_util::FakePoolFetcher, Pool};
+
+    const X: H160 = H160([0xaa; 20]);
+    const Y: H160 = H160([0xbb; 20]);
+
+    fn crc_order(sell_token: H160, buy_token: H160, sell: u128, buy: u128) -> CRCOrderInfo {
+        CRCOrderInfo {
+            order: Order {
+                data: OrderData {
+                    sell_token,
+                    buy_token,
+                    sell_amount: sell.into(),
+                    buy_amount: buy.into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            sell_is_crc: true,
+            buy_is_crc: true,
+            sell_hub: None,
+            buy_hub: None,
+        }
+    }
+
+    /// A ring that sells 10 more X than it buys and buys 10 more Y than it
+    /// sells.
+    fn imbalanced_ring() -> CRCRing {
+        let e18 = 10_u128.pow(18);
+        let orders = vec![
+            crc_order(X, Y, 100 * e18, 100 * e18),
+            crc_order(Y, X, 90 * e18, 90 * e18),
+        ];
+        match_crc_rings(&orders, 2, 10).pop().unwrap()
+    }
+
+    async fn route(pools: Vec<Pool>, ring: CRCRing) -> ResidualRoute {
+        route_ring_residual(
+            ring,
+            &FakePoolFetcher(pools),
+            &SlippageCalculator::from_bps(100, None),
+            &ExternalPrices::default(),
+            Block::Recent,
+        )
+        .await
+        .unwrap()
+        .route
+    }
+
+    #[tokio::test]
+    async fn test_routes_residual_within_slippage() {
+        let e18 = 10_u128.pow(18);
+        let pair = TokenPair::new(X, Y).unwrap();
+        // Y is worth half as much as X, so 10 X buy almost 20 Y.
+        let pool = Pool::uniswap(H160([1; 20]), pair, (1_000 * e18, 2_000 * e18));
+
+        let ResidualRoute::Swap(swap) = route(vec![pool], imbalanced_ring()).await else {
+            panic!("expected a swap");
+        };
+        assert_eq!(swap.pool, pool.address);
+        assert_eq!(swap.sell, TokenAmount::new(X, 10 * e18));
+        assert_eq!(swap.buy.token, Y);
+        assert!(swap.min_buy < swap.buy.amount);
+        assert!(swap.min_buy >= U256::from(10 * e18));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_residual_exceeding_slippage() {
+        let e18 = 10_u128.pow(18);
+        let pair = TokenPair::new(X, Y).unwrap();
+        // Y is worth twice as much as X, so 10 X only buy about 5 Y.
+        let pool = Pool::uniswap(H160([1; 20]), pair, (2_000 * e18, 1_000 * e18));
+
+        let outcome = route(vec![pool], imbalanced_ring()).await;
+        assert!(matches!(
+            outcome,
+            ResidualRoute::ExceedsSlippage { min_buy, deficit }
+                if min_buy < U256::from(10 * e18) && deficit == TokenAmount::new(Y, 10 * e18)
+        ));
+
+        assert_eq!(
+            route(vec![], imbalanced_ring()).await,
+            ResidualRoute::NoLiquidity { sell_token: X, buy_token: Y }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_balanced_ring_needs_no_route() {
+        let ring = match_crc_rings(&[crc_order(X, Y, 10, 10), crc_order(Y, X, 10, 10)], 2, 10)
+            .pop()
+            .unwrap();
+        assert_eq!(route(vec![], ring).await, ResidualRoute::Balanced);
+    }
+}
@@ -0,0 +1,152 @@
+use anyhow::{Context, Result};
+use contracts::{dummy_contract, GPv2Settlement};
+use ethcontract::{H160, U256};
+use maplit::hashmap;
+use model::order::{Order, OrderKind};
+use shared::http_solver::model::InternalizationStrategy;
+use web3::types::Bytes;
+use crate::settlement::Settlement;
+use crate::solver::circles_detection::CRCPair;
+
+/// Where and as whom matched CRC pairs get simulated.
+#[derive(Clone, Copy, Debug)]
+pub struct SimulationConfig {
+    /// The settlement contract the pairs would be settled through.
+    pub settlement_contract: H160,
+    /// The account calling `settle`. Has to be an allow-listed solver for the
+    /// call not to revert.
+    pub solver: H160,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SimulationOutcome {
+    Success,
+    /// The settlement could not be encoded or reverted, with the reason.
+    Failed(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct PairSimulation {
+    pub pair: CRCPair,
+    pub outcome: SimulationOutcome,
+}
+
+/// Per-pair results of simulating matched CRC pairs.
+#[derive(Clone, Debug, Default)]
+pub struct SimulationReport {
+    pub pairs: Vec<PairSimulation>,
+}
+
+impl SimulationReport {
+    /// Returns the pairs whose settlement simulated successfully.
+    pub fn successful_pairs(self) -> Vec<CRCPair> {
+        self.pairs
+            .into_iter()
+            .filter(|simulation| simulation.outcome == SimulationOutcome::Success)
+            .map(|simulation| simulation.pair)
+            .collect()
+    }
+}
+
+/// Encodes the calldata of a `settle` call that executes the two orders of the
+/// pair against each other for `matchable_amount`, at the limit price of the
+/// first order.
+pub fn encode_pair_settlement(pair: &CRCPair, settlement_contract: H160) -> Result<Bytes> {
+    let (first, second) = (&pair.first.order, &pair.second.order);
+    let (first_sell_token, first_buy_token) = pair.tokens;
+    let prices = hashmap! {
+        first_sell_token => first.data.buy_amount,
+        first_buy_token => first.data.sell_amount,
+    };
+    let counter_amount = counter_amount(pair).context("invalid amounts of the first order")?;
+
+    let mut settlement = Settlement::new(prices);
+    settlement.encoder.add_trade(
+        first.clone(),
+        executed_amount(first, pair.matchable_amount, counter_amount),
+        U256::zero(),
+    )?;
+    settlement.encoder.add_trade(
+        second.clone(),
+        executed_amount(second, counter_amount, pair.matchable_amount),
+        U256::zero(),
+    )?;
+    let encoded = settlement.encode(InternalizationStrategy::SkipInternalizableInteraction);
+
+    dummy_contract!(GPv2Settlement, settlement_contract)
+        .methods()
+        .settle(
+            encoded.tokens,
+            encoded.clearing_prices,
+            encoded.trades,
+            encoded.interactions,
+        )
+        .tx
+        .data
+        .context("no calldata")
+}
+
+/// The amount of the token bought by the first order of the pair that it pays
+/// `matchable_amount` with, at its limit price.
+fn counter_amount(pair: &CRCPair) -> Option<U256> {
+    let first = &pair.first.order.data;
+    pair.matchable_amount
+        .checked_mul(first.buy_amount)
+        .and_then(|amount| amount.checked_div(first.sell_amount))
+}
+
+/// The executed amount of an order selling `sold` for `bought`, which is
+/// denominated in the sell token for sell orders and the buy token for buy
+/// orders.
+fn executed_amount(order: &Order, sold: U256, bought: U256) -> U256 {
+    match order.data.kind {
+        OrderKind::Sell => sold,
+        OrderKind::Buy => bought,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::circles_detection::{match_crc_pairs, CRCOrderInfo};
+    use model::order::OrderData;
+
+    fn crc_order(
+        sell_token: H160,
+        buy_token: H160,
+        sell: u64,
+        buy: u64,
+        kind: OrderKind,
+    ) -> CRCOrderInfo {
+        CRCOrderInfo {
+            order: Order {
+                data: OrderData {
+                    sell_token,
+                    buy_token,
+                    sell_amount: sell.into(),
+                    buy_amount: buy.into(),
+                    kind,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            sell_is_crc: true,
+            buy_is_crc: true,
+            sell_hub: None,
+            buy_hub: None,
+        }
+    }
+
+    #[test]
+    fn counter_amount_never_exceeds_what_a_sell_order_sells() {
+        let (a, b) = (H160([0xaa; 20]), H160([0xbb; 20]));
+        for (buy_amount, sell_amount) in [(50, 40), (50, 70), (30, 7), (3, 1)] {
+            let buy = crc_order(a, b, 100, buy_amount, OrderKind::Buy);
+            let sell = crc_order(b, a, sell_amount, 1, OrderKind::Sell);
+            let pairs = match_crc_pairs(&[buy, sell]);
+
+            let counter_amount = counter_amount(&pairs[0]).unwrap();
+            assert!(counter_amount <= pairs[0].second.order.data.sell_amount);
+        }
+    }
+}
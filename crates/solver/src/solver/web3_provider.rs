@@ -1,16 +1,859 @@
-use ethcontract::web3::{Web3, Transport};
+use {
+    ethcontract::{
+        jsonrpc::ErrorCode,
+        web3::{
+            self,
+            error::TransportError,
+            helpers::CallFuture,
+            transports::{Either, Http, WebSocket},
+            types::{BlockId, BlockNumber, Bytes, CallRequest, U256},
+            Transport, Web3,
+        },
+    },
+    once_cell::sync::OnceCell,
+    rand::Rng,
+    std::{
+        future::Future,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    },
+    tokio::time::Instant,
+};
+
+/// Default for how old the latest block may be for a node to be considered
+/// healthy.
+const DEFAULT_MAX_HEAD_AGE: Duration = Duration::from_secs(60);
+
+/// Default for how long requests go to a fallback endpoint before the primary
+/// endpoint is tried again.
+const DEFAULT_PRIMARY_RETRY_INTERVAL: Duration = Duration::from_secs(60);
 
 #[derive(Debug)]
 pub struct Web3Provider<T: Transport> {
-    web3: Web3<T>,
+    /// The primary endpoint followed by the fallback endpoints.
+    endpoints: Vec<Web3<T>>,
+    /// Index of the endpoint requests are currently sent to.
+    active: AtomicUsize,
+    /// When requests last failed over to another endpoint.
+    failed_over_at: Mutex<Option<Instant>>,
+    primary_retry_interval: Duration,
+    retries: Retries,
+    request_timeout: Option<Duration>,
+    max_head_age: Duration,
+    chain_id: OnceCell<u64>,
+    circuit_breaker: Option<CircuitBreaker>,
+}
+
+/// Errors returned by `Web3Provider` calls.
+#[derive(Debug, thiserror::Error)]
+pub enum Web3Error {
+    #[error("request timed out after {0:?}")]
+    Timeout(Duration),
+    #[error(transparent)]
+    Rpc(#[from] web3::Error),
+    #[error("circuit breaker is open after repeated failures")]
+    CircuitOpen,
+}
+
+/// Reasons for a node to fail `Web3Provider::health_check`.
+#[derive(Debug, thiserror::Error)]
+pub enum Web3HealthError {
+    #[error("node is unreachable: {0}")]
+    Unreachable(#[source] Web3Error),
+    #[error("node is on chain {actual} instead of {expected}")]
+    WrongChain { expected: u64, actual: U256 },
+    #[error("latest block {number:?} is {age:?} old")]
+    StaleHead { number: Option<u64>, age: Duration },
+}
+
+/// Gas prices for submitting a transaction.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GasEstimate {
+    Eip1559 {
+        /// Base fee of the latest block.
+        base_fee: U256,
+        /// Tip suggested by the node.
+        max_priority_fee: U256,
+        /// Leaves room for the base fee to double before the transaction gets
+        /// included.
+        max_fee: U256,
+    },
+    /// Chains without EIP-1559 only have a single gas price.
+    Legacy { gas_price: U256 },
+}
+
+impl Web3Error {
+    /// Returns whether the error is likely temporary and the request can be
+    /// retried. Timeouts are always considered transient.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Timeout(_) | Self::CircuitOpen => true,
+            Self::Rpc(err) => is_transient(err),
+        }
+    }
+}
+
+/// State of the circuit breaker configured with
+/// `Web3Provider::with_circuit_breaker`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CircuitState {
+    /// Requests are sent to the node.
+    Closed,
+    /// Requests fail immediately with `Web3Error::CircuitOpen`.
+    Open,
+    /// The cool-down has passed and the next request is let through as a
+    /// trial.
+    HalfOpen,
+}
+
+/// Stops sending requests to a node after too many consecutive transient
+/// failures, so callers don't wait for timeouts during an outage.
+#[derive(Debug)]
+struct CircuitBreaker {
+    failure_threshold: usize,
+    cool_down: Duration,
+    inner: Mutex<CircuitBreakerInner>,
+}
+
+#[derive(Debug, Default)]
+struct CircuitBreakerInner {
+    consecutive_failures: usize,
+    opened_at: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    fn new(failure_threshold: usize, cool_down: Duration) -> Self {
+        Self {
+            failure_threshold,
+            cool_down,
+            inner: Default::default(),
+        }
+    }
+
+    fn state(&self) -> CircuitState {
+        let inner = self.inner.lock().unwrap();
+        match inner.opened_at {
+            None => CircuitState::Closed,
+            Some(opened_at) if opened_at.elapsed() < self.cool_down => CircuitState::Open,
+            Some(_) => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Returns whether a request may be sent. Letting a trial request through
+    /// restarts the cool-down, so only one trial is in flight at a time and a
+    /// cancelled trial doesn't keep the breaker half open.
+    fn try_acquire(&self) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.opened_at {
+            None => true,
+            Some(opened_at) if opened_at.elapsed() < self.cool_down => false,
+            Some(_) => {
+                inner.opened_at = Some(Instant::now());
+                true
+            }
+        }
+    }
+
+    fn record<R>(&self, result: &Result<R, Web3Error>) {
+        let mut inner = self.inner.lock().unwrap();
+        match result {
+            Err(err) if err.is_transient() => {
+                inner.consecutive_failures += 1;
+                if inner.consecutive_failures >= self.failure_threshold {
+                    if inner.opened_at.is_none() {
+                        tracing::warn!(
+                            failures = inner.consecutive_failures,
+                            "opening RPC circuit breaker"
+                        );
+                    }
+                    inner.opened_at = Some(Instant::now());
+                }
+            }
+            // Deterministic errors mean the node is responding.
+            _ => {
+                if inner.opened_at.is_some() {
+                    tracing::info!("closing RPC circuit breaker");
+                }
+                *inner = Default::default();
+            }
+        }
+    }
+}
+
+/// Policy for retrying RPC calls that failed with a transient error.
+#[derive(Clone, Copy, Debug, Default)]
+struct Retries {
+    max_retries: usize,
+    base_delay: Duration,
 }
 
 impl<T: Transport> Web3Provider<T> {
     pub fn new(web3: Web3<T>) -> Self {
-        Self { web3 }
+        Self {
+            endpoints: vec![web3],
+            active: AtomicUsize::new(0),
+            failed_over_at: Mutex::new(None),
+            primary_retry_interval: DEFAULT_PRIMARY_RETRY_INTERVAL,
+            retries: Default::default(),
+            request_timeout: None,
+            max_head_age: DEFAULT_MAX_HEAD_AGE,
+            chain_id: OnceCell::new(),
+            circuit_breaker: None,
+        }
+    }
+
+    /// Retries calls that fail with a transient error up to `max_retries`
+    /// times. The delay between attempts starts at `base_delay` and doubles
+    /// with every retry, with random jitter applied.
+    pub fn with_retries(mut self, max_retries: usize, base_delay: Duration) -> Self {
+        self.retries = Retries {
+            max_retries,
+            base_delay,
+        };
+        self
+    }
+
+    /// Fails individual requests that take longer than `timeout`. Every retry
+    /// attempt gets the full timeout again.
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how old the latest block may be for `health_check` to pass.
+    pub fn with_max_head_age(mut self, max_head_age: Duration) -> Self {
+        self.max_head_age = max_head_age;
+        self
+    }
+
+    /// Fails requests immediately with `Web3Error::CircuitOpen` for
+    /// `cool_down` after `failure_threshold` consecutive requests failed with
+    /// a transient error (after exhausting their retries). Afterwards a single
+    /// trial request is sent and closes the breaker again if it succeeds.
+    pub fn with_circuit_breaker(mut self, failure_threshold: usize, cool_down: Duration) -> Self {
+        self.circuit_breaker = Some(CircuitBreaker::new(failure_threshold, cool_down));
+        self
+    }
+
+    /// Returns the state of the circuit breaker. Always `Closed` if none is
+    /// configured.
+    pub fn circuit_state(&self) -> CircuitState {
+        self.circuit_breaker
+            .as_ref()
+            .map_or(CircuitState::Closed, CircuitBreaker::state)
+    }
+
+    /// Sends requests to the `fallbacks` endpoints, in order, when the
+    /// endpoint in use fails with a transient error. Failing over happens
+    /// immediately and doesn't count as a retry.
+    pub fn with_fallbacks(mut self, fallbacks: impl IntoIterator<Item = Web3<T>>) -> Self {
+        self.endpoints.extend(fallbacks);
+        self
+    }
+
+    /// Sets how long requests go to a fallback endpoint before the primary
+    /// endpoint is tried again.
+    pub fn with_primary_retry_interval(mut self, interval: Duration) -> Self {
+        self.primary_retry_interval = interval;
+        self
     }
 
+    /// Returns the index of the endpoint requests are currently sent to, 0 for
+    /// the primary endpoint and the position in the fallbacks plus 1 for the
+    /// others.
+    pub fn active_endpoint(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// Returns the endpoint requests are currently sent to.
     pub fn web3(&self) -> &Web3<T> {
-        &self.web3
+        &self.endpoints[self.active_endpoint()]
+    }
+
+    /// Executes an `eth_call`, retrying transient failures.
+    pub async fn call(&self, req: CallRequest, block: Option<BlockId>) -> Result<Bytes, Web3Error> {
+        self.retry(|web3| web3.eth().call(req.clone(), block)).await
+    }
+
+    /// Returns the id of the node's chain. It never changes, so it is only
+    /// requested from the node until the first success and served from memory
+    /// afterwards.
+    pub async fn chain_id(&self) -> Result<u64, Web3Error> {
+        if let Some(chain_id) = self.chain_id.get() {
+            return Ok(*chain_id);
+        }
+        let chain_id = self.retry(|web3| web3.eth().chain_id()).await?;
+        let chain_id = u64::try_from(chain_id).map_err(|_| {
+            web3::Error::InvalidResponse(format!("chain id {chain_id} does not fit into 64 bits"))
+        })?;
+        // Concurrent callers may have requested it as well, they all got the
+        // same chain id.
+        Ok(*self.chain_id.get_or_init(|| chain_id))
+    }
+
+    /// Verifies that the node is reachable, on the expected chain and keeps
+    /// up with it, i.e. its latest block is recent.
+    pub async fn health_check(&self, expected_chain_id: u64) -> Result<(), Web3HealthError> {
+        let chain_id = self
+            .chain_id()
+            .await
+            .map_err(Web3HealthError::Unreachable)?;
+        if chain_id != expected_chain_id {
+            return Err(Web3HealthError::WrongChain {
+                expected: expected_chain_id,
+                actual: chain_id.into(),
+            });
+        }
+
+        let block = self
+            .retry(|web3| web3.eth().block(BlockId::Number(BlockNumber::Latest)))
+            .await
+            .map_err(Web3HealthError::Unreachable)?;
+        let Some(block) = block else {
+            return Err(Web3HealthError::StaleHead {
+                number: None,
+                age: Duration::MAX,
+            });
+        };
+        let timestamp = UNIX_EPOCH + Duration::from_secs(block.timestamp.low_u64());
+        let age = SystemTime::now()
+            .duration_since(timestamp)
+            .unwrap_or_default();
+        if age > self.max_head_age {
+            return Err(Web3HealthError::StaleHead {
+                number: block.number.map(|number| number.as_u64()),
+                age,
+            });
+        }
+        Ok(())
+    }
+
+    /// Estimates the current gas prices. Uses the EIP-1559 fee market if the
+    /// latest block has a base fee and falls back to `eth_gasPrice` otherwise.
+    pub async fn gas_price_estimate(&self) -> Result<GasEstimate, Web3Error> {
+        let block = self
+            .retry(|web3| web3.eth().block(BlockId::Number(BlockNumber::Latest)))
+            .await?;
+        let Some(base_fee) = block.and_then(|block| block.base_fee_per_gas) else {
+            let gas_price = self.retry(|web3| web3.eth().gas_price()).await?;
+            return Ok(GasEstimate::Legacy { gas_price });
+        };
+        let max_priority_fee: U256 = self
+            .retry(|web3| {
+                CallFuture::new(web3.transport().execute("eth_maxPriorityFeePerGas", vec![]))
+            })
+            .await?;
+        Ok(GasEstimate::Eip1559 {
+            base_fee,
+            max_priority_fee,
+            max_fee: base_fee
+                .saturating_mul(2.into())
+                .saturating_add(max_priority_fee),
+        })
+    }
+
+    async fn retry<'a, F, Fut, R>(&'a self, request: F) -> Result<R, Web3Error>
+    where
+        F: FnMut(&'a Web3<T>) -> Fut,
+        Fut: Future<Output = web3::Result<R>>,
+    {
+        let Some(breaker) = &self.circuit_breaker else {
+            return self.retry_inner(request).await;
+        };
+        if !breaker.try_acquire() {
+            return Err(Web3Error::CircuitOpen);
+        }
+        let result = self.retry_inner(request).await;
+        breaker.record(&result);
+        result
+    }
+
+    async fn retry_inner<'a, F, Fut, R>(&'a self, mut request: F) -> Result<R, Web3Error>
+    where
+        F: FnMut(&'a Web3<T>) -> Fut,
+        Fut: Future<Output = web3::Result<R>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match self.with_failover(&mut request).await {
+                Err(err) if attempt < self.retries.max_retries && err.is_transient() => {
+                    let delay = backoff(self.retries.base_delay, attempt);
+                    tracing::debug!(?err, attempt, ?delay, "retrying transient RPC error");
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Sends the request to the active endpoint, moving on to the next one
+    /// for as long as they fail with transient errors.
+    async fn with_failover<'a, F, Fut, R>(&'a self, request: &mut F) -> Result<R, Web3Error>
+    where
+        F: FnMut(&'a Web3<T>) -> Fut,
+        Fut: Future<Output = web3::Result<R>>,
+    {
+        let mut endpoint = self.select_endpoint();
+        for _ in 1..self.endpoints.len() {
+            match self.with_timeout(request(&self.endpoints[endpoint])).await {
+                Err(err) if err.is_transient() => {
+                    tracing::debug!(?err, endpoint, "RPC endpoint failed");
+                    endpoint = self.fail_over(endpoint);
+                }
+                result => return result,
+            }
+        }
+        self.with_timeout(request(&self.endpoints[endpoint])).await
+    }
+
+    /// Returns the endpoint to send the next request to. Moves back to the
+    /// primary endpoint once `primary_retry_interval` passed since the last
+    /// failover.
+    fn select_endpoint(&self) -> usize {
+        let active = self.active.load(Ordering::Relaxed);
+        if active == 0 {
+            return 0;
+        }
+        let mut failed_over_at = self.failed_over_at.lock().unwrap();
+        if failed_over_at.is_some_and(|at| at.elapsed() >= self.primary_retry_interval) {
+            tracing::info!("retrying primary RPC endpoint");
+            *failed_over_at = None;
+            self.active.store(0, Ordering::Relaxed);
+            return 0;
+        }
+        active
+    }
+
+    /// Moves requests from the failed endpoint to the next one and returns
+    /// it.
+    fn fail_over(&self, failed: usize) -> usize {
+        let next = (failed + 1) % self.endpoints.len();
+        // Concurrent requests may have failed over already.
+        if self
+            .active
+            .compare_exchange(failed, next, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            tracing::warn!(failed, next, "failing over to next RPC endpoint");
+            *self.failed_over_at.lock().unwrap() = Some(Instant::now());
+        }
+        next
+    }
+
+    async fn with_timeout<Fut, R>(&self, request: Fut) -> Result<R, Web3Error>
+    where
+        Fut: Future<Output = web3::Result<R>>,
+    {
+        let Some(timeout) = self.request_timeout else {
+            return Ok(request.await?);
+        };
+        match tokio::time::timeout(timeout, request).await {
+            Ok(result) => Ok(result?),
+            Err(_) => Err(Web3Error::Timeout(timeout)),
+        }
+    }
+}
+
+/// A transport chosen at runtime based on the node URL.
+pub type DynTransport = Either<WebSocket, Http>;
+
+/// The kind of transport used for a node URL.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TransportKind {
+    Http,
+    WebSocket,
+}
+
+impl TransportKind {
+    /// Picks `WebSocket` for `ws://` and `wss://` URLs and `Http` otherwise.
+    pub fn for_url(url: &str) -> Self {
+        let scheme = url.split_once("://").map(|(scheme, _)| scheme);
+        match scheme {
+            Some(scheme) if scheme.eq_ignore_ascii_case("ws") => Self::WebSocket,
+            Some(scheme) if scheme.eq_ignore_ascii_case("wss") => Self::WebSocket,
+            _ => Self::Http,
+        }
+    }
+}
+
+impl Web3Provider<DynTransport> {
+    /// Creates a provider for the node at `url`. WebSocket URLs connect
+    /// eagerly, which is why this is async.
+    pub async fn from_url(url: &str) -> web3::Result<Self> {
+        let transport = match TransportKind::for_url(url) {
+            TransportKind::WebSocket => Either::Left(WebSocket::new(url).await?),
+            TransportKind::Http => Either::Right(Http::new(url)?),
+        };
+        Ok(Self::new(Web3::new(transport)))
+    }
+}
+
+/// Exponential backoff with jitter: the delay for the `n`-th retry is picked
+/// uniformly from `[base * 2^n / 2, base * 2^n]`.
+fn backoff(base: Duration, attempt: usize) -> Duration {
+    let exponent = u32::try_from(attempt).unwrap_or(u32::MAX);
+    let delay = base.saturating_mul(2_u32.saturating_pow(exponent));
+    delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+}
+
+/// Returns whether an RPC error is likely caused by a temporary node or
+/// network condition (timeouts, rate limiting, 5xx responses) rather than the
+/// request itself (reverts, invalid parameters).
+pub fn is_transient(err: &web3::Error) -> bool {
+    match err {
+        web3::Error::Unreachable | web3::Error::Io(_) => true,
+        web3::Error::Transport(TransportError::Code(code)) => *code == 429 || *code >= 500,
+        web3::Error::Transport(TransportError::Message(_)) => true,
+        // -32005 is the de facto standard "limit exceeded" code used by node
+        // providers for rate limiting.
+        web3::Error::Rpc(err) => matches!(
+            err.code,
+            ErrorCode::InternalError | ErrorCode::ServerError(-32005)
+        ),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        ethcontract::jsonrpc::Call,
+        ethrpc::mock::MockTransport,
+        futures::{future::BoxFuture, FutureExt},
+        serde_json::{json, Value},
+        std::{
+            collections::VecDeque,
+            sync::{
+                atomic::{AtomicUsize, Ordering},
+                Arc,
+                Mutex,
+            },
+        },
+        web3::RequestId,
+    };
+
+    /// Transport that delays every response by the next of the configured
+    /// delays.
+    #[derive(Clone, Debug)]
+    struct SlowTransport {
+        inner: MockTransport,
+        delays: Arc<Mutex<VecDeque<Duration>>>,
+    }
+
+    impl Transport for SlowTransport {
+        type Out = BoxFuture<'static, web3::Result<Value>>;
+
+        fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+            self.inner.prepare(method, params)
+        }
+
+        fn send(&self, id: RequestId, call: Call) -> Self::Out {
+            let response = self.inner.send(id, call);
+            let delay = self.delays.lock().unwrap().pop_front().unwrap_or_default();
+            async move {
+                tokio::time::sleep(delay).await;
+                response.await
+            }
+            .boxed()
+        }
+    }
+
+    fn slow_transport(delays: impl IntoIterator<Item = Duration>) -> SlowTransport {
+        let inner = MockTransport::new();
+        inner
+            .mock()
+            .expect_execute()
+            .returning(|_, _| Ok(json!("0x01")));
+        SlowTransport {
+            inner,
+            delays: Arc::new(Mutex::new(delays.into_iter().collect())),
+        }
+    }
+
+    fn latest_block(base_fee_per_gas: Option<U256>) -> Value {
+        serde_json::to_value(web3::types::Block::<web3::types::H256> {
+            base_fee_per_gas,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn rpc_error(code: ErrorCode) -> web3::Error {
+        web3::Error::Rpc(ethcontract::jsonrpc::Error {
+            code,
+            message: Default::default(),
+            data: None,
+        })
+    }
+
+    #[test]
+    fn classifies_transient_errors() {
+        assert!(is_transient(&web3::Error::Unreachable));
+        assert!(is_transient(&web3::Error::Transport(TransportError::Code(
+            429
+        ))));
+        assert!(is_transient(&web3::Error::Transport(TransportError::Code(
+            503
+        ))));
+        assert!(is_transient(&rpc_error(ErrorCode::ServerError(-32005))));
+
+        assert!(!is_transient(&web3::Error::Transport(
+            TransportError::Code(400)
+        )));
+        assert!(!is_transient(&rpc_error(ErrorCode::InvalidParams)));
+        // Reverts are reported as server errors with code 3.
+        assert!(!is_transient(&rpc_error(ErrorCode::ServerError(3))));
+        assert!(!is_transient(&web3::Error::Decoder("bad".into())));
+    }
+
+    #[test]
+    fn selects_transport_by_url_scheme() {
+        for (url, kind) in [
+            ("ws://localhost:8546", TransportKind::WebSocket),
+            ("wss://node.example.com", TransportKind::WebSocket),
+            ("WSS://node.example.com", TransportKind::WebSocket),
+            ("http://localhost:8545", TransportKind::Http),
+            ("https://node.example.com", TransportKind::Http),
+            ("localhost:8545", TransportKind::Http),
+        ] {
+            assert_eq!(TransportKind::for_url(url), kind, "{url}");
+        }
+    }
+
+    #[tokio::test]
+    async fn from_url_uses_http_transport() {
+        let provider = Web3Provider::from_url("http://localhost:8545")
+            .await
+            .unwrap();
+        assert!(matches!(provider.web3().transport(), Either::Right(_)));
+    }
+
+    #[tokio::test]
+    async fn retries_transient_failures() {
+        let transport = MockTransport::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+        transport.mock().expect_execute().times(3).returning({
+            let calls = calls.clone();
+            move |method, _| {
+                assert_eq!(method, "eth_call");
+                if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                    Err(web3::Error::Transport(TransportError::Code(503)))
+                } else {
+                    Ok(json!("0x01"))
+                }
+            }
+        });
+
+        let provider =
+            Web3Provider::new(Web3::new(transport)).with_retries(2, Duration::from_millis(1));
+        let result = provider.call(Default::default(), None).await.unwrap();
+        assert_eq!(result, Bytes(vec![1]));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn times_out_slow_requests() {
+        let transport = slow_transport([Duration::from_secs(10)]);
+        let provider =
+            Web3Provider::new(Web3::new(transport)).with_request_timeout(Duration::from_secs(1));
+
+        let result = provider.call(Default::default(), None).await;
+        assert!(
+            matches!(result, Err(Web3Error::Timeout(timeout)) if timeout == Duration::from_secs(1))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_timed_out_requests() {
+        let transport = slow_transport([Duration::from_secs(10), Duration::ZERO]);
+        let provider = Web3Provider::new(Web3::new(transport))
+            .with_request_timeout(Duration::from_secs(1))
+            .with_retries(1, Duration::from_millis(1));
+
+        let result = provider.call(Default::default(), None).await.unwrap();
+        assert_eq!(result, Bytes(vec![1]));
+    }
+
+    #[tokio::test]
+    async fn health_check_detects_wrong_chain() {
+        let transport = MockTransport::new();
+        transport
+            .mock()
+            .expect_execute()
+            .times(1)
+            .returning(|method, _| {
+                assert_eq!(method, "eth_chainId");
+                Ok(json!("0x64"))
+            });
+
+        let provider = Web3Provider::new(Web3::new(transport));
+        let result = provider.health_check(1).await;
+        assert!(matches!(
+            result,
+            Err(Web3HealthError::WrongChain { expected: 1, actual }) if actual == 100.into()
+        ));
+    }
+
+    #[tokio::test]
+    async fn caches_chain_id() {
+        let transport = MockTransport::new();
+        transport
+            .mock()
+            .expect_execute()
+            .times(1)
+            .returning(|method, _| {
+                assert_eq!(method, "eth_chainId");
+                Ok(json!("0x64"))
+            });
+
+        let provider = Arc::new(Web3Provider::new(Web3::new(transport)));
+        for _ in 0..3 {
+            assert_eq!(provider.chain_id().await.unwrap(), 100);
+        }
+        let provider_ = provider.clone();
+        let chain_id = tokio::spawn(async move { provider_.chain_id().await.unwrap() });
+        assert_eq!(chain_id.await.unwrap(), 100);
+    }
+
+    #[tokio::test]
+    async fn gas_price_estimate_falls_back_to_legacy() {
+        let transport = MockTransport::new();
+        transport
+            .mock()
+            .expect_execute()
+            .returning(|method, _| match method {
+                "eth_getBlockByNumber" => Ok(latest_block(None)),
+                "eth_gasPrice" => Ok(json!("0x3b9aca00")),
+                _ => panic!("unexpected call {method}"),
+            });
+
+        let provider = Web3Provider::new(Web3::new(transport));
+        assert_eq!(
+            provider.gas_price_estimate().await.unwrap(),
+            GasEstimate::Legacy {
+                gas_price: 1_000_000_000.into()
+            }
+        );
     }
-} 
\ No newline at end of file
+
+    #[tokio::test]
+    async fn gas_price_estimate_uses_base_fee() {
+        let transport = MockTransport::new();
+        transport
+            .mock()
+            .expect_execute()
+            .returning(|method, _| match method {
+                "eth_getBlockByNumber" => Ok(latest_block(Some(100.into()))),
+                "eth_maxPriorityFeePerGas" => Ok(json!("0x2")),
+                _ => panic!("unexpected call {method}"),
+            });
+
+        let provider = Web3Provider::new(Web3::new(transport));
+        assert_eq!(
+            provider.gas_price_estimate().await.unwrap(),
+            GasEstimate::Eip1559 {
+                base_fee: 100.into(),
+                max_priority_fee: 2.into(),
+                max_fee: 202.into(),
+            }
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn circuit_breaker_fails_fast() {
+        let transport = MockTransport::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+        transport.mock().expect_execute().times(3).returning({
+            let calls = calls.clone();
+            move |_, _| {
+                if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                    Err(web3::Error::Transport(TransportError::Code(503)))
+                } else {
+                    Ok(json!("0x01"))
+                }
+            }
+        });
+
+        let provider = Web3Provider::new(Web3::new(transport))
+            .with_circuit_breaker(2, Duration::from_secs(60));
+        for _ in 0..2 {
+            let result = provider.call(Default::default(), None).await;
+            assert!(matches!(result, Err(Web3Error::Rpc(_))));
+        }
+        assert_eq!(provider.circuit_state(), CircuitState::Open);
+
+        // Calls fail without reaching the transport.
+        for _ in 0..5 {
+            let result = provider.call(Default::default(), None).await;
+            assert!(matches!(result, Err(Web3Error::CircuitOpen)));
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        tokio::time::advance(Duration::from_secs(61)).await;
+        assert_eq!(provider.circuit_state(), CircuitState::HalfOpen);
+        let result = provider.call(Default::default(), None).await.unwrap();
+        assert_eq!(result, Bytes(vec![1]));
+        assert_eq!(provider.circuit_state(), CircuitState::Closed);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fails_over_to_fallback_endpoints() {
+        let endpoint = |fails: bool| {
+            let transport = MockTransport::new();
+            let calls = Arc::new(AtomicUsize::new(0));
+            transport.mock().expect_execute().returning({
+                let calls = calls.clone();
+                move |_, _| {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    if fails {
+                        Err(web3::Error::Transport(TransportError::Code(503)))
+                    } else {
+                        Ok(json!("0x01"))
+                    }
+                }
+            });
+            (Web3::new(transport), calls)
+        };
+        let (primary, primary_calls) = endpoint(true);
+        let (fallback, fallback_calls) = endpoint(false);
+
+        let provider = Web3Provider::new(primary)
+            .with_fallbacks([fallback])
+            .with_primary_retry_interval(Duration::from_secs(60));
+        let result = provider.call(Default::default(), None).await.unwrap();
+        assert_eq!(result, Bytes(vec![1]));
+        assert_eq!(provider.active_endpoint(), 1);
+
+        // Requests go to the fallback directly.
+        provider.call(Default::default(), None).await.unwrap();
+        assert_eq!(primary_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(fallback_calls.load(Ordering::SeqCst), 2);
+
+        // The primary gets tried again after a while.
+        tokio::time::advance(Duration::from_secs(61)).await;
+        provider.call(Default::default(), None).await.unwrap();
+        assert_eq!(primary_calls.load(Ordering::SeqCst), 2);
+        assert_eq!(fallback_calls.load(Ordering::SeqCst), 3);
+        assert_eq!(provider.active_endpoint(), 1);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_deterministic_failures() {
+        let transport = MockTransport::new();
+        transport
+            .mock()
+            .expect_execute()
+            .times(1)
+            .returning(|_, _| Err(rpc_error(ErrorCode::InvalidParams)));
+
+        let provider =
+            Web3Provider::new(Web3::new(transport)).with_retries(2, Duration::from_millis(1));
+        assert!(provider.call(Default::default(), None).await.is_err());
+    }
+}
@@ -0,0 +1,48 @@
+//! Helpers shared by the unit tests of this crate.
+
+use std::sync::{Arc, Mutex};
+
+/// Records the level and message of every event logged while it is installed.
+#[derive(Clone, Default)]
+pub struct Events(Arc<Mutex<Vec<(tracing::Level, String)>>>);
+
+impl Events {
+    /// Installs a recorder as the default subscriber of the current thread
+    /// until the returned guard is dropped.
+    pub fn capture() -> (Self, tracing::subscriber::DefaultGuard) {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let events = Self::default();
+        let guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(events.clone()));
+        (events, guard)
+    }
+
+    /// Returns whether a warning containing `message` was logged.
+    pub fn warned(&self, message: &str) -> bool {
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|(level, logged)| *level == tracing::Level::WARN && logged.contains(message))
+    }
+}
+
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for Events {
+    fn on_event(&self, event: &tracing::Event<'_>, _: tracing_subscriber::layer::Context<'_, S>) {
+        struct Message(String);
+        impl tracing::field::Visit for Message {
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+                if field.name() == "message" {
+                    self.0 = format!("{value:?}");
+                }
+            }
+        }
+        let mut message = Message(String::new());
+        event.record(&mut message);
+        self.0
+            .lock()
+            .unwrap()
+            .push((*event.metadata().level(), message.0));
+    }
+}
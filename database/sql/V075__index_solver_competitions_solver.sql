@@ -0,0 +1,5 @@
+-- Index the address of the solver stored in the competition blobs so that all
+-- competitions of a solver can be loaded without scanning the whole table.
+-- Queries have to filter on the exact same `json ->> 'solver'` expression for
+-- the index to be used.
+CREATE INDEX solver_competitions_solver ON solver_competitions ((json ->> 'solver'));
@@ -0,0 +1,7 @@
+-- Block in which the competition's settlement was mined. Nullable because it
+-- is only known once the settlement has been observed on-chain and gets reset
+-- when that block is reorged out.
+ALTER TABLE solver_competitions
+    ADD COLUMN block_number bigint;
+
+CREATE INDEX solver_competitions_block_number ON solver_competitions USING BTREE (block_number);
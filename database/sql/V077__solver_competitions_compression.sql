@@ -0,0 +1,8 @@
+-- Large competitions can be stored compressed. Such rows have a NULL `json`
+-- and keep the serialized JSON in `compressed_json` instead, compressed with
+-- the algorithm in `compression`.
+CREATE TYPE CompressionKind AS ENUM ('gzip');
+
+ALTER TABLE solver_competitions
+    ADD COLUMN compression CompressionKind,
+    ADD COLUMN compressed_json bytea;
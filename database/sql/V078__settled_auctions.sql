@@ -0,0 +1,7 @@
+-- Auctions whose competition was stored together with the transaction that
+-- settled it. Written in the same transaction as the competition, so that an
+-- auction is never marked as settled without its competition being stored.
+CREATE TABLE settled_auctions (
+    auction_id bigint PRIMARY KEY,
+    tx_hash bytea NOT NULL
+);
@@ -0,0 +1,5 @@
+-- Solver addresses in the competition blobs aren't consistently cased, so
+-- index them lowercased. Queries have to filter on the exact same
+-- `lower(json ->> 'solver')` expression for the index to be used.
+DROP INDEX solver_competitions_solver;
+CREATE INDEX solver_competitions_solver ON solver_competitions (lower(json ->> 'solver'));